@@ -13,9 +13,11 @@ extern crate t_rex_core;
 extern crate t_rex_webserver;
 
 use t_rex_core::core::grid::Extent;
+use t_rex_core::cache::Tilecache;
 use t_rex_webserver as webserver;
 use clap::{App, SubCommand, ArgMatches, AppSettings};
 use std::env;
+use std::process;
 use log::{LogRecord, LogLevelFilter};
 use env_logger::LogBuilder;
 
@@ -46,11 +48,37 @@ fn init_logger() {
     builder.init().unwrap();
 }
 
+fn parse_extent(numlist: &str) -> Extent {
+    let arr: Vec<f64> = numlist
+        .split(",")
+        .map(|v| {
+                 v.parse()
+                     .expect("Error parsing 'extent' as list of float values")
+             })
+        .collect();
+    Extent {
+        minx: arr[0],
+        miny: arr[1],
+        maxx: arr[2],
+        maxy: arr[3],
+    }
+}
+
 fn generate(args: &ArgMatches) {
     let (mut service, config) = webserver::server::service_from_args(args);
-    config
-        .cache
-        .expect("Missing configuration entry base in [cache.file]");
+    match args.value_of("output") {
+        Some(output) => {
+            service.cache = Tilecache::from_output(output).unwrap_or_else(|err| {
+                                                                               println!("{}", err);
+                                                                               process::exit(1)
+                                                                           });
+        }
+        None => {
+            config
+                .cache
+                .expect("Missing configuration entry base in [cache.file]");
+        }
+    }
     let tileset = args.value_of("tileset");
     let minzoom = args.value_of("minzoom")
         .map(|s| {
@@ -62,22 +90,7 @@ fn generate(args: &ArgMatches) {
                  s.parse::<u8>()
                      .expect("Error parsing 'maxzoom' as integer value")
              });
-    let extent = args.value_of("extent")
-        .and_then(|numlist| {
-            let arr: Vec<f64> = numlist
-                .split(",")
-                .map(|v| {
-                         v.parse()
-                             .expect("Error parsing 'extent' as list of float values")
-                     })
-                .collect();
-            Some(Extent {
-                     minx: arr[0],
-                     miny: arr[1],
-                     maxx: arr[2],
-                     maxy: arr[3],
-                 })
-        });
+    let extent = args.value_of("extent").map(parse_extent);
     let nodes = args.value_of("nodes")
         .map(|s| {
                  s.parse::<u8>()
@@ -88,6 +101,11 @@ fn generate(args: &ArgMatches) {
                  s.parse::<u8>()
                      .expect("Error parsing 'nodeno' as integer value")
              });
+    let threads = args.value_of("threads")
+        .map(|s| {
+                 s.parse::<u8>()
+                     .expect("Error parsing 'threads' as integer value")
+             });
     let progress = args.value_of("progress")
         .map_or(true, |s| {
             s.parse::<bool>()
@@ -98,6 +116,7 @@ fn generate(args: &ArgMatches) {
             s.parse::<bool>()
                 .expect("Error parsing 'overwrite' as boolean value")
         });
+    let dry_run = args.is_present("dry-run");
     service.prepare_feature_queries();
     service.generate(tileset,
                      minzoom,
@@ -105,8 +124,33 @@ fn generate(args: &ArgMatches) {
                      extent,
                      nodes,
                      nodeno,
+                     threads,
                      progress,
-                     overwrite);
+                     overwrite,
+                     dry_run);
+}
+
+fn clean(args: &ArgMatches) {
+    let (service, config) = webserver::server::service_from_args(args);
+    config
+        .cache
+        .expect("Missing configuration entry [cache]");
+    let tileset = args.value_of("tileset");
+    let minzoom = args.value_of("minzoom")
+        .map(|s| {
+                 s.parse::<u8>()
+                     .expect("Error parsing 'minzoom' as integer value")
+             });
+    let maxzoom = args.value_of("maxzoom")
+        .map(|s| {
+                 s.parse::<u8>()
+                     .expect("Error parsing 'maxzoom' as integer value")
+             });
+    let extent = args.value_of("extent").map(parse_extent);
+    if let Err(e) = service.purge(tileset, minzoom, maxzoom, extent) {
+        println!("Error removing tiles: {}", e);
+        process::exit(1)
+    }
 }
 
 fn main() {
@@ -123,6 +167,8 @@ fn main() {
                                               --clip=[true|false] 'Clip geometries'
                                               --cache=[DIR] 'Use tile cache in DIR'
                                               -c, --config=[FILE] 'Load from custom config file'
+                                              --bind=[ADDRESS] 'Bind address HOST[:PORT], overrides webserver config'
+                                              --threads=[NUM] 'Number of worker threads, overrides webserver config'
                                               --openbrowser=[true|false] 'Open backend URL in browser'")
                         .about("Start web server and serve MVT vector tiles"))
         .subcommand(SubCommand::with_name("genconfig")
@@ -137,11 +183,22 @@ fn main() {
                                               --minzoom=[LEVEL] 'Minimum zoom level'
                                               --maxzoom=[LEVEL] 'Maximum zoom level'
                                               --extent=[minx,miny,maxx,maxy] 'Extent of tiles'
+                                              --output=[DIR|FILE] 'Write tiles to directory or MBTiles file instead of configured cache'
                                               --nodes=[NUM] 'Number of generator nodes'
                                               --nodeno=[NUM] 'Number of this nodes (0 <= n < nodes)'
+                                              --threads=[NUM] 'Number of worker threads (default: DB pool size)'
+                                              --dry-run 'Count tiles without generating them'
                                               --progress=[true|false] 'Show progress bar')
                                               --overwrite=[false|true] 'Overwrite previously cached tiles'")
-                        .about("Generate tiles for cache"));
+                        .about("Generate tiles for cache"))
+        .subcommand(SubCommand::with_name("clean")
+                        .setting(AppSettings::AllowLeadingHyphen)
+                        .args_from_usage("-c, --config=<FILE> 'Load from custom config file'
+                                              --tileset=[NAME] 'Tileset name'
+                                              --minzoom=[LEVEL] 'Minimum zoom level'
+                                              --maxzoom=[LEVEL] 'Maximum zoom level'
+                                              --extent=[minx,miny,maxx,maxy] 'Extent of tiles'")
+                        .about("Remove tiles from cache"));
 
     match app.get_matches_from_safe_borrow(env::args()) { //app.get_matches() prohibits later call of app.print_help()
         Result::Err(e) => {
@@ -152,6 +209,7 @@ fn main() {
                 ("serve", Some(sub_m)) => webserver::server::webserver(sub_m),
                 ("genconfig", Some(sub_m)) => println!("{}", webserver::server::gen_config(sub_m)),
                 ("generate", Some(sub_m)) => generate(sub_m),
+                ("clean", Some(sub_m)) => clean(sub_m),
                 _ => {
                     let _ = app.print_help();
                     println!("");
@@ -0,0 +1,55 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+#[macro_use]
+extern crate criterion;
+extern crate t_rex_core;
+
+use criterion::Criterion;
+use t_rex_core::core::feature::FeatureStruct;
+use t_rex_core::core::geom::{self, GeometryType};
+use t_rex_core::core::grid::Extent;
+use t_rex_core::core::layer::Layer;
+use t_rex_core::mvt::tile::Tile;
+
+
+/// Dense point layer with 10'000 features
+fn point_features() -> Vec<FeatureStruct> {
+    (0..10000)
+        .map(|i| {
+                 FeatureStruct {
+                     fid: Some(i),
+                     attributes: Vec::new(),
+                     geometry: GeometryType::Point(geom::Point::new((i % 100) as f64 * 2.56,
+                                                                    (i / 100) as f64 * 2.56,
+                                                                    Some(3857))),
+                 }
+             })
+        .collect()
+}
+
+fn encode_points(c: &mut Criterion) {
+    let extent = Extent {
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 256.0,
+        maxy: 256.0,
+    };
+    let layer = Layer::new("points");
+    let features = point_features();
+    c.bench_function("encode 10k points", move |b| {
+        b.iter(|| {
+            let mut tile = Tile::new(&extent, 4096, true);
+            let mut mvt_layer = tile.new_layer(&layer);
+            for feature in &features {
+                tile.add_feature(&mut mvt_layer, feature);
+            }
+            tile.add_layer(mvt_layer);
+        })
+    });
+}
+
+criterion_group!(benches, encode_points);
+criterion_main!(benches);
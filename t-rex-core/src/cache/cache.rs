@@ -3,8 +3,10 @@
 // Licensed under the MIT License. See LICENSE file in the project root for full license information.
 //
 
+use core::grid::{Extent, Grid};
 use std::io::Read;
 use std::io;
+use std::time::SystemTime;
 
 
 pub trait Cache {
@@ -14,6 +16,36 @@ pub trait Cache {
     fn read<F>(&self, path: &str, read: F) -> bool where F: FnMut(&mut Read);
     fn write(&self, path: &str, obj: &[u8]) -> Result<(), io::Error>;
     fn exists(&self, path: &str) -> bool;
+    fn remove(&self, path: &str) -> Result<(), io::Error>;
+    /// Modification time of cache entry, if supported by the cache
+    fn modified(&self, _path: &str) -> Option<SystemTime> {
+        None
+    }
+    /// Size of cache entry in bytes, if the entry is a file
+    fn file_size(&self, _path: &str) -> Option<u64> {
+        None
+    }
+    /// Remove tiles of tileset overlapping extent (in grid SRS) in zoom level range
+    fn purge(&self,
+             tileset: &str,
+             grid: &Grid,
+             extent: &Extent,
+             minzoom: u8,
+             maxzoom: u8)
+             -> Result<(), io::Error> {
+        for zoom in minzoom..maxzoom + 1 {
+            if zoom > grid.maxzoom() {
+                break;
+            }
+            let (minx, miny, maxx, maxy) = grid.extent_to_tile_range(extent, zoom);
+            for xtile in minx..maxx + 1 {
+                for ytile in miny..maxy + 1 {
+                    self.remove(&format!("{}/{}/{}/{}.pbf", tileset, zoom, xtile, ytile))?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 
@@ -40,4 +72,17 @@ impl Cache for Nocache {
     fn exists(&self, _path: &str) -> bool {
         false
     }
+    fn remove(&self, _path: &str) -> Result<(), io::Error> {
+        Ok(())
+    }
+    #[allow(unused_variables)]
+    fn purge(&self,
+             tileset: &str,
+             grid: &Grid,
+             extent: &Extent,
+             minzoom: u8,
+             maxzoom: u8)
+             -> Result<(), io::Error> {
+        Ok(())
+    }
 }
@@ -4,19 +4,231 @@
 //
 
 use cache::cache::Cache;
+use cache::content_hash;
+use core::grid::{Extent, Grid};
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::{Duration, SystemTime};
+
+
+/// Counter for unique temporary file names
+static TMPFILE_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Directory of deduplicated tile content, relative to cache base path
+const BLOB_DIR: &'static str = ".blobs";
 
 
 pub struct Filecache {
     pub basepath: String,
     pub baseurl: Option<String>,
+    /// Expiration time of cache entries (None: never expire)
+    pub max_age: Option<Duration>,
+    /// Delete expired entries on access
+    pub delete_expired: bool,
+    /// Number of hash bucket directories inserted above tile files (0: z/x/y.pbf)
+    pub shard_levels: u8,
+    /// Store identical tiles once, with tile files as hard links to the content
+    pub dedup: bool,
+}
+
+/// Number of hard links of a file
+#[cfg(unix)]
+fn link_count(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).map(|meta| meta.nlink()).ok()
+}
+
+/// Link counts are not available on other platforms, blobs are kept
+#[cfg(not(unix))]
+fn link_count(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// FNV-1a hash, stable across platforms and releases
+fn fnv1a(s: &str) -> u32 {
+    s.bytes().fold(0x811c9dc5, |hash, b| (hash ^ b as u32).wrapping_mul(0x01000193))
+}
+
+impl Filecache {
+    /// Full file path of cache entry. Tiles are distributed into `shard_levels`
+    /// bucket directories derived from a hash of the tile file name.
+    pub fn tile_path(&self, path: &str) -> String {
+        if self.shard_levels == 0 || !path.ends_with(".pbf") {
+            return format!("{}/{}", self.basepath, path);
+        }
+        let (dir, file) = match path.rfind('/') {
+            Some(pos) => (&path[..pos + 1], &path[pos + 1..]),
+            None => ("", path),
+        };
+        let hash = fnv1a(path);
+        let buckets: Vec<String> = (0..self.shard_levels.min(4))
+            .map(|level| format!("{:02x}", (hash >> (level * 8)) & 0xff))
+            .collect();
+        format!("{}/{}{}/{}", self.basepath, dir, buckets.join("/"), file)
+    }
+    /// Full file path of deduplicated tile content
+    pub fn blob_path(&self, hash: &str) -> String {
+        format!("{}/{}/{}/{}.pbf", self.basepath, BLOB_DIR, &hash[..2], hash)
+    }
+    /// Check whether file is older than `max_age`
+    fn outdated(&self, fullpath: &str) -> bool {
+        let max_age = match self.max_age {
+            Some(max_age) => max_age,
+            None => return false,
+        };
+        let age = fs::metadata(fullpath)
+            .and_then(|meta| meta.modified())
+            .map(|mtime| {
+                     SystemTime::now()
+                         .duration_since(mtime)
+                         .unwrap_or(Duration::from_secs(0))
+                 });
+        match age {
+            Ok(age) => age > max_age,
+            Err(_) => false,
+        }
+    }
+    /// Check whether cache entry is older than `max_age`
+    fn expired(&self, fullpath: &str) -> bool {
+        if !self.outdated(fullpath) {
+            return false;
+        }
+        debug!("Filecache entry {} expired", fullpath);
+        if self.delete_expired {
+            let _ = fs::remove_file(fullpath);
+        }
+        true
+    }
+    fn tmp_path(fullpath: &str) -> String {
+        format!("{}.{}-{}.tmp",
+                fullpath,
+                process::id(),
+                TMPFILE_COUNTER.fetch_add(1, Ordering::SeqCst))
+    }
+    fn write_file(fullpath: &str, obj: &[u8]) -> Result<(), io::Error> {
+        let p = Path::new(fullpath);
+        try!(fs::create_dir_all(p.parent().unwrap()));
+        // Write into temporary file and rename it, to prevent readers from seeing partial content
+        let tmppath = Filecache::tmp_path(fullpath);
+        let written = File::create(&tmppath).and_then(|mut f| f.write_all(obj));
+        if let Err(e) = written.and_then(|_| fs::rename(&tmppath, fullpath)) {
+            let _ = fs::remove_file(&tmppath);
+            return Err(e);
+        }
+        Ok(())
+    }
+    /// Store tile content once and link tile file to it
+    fn write_dedup(&self, fullpath: &str, obj: &[u8]) -> Result<(), io::Error> {
+        let blobpath = self.blob_path(&content_hash(obj));
+        let mut stored = Vec::new();
+        let found = File::open(&blobpath)
+            .and_then(|mut f| f.read_to_end(&mut stored))
+            .is_ok();
+        if found && stored != obj {
+            // Hash collision
+            warn!("Filecache content hash collision for {}", fullpath);
+            return Filecache::write_file(fullpath, obj);
+        }
+        // Renew outdated content, so that new links don't inherit its modification time
+        if !found || self.outdated(&blobpath) {
+            try!(Filecache::write_file(&blobpath, obj));
+        }
+        try!(fs::create_dir_all(Path::new(fullpath).parent().unwrap()));
+        let tmppath = Filecache::tmp_path(fullpath);
+        match fs::hard_link(&blobpath, &tmppath).and_then(|_| fs::rename(&tmppath, fullpath)) {
+            Ok(_) => {
+                // rename is a no-op if the tile file is already linked to the same content
+                let _ = fs::remove_file(&tmppath);
+                Ok(())
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&tmppath);
+                debug!("Filecache hard link to {} failed: {}", blobpath, e);
+                Filecache::write_file(fullpath, obj)
+            }
+        }
+    }
+    /// Remove deduplicated content which is not linked by any tile file.
+    /// A concurrent write linking the removed blob falls back to a plain tile file.
+    fn release_blob(blobpath: &Path) -> Result<(), io::Error> {
+        if link_count(blobpath) != Some(1) {
+            return Ok(());
+        }
+        debug!("Filecache.remove {}", blobpath.display());
+        match fs::remove_file(blobpath) {
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        // Prune empty bucket directory
+        if let Some(bucket) = blobpath.parent() {
+            let _ = fs::remove_dir(bucket);
+        }
+        Ok(())
+    }
+    /// Remove all deduplicated content without tile files linking to it
+    fn sweep_blobs(&self) -> Result<(), io::Error> {
+        let blobdir = format!("{}/{}", self.basepath, BLOB_DIR);
+        let buckets = match fs::read_dir(&blobdir) {
+            Ok(buckets) => buckets,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for bucket in buckets {
+            let bucket = bucket?.path();
+            if !bucket.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&bucket)? {
+                let path = entry?.path();
+                // Skip temporary files of writes in progress
+                if path.extension().map_or(false, |ext| ext == "pbf") {
+                    Filecache::release_blob(&path)?;
+                }
+            }
+        }
+        let _ = fs::remove_dir(&blobdir);
+        Ok(())
+    }
+    /// Remove tiles with a row in `miny..maxy` from a tile column directory,
+    /// including shard bucket directories below it
+    fn purge_column(dir: &Path, miny: u32, maxy: u32) -> Result<(), io::Error> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Filecache::purge_column(&path, miny, maxy)?;
+                continue;
+            }
+            if path.extension().map_or(true, |ext| ext != "pbf") {
+                continue;
+            }
+            let ytile = path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u32>().ok());
+            match ytile {
+                Some(ytile) if ytile >= miny && ytile <= maxy => fs::remove_file(&path)?,
+                _ => {}
+            }
+        }
+        // Prune empty directory
+        let _ = fs::remove_dir(dir);
+        Ok(())
+    }
 }
 
 impl Cache for Filecache {
     fn info(&self) -> String {
-        format!("Tile cache directory: {}", self.basepath)
+        let mut info = format!("Tile cache directory: {}", self.basepath);
+        if self.shard_levels > 0 {
+            info.push_str(&format!(" ({} shard levels)", self.shard_levels));
+        }
+        if self.dedup {
+            info.push_str(" (deduplicated)");
+        }
+        info
     }
     fn baseurl(&self) -> String {
         self.baseurl
@@ -26,8 +238,11 @@ impl Cache for Filecache {
     fn read<F>(&self, path: &str, mut read: F) -> bool
         where F: FnMut(&mut Read)
     {
-        let fullpath = format!("{}/{}", self.basepath, path);
+        let fullpath = self.tile_path(path);
         debug!("Filecache.read {}", fullpath);
+        if self.expired(&fullpath) {
+            return false;
+        }
         match File::open(&fullpath) {
             Ok(mut f) => {
                 read(&mut f);
@@ -37,16 +252,110 @@ impl Cache for Filecache {
         }
     }
     fn write(&self, path: &str, obj: &[u8]) -> Result<(), io::Error> {
-        let fullpath = format!("{}/{}", self.basepath, path);
+        let fullpath = self.tile_path(path);
         debug!("Filecache.write {}", fullpath);
-        let p = Path::new(&fullpath);
-        try!(fs::create_dir_all(p.parent().unwrap()));
-        let mut f = try!(File::create(&fullpath));
-        f.write_all(obj)
+        if self.dedup && path.ends_with(".pbf") {
+            self.write_dedup(&fullpath, obj)
+        } else {
+            Filecache::write_file(&fullpath, obj)
+        }
     }
 
     fn exists(&self, path: &str) -> bool {
-        let fullpath = format!("{}/{}", self.basepath, path);
-        Path::new(&fullpath).exists()
+        let fullpath = self.tile_path(path);
+        Path::new(&fullpath).exists() && !self.expired(&fullpath)
+    }
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        fs::metadata(self.tile_path(path))
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+    fn file_size(&self, path: &str) -> Option<u64> {
+        fs::metadata(self.tile_path(path)).map(|meta| meta.len()).ok()
+    }
+    fn remove(&self, path: &str) -> Result<(), io::Error> {
+        let fullpath = self.tile_path(path);
+        // Content blob of deduplicated tile
+        let blobpath = if self.dedup && path.ends_with(".pbf") {
+            let mut obj = Vec::new();
+            File::open(&fullpath)
+                .and_then(|mut f| f.read_to_end(&mut obj))
+                .ok()
+                .map(|_| self.blob_path(&content_hash(&obj)))
+        } else {
+            None
+        };
+        match fs::remove_file(&fullpath) {
+            Ok(_) => debug!("Filecache.remove {}", fullpath),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        if let Some(blobpath) = blobpath {
+            Filecache::release_blob(Path::new(&blobpath))?;
+        }
+        // Prune empty directories
+        let basepath = Path::new(&self.basepath);
+        let mut dir = Path::new(&fullpath).parent();
+        while let Some(d) = dir {
+            if d == basepath || fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+        Ok(())
+    }
+    /// Remove directories of existing zoom levels and tile columns instead of
+    /// looking up every tile of the zoom level range
+    fn purge(&self,
+             tileset: &str,
+             grid: &Grid,
+             extent: &Extent,
+             minzoom: u8,
+             maxzoom: u8)
+             -> Result<(), io::Error> {
+        let tilesetdir = format!("{}/{}", self.basepath, tileset);
+        for zoom in minzoom..maxzoom + 1 {
+            if zoom > grid.maxzoom() {
+                break;
+            }
+            let zoomdir = format!("{}/{}", tilesetdir, zoom);
+            let entries = match fs::read_dir(&zoomdir) {
+                Ok(entries) => entries,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let (level_maxx, level_maxy) = grid.level_limit(zoom);
+            let (minx, miny, maxx, maxy) = grid.extent_to_tile_range(extent, zoom);
+            let all_rows = miny == 0 && maxy + 1 >= level_maxy;
+            if all_rows && minx == 0 && maxx + 1 >= level_maxx {
+                debug!("Filecache.purge {}", zoomdir);
+                fs::remove_dir_all(&zoomdir)?;
+                continue;
+            }
+            for entry in entries {
+                let entry = entry?;
+                let xtile = entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| name.parse::<u32>().ok());
+                match xtile {
+                    Some(xtile) if xtile >= minx && xtile <= maxx => {}
+                    _ => continue,
+                }
+                if all_rows {
+                    debug!("Filecache.purge {}", entry.path().display());
+                    fs::remove_dir_all(entry.path())?;
+                } else {
+                    Filecache::purge_column(&entry.path(), miny, maxy)?;
+                }
+            }
+            let _ = fs::remove_dir(&zoomdir);
+        }
+        // Prune empty tileset directory
+        let _ = fs::remove_dir(&tilesetdir);
+        if self.dedup {
+            self.sweep_blobs()?;
+        }
+        Ok(())
     }
 }
@@ -21,6 +21,10 @@ fn test_dircache() {
     let cache = Filecache {
         basepath: basepath,
         baseurl: Some("http://localhost:6767".to_string()),
+        max_age: None,
+        delete_expired: false,
+        shard_levels: 0,
+        dedup: false,
     };
     let path = "tileset/0/1/2.pbf";
     let fullpath = format!("{}/{}", cache.basepath, path);
@@ -28,10 +32,12 @@ fn test_dircache() {
 
     // Cache miss
     assert_eq!(cache.read(path, |_| {}), false);
+    assert_eq!(cache.file_size(path), None);
 
     // Write into cache
     let _ = cache.write(path, obj.as_bytes());
     assert!(Path::new(&fullpath).exists());
+    assert_eq!(cache.file_size(path), Some(10));
 
     // Cache hit
     assert_eq!(cache.read(path, |_| {}), true);
@@ -41,3 +47,281 @@ fn test_dircache() {
     cache.read(path, |f| { let _ = f.read_to_string(&mut s); });
     assert_eq!(&s, "0123456789");
 }
+
+#[test]
+fn test_dircache_expiration() {
+    use std::env;
+    use std::thread;
+    use std::time::Duration;
+
+    let mut dir = env::temp_dir();
+    dir.push("t_rex_test_expiration");
+    let basepath = format!("{}", &dir.display());
+    let _ = fs::remove_dir_all(&basepath);
+
+    let mut cache = Filecache {
+        basepath: basepath,
+        baseurl: None,
+        max_age: Some(Duration::from_secs(3600)),
+        delete_expired: false,
+        shard_levels: 0,
+        dedup: false,
+    };
+    let path = "tileset/0/1/2.pbf";
+    let fullpath = format!("{}/{}", cache.basepath, path);
+    let _ = cache.write(path, "0123456789".as_bytes());
+
+    // Not expired yet
+    assert!(cache.exists(path));
+    assert_eq!(cache.read(path, |_| {}), true);
+
+    thread::sleep(Duration::from_millis(20));
+    cache.max_age = Some(Duration::from_millis(10));
+    assert!(!cache.exists(path));
+    assert_eq!(cache.read(path, |_| {}), false);
+    assert!(Path::new(&fullpath).exists());
+
+    // Lazy deletion
+    cache.delete_expired = true;
+    assert_eq!(cache.read(path, |_| {}), false);
+    assert!(!Path::new(&fullpath).exists());
+
+    // Never expire
+    let _ = cache.write(path, "0123456789".as_bytes());
+    thread::sleep(Duration::from_millis(20));
+    cache.max_age = None;
+    assert_eq!(cache.read(path, |_| {}), true);
+}
+
+#[test]
+fn test_dircache_concurrent_writes() {
+    use std::env;
+    use std::sync::Arc;
+    use std::thread;
+
+    let mut dir = env::temp_dir();
+    dir.push("t_rex_test_concurrent");
+    let basepath = format!("{}", &dir.display());
+    let _ = fs::remove_dir_all(&basepath);
+
+    let cache = Arc::new(Filecache {
+                             basepath: basepath.clone(),
+                             baseurl: None,
+                             max_age: None,
+                             delete_expired: false,
+                             shard_levels: 0,
+                             dedup: false,
+                         });
+    let path = "tileset/0/1/2.pbf";
+    let obj: Vec<u8> = (0..100000).map(|i| (i % 251) as u8).collect();
+
+    let threads: Vec<_> = (0..16)
+        .map(|_| {
+            let cache = cache.clone();
+            let obj = obj.clone();
+            thread::spawn(move || for _ in 0..20 {
+                              cache.write(path, &obj).unwrap();
+                              let mut data = Vec::new();
+                              if cache.read(path, |f| { let _ = f.read_to_end(&mut data); }) {
+                                  assert_eq!(data.len(), obj.len());
+                                  assert!(data == obj);
+                              }
+                          })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // No temporary files left
+    let entries = fs::read_dir(format!("{}/tileset/0/1", basepath)).unwrap().count();
+    assert_eq!(entries, 1);
+}
+
+#[test]
+fn test_dircache_purge() {
+    use core::grid::Grid;
+    use std::env;
+
+    let mut dir = env::temp_dir();
+    dir.push("t_rex_test_purge");
+    let basepath = format!("{}", &dir.display());
+    let _ = fs::remove_dir_all(&basepath);
+
+    let cache = Filecache {
+        basepath: basepath.clone(),
+        baseurl: None,
+        max_age: None,
+        delete_expired: false,
+        shard_levels: 0,
+        dedup: false,
+    };
+    let tiles = ["tileset/0/0/0.pbf",
+                 "tileset/1/0/0.pbf",
+                 "tileset/1/1/0.pbf",
+                 "tileset/2/0/0.pbf",
+                 "tileset/2/3/3.pbf",
+                 "other/1/0/0.pbf"];
+    for path in tiles.iter() {
+        let _ = cache.write(path, "0123456789".as_bytes());
+    }
+    let _ = cache.write("tileset.json", "{}".as_bytes());
+
+    // Purge upper left quarter of zoom levels 1 and 2
+    let grid = Grid::web_mercator();
+    let extent = grid.tile_extent_xyz(0, 0, 1);
+    assert!(cache.purge("tileset", &grid, &extent, 1, 2).is_ok());
+
+    assert!(cache.exists("tileset/0/0/0.pbf"));
+    assert!(!cache.exists("tileset/1/0/0.pbf"));
+    assert!(cache.exists("tileset/1/1/0.pbf"));
+    assert!(!cache.exists("tileset/2/0/0.pbf"));
+    assert!(cache.exists("tileset/2/3/3.pbf"));
+    assert!(cache.exists("other/1/0/0.pbf"));
+    assert!(cache.exists("tileset.json"));
+
+    // Empty directories are removed
+    assert!(!Path::new(&format!("{}/tileset/1/0", basepath)).exists());
+    assert!(Path::new(&format!("{}/tileset/1/1", basepath)).exists());
+    assert!(!Path::new(&format!("{}/tileset/2/0", basepath)).exists());
+    assert!(Path::new(&format!("{}/tileset/2", basepath)).exists());
+
+    // Purge everything
+    let extent = grid.tile_extent(0, 0, 0);
+    assert!(cache.purge("tileset", &grid, &extent, 0, 2).is_ok());
+    assert!(!Path::new(&format!("{}/tileset", basepath)).exists());
+    assert!(cache.exists("tileset.json"));
+}
+
+#[test]
+fn test_dircache_sharding() {
+    use core::grid::Grid;
+    use std::env;
+
+    let mut dir = env::temp_dir();
+    dir.push("t_rex_test_sharding");
+    let basepath = format!("{}", &dir.display());
+    let _ = fs::remove_dir_all(&basepath);
+
+    let mut cache = Filecache {
+        basepath: basepath.clone(),
+        baseurl: None,
+        max_age: None,
+        delete_expired: false,
+        shard_levels: 0,
+        dedup: false,
+    };
+    assert_eq!(cache.tile_path("tileset/0/1/2.pbf"),
+               format!("{}/tileset/0/1/2.pbf", basepath));
+
+    cache.shard_levels = 2;
+    let path = "tileset/2/1/3.pbf";
+    let fullpath = cache.tile_path(path);
+    // Same key maps to the same path
+    assert_eq!(cache.tile_path(path), fullpath);
+    assert!(fullpath.starts_with(&format!("{}/tileset/2/1/", basepath)));
+    assert!(fullpath.ends_with("/3.pbf"));
+    assert_eq!(fullpath.split('/').count(),
+               format!("{}/{}", basepath, path).split('/').count() + 2);
+    // Metadata files are not sharded
+    assert_eq!(cache.tile_path("tileset.json"),
+               format!("{}/tileset.json", basepath));
+
+    // Write, lookup and purge agree on the location
+    let _ = cache.write(path, "0123456789".as_bytes());
+    assert!(Path::new(&fullpath).exists());
+    assert!(cache.exists(path));
+    let mut s = String::new();
+    assert!(cache.read(path, |f| { let _ = f.read_to_string(&mut s); }));
+    assert_eq!(&s, "0123456789");
+
+    // Partial purge of a sharded tile column
+    let grid = Grid::web_mercator();
+    let _ = cache.write("tileset/2/1/0.pbf", "0123456789".as_bytes());
+    let extent = grid.tile_extent_xyz(0, 0, 1);
+    assert!(cache.purge("tileset", &grid, &extent, 2, 2).is_ok());
+    assert!(!cache.exists("tileset/2/1/0.pbf"));
+    assert!(cache.exists(path));
+
+    let extent = grid.tile_extent(0, 0, 0);
+    assert!(cache.purge("tileset", &grid, &extent, 0, 2).is_ok());
+    assert!(!cache.exists(path));
+    assert!(!Path::new(&format!("{}/tileset", basepath)).exists());
+}
+
+#[test]
+fn test_dircache_dedup() {
+    use std::env;
+
+    let mut dir = env::temp_dir();
+    dir.push("t_rex_test_dedup");
+    let basepath = format!("{}", &dir.display());
+    let _ = fs::remove_dir_all(&basepath);
+
+    let cache = Filecache {
+        basepath: basepath.clone(),
+        baseurl: None,
+        max_age: None,
+        delete_expired: false,
+        shard_levels: 0,
+        dedup: true,
+    };
+    let empty = "empty ocean".as_bytes();
+    assert!(cache.write("tileset/2/0/0.pbf", empty).is_ok());
+    assert!(cache.write("tileset/2/0/1.pbf", empty).is_ok());
+    assert!(cache.write("tileset/2/1/1.pbf", "land".as_bytes()).is_ok());
+    // Overwriting with identical content keeps a single link
+    assert!(cache.write("tileset/2/0/1.pbf", empty).is_ok());
+
+    let blobs: Vec<_> = fs::read_dir(format!("{}/.blobs", basepath))
+        .unwrap()
+        .flat_map(|bucket| fs::read_dir(bucket.unwrap().path()).unwrap())
+        .collect();
+    assert_eq!(blobs.len(), 2);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        use cache::content_hash;
+
+        let ino = |path: &str| fs::metadata(format!("{}/{}", basepath, path)).unwrap().ino();
+        assert_eq!(ino("tileset/2/0/0.pbf"), ino("tileset/2/0/1.pbf"));
+        assert!(ino("tileset/2/0/0.pbf") != ino("tileset/2/1/1.pbf"));
+        let blob = fs::metadata(cache.blob_path(&content_hash(empty))).unwrap();
+        assert_eq!(blob.ino(), ino("tileset/2/0/0.pbf"));
+        assert_eq!(blob.nlink(), 3);
+    }
+
+    let mut s = String::new();
+    assert!(cache.read("tileset/2/0/1.pbf", |f| { let _ = f.read_to_string(&mut s); }));
+    assert_eq!(&s, "empty ocean");
+
+    // Metadata files are not deduplicated
+    assert!(cache.write("tileset.json", empty).is_ok());
+    assert_eq!(fs::metadata(format!("{}/tileset.json", basepath)).unwrap().len(),
+               empty.len() as u64);
+
+    // Removing a tile keeps the content of other tiles
+    assert!(cache.remove("tileset/2/0/0.pbf").is_ok());
+    assert!(cache.exists("tileset/2/0/1.pbf"));
+
+    #[cfg(unix)]
+    {
+        use cache::content_hash;
+        use core::grid::Grid;
+
+        let blob_exists = |obj: &[u8]| Path::new(&cache.blob_path(&content_hash(obj))).exists();
+        assert!(blob_exists(empty));
+        // Content of the last link is reclaimed
+        assert!(cache.remove("tileset/2/1/1.pbf").is_ok());
+        assert!(!blob_exists("land".as_bytes()));
+        assert!(blob_exists(empty));
+
+        let grid = Grid::web_mercator();
+        let extent = grid.tile_extent(0, 0, 0);
+        assert!(cache.purge("tileset", &grid, &extent, 0, 2).is_ok());
+        assert!(!cache.exists("tileset/2/0/1.pbf"));
+        assert!(!blob_exists(empty));
+        assert!(!Path::new(&format!("{}/.blobs", basepath)).exists());
+    }
+}
@@ -0,0 +1,207 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use cache::cache::Cache;
+use core::grid::{Extent, Grid};
+use mvt::tile::Tile;
+use rusqlite::{self, Connection};
+use serde_json;
+use std::io::{self, Read};
+use std::sync::Mutex;
+
+
+/// Tile cache stored in an MBTiles (https://github.com/mapbox/mbtiles-spec) SQLite file
+pub struct MBTilesCache {
+    pub path: String,
+    conn: Mutex<Connection>,
+}
+
+/// Cache entry
+enum Entry {
+    /// Tile in XYZ adressing scheme
+    Tile { zoom: u8, x: u32, y: u32 },
+    Metadata,
+}
+
+impl MBTilesCache {
+    /// Open or create MBTiles file
+    pub fn open(path: &str) -> Result<MBTilesCache, String> {
+        let conn = Connection::open(path)
+            .map_err(|e| format!("Error opening MBTiles file '{}': {}", path, e))?;
+        conn.execute_batch("
+            CREATE TABLE IF NOT EXISTS metadata (name text, value text);
+            CREATE UNIQUE INDEX IF NOT EXISTS name ON metadata (name);
+            CREATE TABLE IF NOT EXISTS tiles (zoom_level integer, tile_column integer,
+                                              tile_row integer, tile_data blob);
+            CREATE UNIQUE INDEX IF NOT EXISTS tile_index
+                ON tiles (zoom_level, tile_column, tile_row);")
+            .map_err(|e| format!("Error creating MBTiles tables in '{}': {}", path, e))?;
+        Ok(MBTilesCache {
+               path: path.to_string(),
+               conn: Mutex::new(conn),
+           })
+    }
+    /// Parse cache path `tileset/z/x/y.pbf` or `tileset/metadata.json`
+    fn entry(path: &str) -> Option<Entry> {
+        let parts: Vec<&str> = path.split('/').collect();
+        match parts.len() {
+            2 if parts[1] == "metadata.json" => Some(Entry::Metadata),
+            4 if parts[3].ends_with(".pbf") => {
+                let zoom = parts[1].parse().ok();
+                let x = parts[2].parse().ok();
+                let y = parts[3].trim_right_matches(".pbf").parse().ok();
+                match (zoom, x, y) {
+                    (Some(zoom), Some(x), Some(y)) => Some(Entry::Tile { zoom, x, y }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+    /// MBTiles uses the TMS adressing scheme
+    fn tile_row(zoom: u8, y: u32) -> i64 {
+        (1i64 << zoom) - 1 - y as i64
+    }
+    /// Compressed tile without content
+    fn is_empty_tile(data: &[u8]) -> bool {
+        data.len() < 64 && Tile::gunzip(data).map(|tile| tile.is_empty()).unwrap_or(false)
+    }
+    fn read_tile(&self, zoom: u8, x: u32, y: u32) -> Option<Vec<u8>> {
+        let conn = self.conn.lock().unwrap();
+        let data: Option<Vec<u8>> =
+            conn.query_row("SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                           &[&(zoom as i64), &(x as i64), &Self::tile_row(zoom, y)],
+                           |row| row.get(0))
+                .ok();
+        // Empty tiles are returned as empty object like in other caches
+        data.map(|data| if Self::is_empty_tile(&data) {
+                     Vec::new()
+                 } else {
+                     data
+                 })
+    }
+    fn write_tile(&self, zoom: u8, x: u32, y: u32, obj: &[u8]) -> rusqlite::Result<()> {
+        // MBTiles readers expect gzip compressed tiles, also for empty tiles
+        let data = if Tile::is_gzipped(obj) {
+            obj.to_vec()
+        } else {
+            Tile::gzip(obj)
+        };
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("INSERT OR REPLACE INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+                     &[&(zoom as i64), &(x as i64), &Self::tile_row(zoom, y), &data])?;
+        tx.commit()
+    }
+    fn remove_tile(&self, zoom: u8, x: u32, y: u32) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                     &[&(zoom as i64), &(x as i64), &Self::tile_row(zoom, y)])
+            .map(|_| ())
+    }
+    fn write_metadata(&self, obj: &[u8]) -> rusqlite::Result<()> {
+        let metadata: serde_json::Value = match serde_json::from_slice(obj) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Invalid MBTiles metadata: {}", e);
+                return Ok(());
+            }
+        };
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if let Some(entries) = metadata.as_object() {
+            for (name, value) in entries {
+                let value = match value {
+                    &serde_json::Value::String(ref s) => s.clone(),
+                    v => v.to_string(),
+                };
+                tx.execute("INSERT OR REPLACE INTO metadata (name, value) VALUES (?1, ?2)",
+                             &[name, &value])?;
+            }
+        }
+        tx.commit()
+    }
+}
+
+impl Cache for MBTilesCache {
+    fn info(&self) -> String {
+        format!("Tile cache MBTiles file: {}", self.path)
+    }
+    fn baseurl(&self) -> String {
+        "http://localhost:6767".to_string()
+    }
+    fn read<F>(&self, path: &str, mut read: F) -> bool
+        where F: FnMut(&mut Read)
+    {
+        debug!("MBTilesCache.read {}", path);
+        match Self::entry(path) {
+            Some(Entry::Tile { zoom, x, y }) => {
+                match self.read_tile(zoom, x, y) {
+                    Some(data) => {
+                        read(&mut &data[..]);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+    fn write(&self, path: &str, obj: &[u8]) -> Result<(), io::Error> {
+        debug!("MBTilesCache.write {}", path);
+        let result = match Self::entry(path) {
+            Some(Entry::Tile { zoom, x, y }) => self.write_tile(zoom, x, y, obj),
+            Some(Entry::Metadata) => self.write_metadata(obj),
+            // TileJSON and style files are not stored in MBTiles
+            None => Ok(()),
+        };
+        result.map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+    fn exists(&self, path: &str) -> bool {
+        match Self::entry(path) {
+            Some(Entry::Tile { zoom, x, y }) => {
+                let conn = self.conn.lock().unwrap();
+                conn.query_row("SELECT 1 FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                               &[&(zoom as i64), &(x as i64), &Self::tile_row(zoom, y)],
+                               |_| ())
+                    .is_ok()
+            }
+            _ => false,
+        }
+    }
+    fn remove(&self, path: &str) -> Result<(), io::Error> {
+        match Self::entry(path) {
+            Some(Entry::Tile { zoom, x, y }) => {
+                self.remove_tile(zoom, x, y)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+            }
+            _ => Ok(()),
+        }
+    }    /// Delete tiles of each zoom level with a single statement
+    #[allow(unused_variables)]
+    fn purge(&self,
+             tileset: &str,
+             grid: &Grid,
+             extent: &Extent,
+             minzoom: u8,
+             maxzoom: u8)
+             -> Result<(), io::Error> {
+        let conn = self.conn.lock().unwrap();
+        for zoom in minzoom..maxzoom + 1 {
+            if zoom > grid.maxzoom() {
+                break;
+            }
+            let (minx, miny, maxx, maxy) = grid.extent_to_tile_range(extent, zoom);
+            conn.execute("DELETE FROM tiles WHERE zoom_level = ?1 AND tile_column BETWEEN ?2 AND ?3 AND tile_row BETWEEN ?4 AND ?5",
+                         &[&(zoom as i64),
+                           &(minx as i64),
+                           &(maxx as i64),
+                           &Self::tile_row(zoom, maxy),
+                           &Self::tile_row(zoom, miny)])
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        }
+        Ok(())
+    }
+}
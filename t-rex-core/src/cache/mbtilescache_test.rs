@@ -0,0 +1,91 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use cache::cache::Cache;
+use cache::mbtilescache::MBTilesCache;
+use mvt::tile::Tile;
+use rusqlite::Connection;
+use std::fs;
+
+
+#[test]
+fn test_mbtilescache() {
+    use std::env;
+
+    let mut file = env::temp_dir();
+    file.push("t_rex_test.mbtiles");
+    let path = format!("{}", &file.display());
+    let _ = fs::remove_file(&path);
+
+    let cache = MBTilesCache::open(&path).unwrap();
+    let tilepath = "tileset/2/1/0.pbf";
+    let obj = "0123456789";
+
+    // Cache miss
+    assert_eq!(cache.read(tilepath, |_| {}), false);
+    assert!(!cache.exists(tilepath));
+
+    // Write into cache
+    assert!(cache.write(tilepath, obj.as_bytes()).is_ok());
+    assert!(cache.exists(tilepath));
+
+    // Overwrite existing entry
+    assert!(cache.write(tilepath, obj.as_bytes()).is_ok());
+
+    // Read from cache, stored gzip compressed
+    let mut data = Vec::new();
+    assert_eq!(cache.read(tilepath, |f| { let _ = f.read_to_end(&mut data); }),
+               true);
+    assert!(Tile::is_gzipped(&data));
+    assert_eq!(Tile::gunzip(&data).unwrap(), obj.as_bytes());
+
+    // Compressed tiles are stored unchanged
+    let tilegz = Tile::gzip(obj.as_bytes());
+    assert!(cache.write(tilepath, &tilegz).is_ok());
+    let mut data = Vec::new();
+    cache.read(tilepath, |f| { let _ = f.read_to_end(&mut data); });
+    assert_eq!(data, tilegz);
+
+    // Empty tiles are stored as compressed empty tile
+    let emptypath = "tileset/2/1/1.pbf";
+    assert!(cache.write(emptypath, &[]).is_ok());
+    let mut data = vec![0u8];
+    assert_eq!(cache.read(emptypath, |f| { data.clear(); let _ = f.read_to_end(&mut data); }),
+               true);
+    assert!(data.is_empty());
+
+    // Metadata
+    let metadata = r#"{"name":"tileset","format":"pbf","minzoom":0}"#;
+    assert!(cache.write("tileset/metadata.json", metadata.as_bytes()).is_ok());
+    // Not stored in MBTiles
+    assert!(cache.write("tileset.json", "{}".as_bytes()).is_ok());
+    assert_eq!(cache.read("tileset.json", |_| {}), false);
+
+    let conn = Connection::open(&path).unwrap();
+    let tiles: i64 = conn.query_row("SELECT count(*) FROM tiles", &[], |row| row.get(0))
+        .unwrap();
+    assert_eq!(tiles, 2);
+    let empty: Vec<u8> = conn.query_row("SELECT tile_data FROM tiles WHERE zoom_level = 2 AND tile_column = 1 AND tile_row = 2",
+                                        &[],
+                                        |row| row.get(0))
+        .unwrap();
+    assert!(Tile::is_gzipped(&empty));
+    // TMS y-flip
+    let tile_row: i64 = conn.query_row("SELECT tile_row FROM tiles WHERE zoom_level = 2 AND tile_column = 1 AND tile_data = ?1",
+                                       &[&tilegz],
+                                       |row| row.get(0))
+        .unwrap();
+    assert_eq!(tile_row, 3);
+    let format: String = conn.query_row("SELECT value FROM metadata WHERE name = 'format'",
+                                        &[],
+                                        |row| row.get(0))
+        .unwrap();
+    assert_eq!(format, "pbf");
+    let minzoom: String = conn.query_row("SELECT value FROM metadata WHERE name = 'minzoom'",
+                                         &[],
+                                         |row| row.get(0))
+        .unwrap();
+    assert_eq!(minzoom, "0");
+}
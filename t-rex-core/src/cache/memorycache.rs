@@ -0,0 +1,90 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use cache::cache::Cache;
+use lru_cache::LruCache;
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::usize;
+
+
+/// In-memory tile cache with least-recently-used eviction
+pub struct MemoryCache {
+    /// Maximal size of cached objects in bytes
+    pub max_size: usize,
+    entries: Mutex<LruEntries>,
+}
+
+struct LruEntries {
+    lru: LruCache<String, Vec<u8>>,
+    /// Total size of cached objects in bytes
+    size: usize,
+}
+
+impl MemoryCache {
+    pub fn new(max_size: usize) -> MemoryCache {
+        MemoryCache {
+            max_size: max_size,
+            entries: Mutex::new(LruEntries {
+                                    lru: LruCache::new(usize::MAX),
+                                    size: 0,
+                                }),
+        }
+    }
+    /// Total size of cached objects in bytes
+    pub fn size(&self) -> usize {
+        self.entries.lock().unwrap().size
+    }
+}
+
+impl Cache for MemoryCache {
+    fn info(&self) -> String {
+        format!("Tile cache in memory: {} MB",
+                self.max_size / (1024 * 1024))
+    }
+    fn baseurl(&self) -> String {
+        "http://localhost:6767".to_string()
+    }
+    fn read<F>(&self, path: &str, mut read: F) -> bool
+        where F: FnMut(&mut Read)
+    {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.lru.get_mut(path) {
+            Some(obj) => {
+                read(&mut &obj[..]);
+                true
+            }
+            None => false,
+        }
+    }
+    fn write(&self, path: &str, obj: &[u8]) -> Result<(), io::Error> {
+        if obj.len() > self.max_size {
+            debug!("MemoryCache: {} exceeds cache size", path);
+            return Ok(());
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(old) = entries.lru.insert(path.to_string(), obj.to_vec()) {
+            entries.size -= old.len();
+        }
+        entries.size += obj.len();
+        while entries.size > self.max_size {
+            match entries.lru.remove_lru() {
+                Some((_, evicted)) => entries.size -= evicted.len(),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.entries.lock().unwrap().lru.contains_key(path)
+    }
+    fn remove(&self, path: &str) -> Result<(), io::Error> {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(obj) = entries.lru.remove(path) {
+            entries.size -= obj.len();
+        }
+        Ok(())
+    }
+}
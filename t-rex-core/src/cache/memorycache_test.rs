@@ -0,0 +1,43 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use cache::cache::Cache;
+use cache::memorycache::MemoryCache;
+
+
+#[test]
+fn test_memorycache() {
+    let cache = MemoryCache::new(25);
+    let obj = "0123456789";
+
+    // Cache miss
+    assert_eq!(cache.read("tileset/0/0/0.pbf", |_| {}), false);
+
+    // Write into cache
+    let _ = cache.write("tileset/0/0/0.pbf", obj.as_bytes());
+    assert!(cache.exists("tileset/0/0/0.pbf"));
+    let _ = cache.write("tileset/0/0/0.pbf", obj.as_bytes());
+    assert_eq!(cache.size(), 10);
+
+    // Read from cache
+    let mut s = String::new();
+    assert_eq!(cache.read("tileset/0/0/0.pbf", |f| { let _ = f.read_to_string(&mut s); }),
+               true);
+    assert_eq!(&s, "0123456789");
+
+    // Evict least recently used entry
+    let _ = cache.write("tileset/1/0/0.pbf", obj.as_bytes());
+    assert_eq!(cache.read("tileset/0/0/0.pbf", |_| {}), true);
+    let _ = cache.write("tileset/1/0/1.pbf", obj.as_bytes());
+    assert_eq!(cache.size(), 20);
+    assert!(cache.exists("tileset/0/0/0.pbf"));
+    assert!(!cache.exists("tileset/1/0/0.pbf"));
+    assert!(cache.exists("tileset/1/0/1.pbf"));
+
+    // Objects larger than the cache are not stored
+    let _ = cache.write("tileset/2/0/0.pbf", &[0; 30]);
+    assert!(!cache.exists("tileset/2/0/0.pbf"));
+    assert_eq!(cache.size(), 20);
+}
@@ -5,22 +5,68 @@
 
 pub mod cache;
 pub mod filecache;
+pub mod mbtilescache;
+pub mod memorycache;
+pub mod s3cache;
 
 #[cfg(test)]
 mod filecache_test;
+#[cfg(test)]
+mod mbtilescache_test;
+#[cfg(test)]
+mod memorycache_test;
+#[cfg(test)]
+mod s3cache_test;
 
 pub use self::cache::Cache;
 pub use self::cache::Nocache;
 pub use self::filecache::Filecache;
+pub use self::mbtilescache::MBTilesCache;
+pub use self::memorycache::MemoryCache;
+pub use self::s3cache::S3Cache;
 use std::io::Read;
 use std::io;
+use std::time::{Duration, SystemTime};
 use core::Config;
+use core::grid::{Extent, Grid};
 use core::ApplicationCfg;
+use core::error::TrexError;
 
 
+/// Content hash of tile data (64 bit FNV-1a and data length), stable across platforms and releases
+pub fn content_hash(data: &[u8]) -> String {
+    let hash = data.iter()
+        .fold(0xcbf29ce484222325u64,
+              |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}{:08x}", hash, data.len())
+}
+
 pub enum Tilecache {
     Nocache(Nocache),
     Filecache(Filecache),
+    MBTilesCache(MBTilesCache),
+    S3Cache(S3Cache),
+    MemoryCache(MemoryCache),
+}
+
+impl Tilecache {
+    /// Generator output: MBTiles file with extension `.mbtiles`, file cache directory otherwise
+    pub fn from_output(path: &str) -> Result<Tilecache, TrexError> {
+        if path.ends_with(".mbtiles") {
+            MBTilesCache::open(path)
+                .map(Tilecache::MBTilesCache)
+                .map_err(TrexError::Cache)
+        } else {
+            Ok(Tilecache::Filecache(Filecache {
+                                        basepath: path.to_string(),
+                                        baseurl: None,
+                                        max_age: None,
+                                        delete_expired: false,
+                                        shard_levels: 0,
+                                        dedup: false,
+                                    }))
+        }
+    }
 }
 
 impl Cache for Tilecache {
@@ -28,12 +74,18 @@ impl Cache for Tilecache {
         match self {
             &Tilecache::Nocache(ref cache) => cache.info(),
             &Tilecache::Filecache(ref cache) => cache.info(),
+            &Tilecache::MBTilesCache(ref cache) => cache.info(),
+            &Tilecache::S3Cache(ref cache) => cache.info(),
+            &Tilecache::MemoryCache(ref cache) => cache.info(),
         }
     }
     fn baseurl(&self) -> String {
         match self {
             &Tilecache::Nocache(ref cache) => cache.baseurl(),
             &Tilecache::Filecache(ref cache) => cache.baseurl(),
+            &Tilecache::MBTilesCache(ref cache) => cache.baseurl(),
+            &Tilecache::S3Cache(ref cache) => cache.baseurl(),
+            &Tilecache::MemoryCache(ref cache) => cache.baseurl(),
         }
     }
     fn read<F>(&self, path: &str, read: F) -> bool
@@ -42,42 +94,137 @@ impl Cache for Tilecache {
         match self {
             &Tilecache::Nocache(ref cache) => cache.read(path, read),
             &Tilecache::Filecache(ref cache) => cache.read(path, read),
+            &Tilecache::MBTilesCache(ref cache) => cache.read(path, read),
+            &Tilecache::S3Cache(ref cache) => cache.read(path, read),
+            &Tilecache::MemoryCache(ref cache) => cache.read(path, read),
         }
     }
     fn write(&self, path: &str, obj: &[u8]) -> Result<(), io::Error> {
         match self {
             &Tilecache::Nocache(ref cache) => cache.write(path, obj),
             &Tilecache::Filecache(ref cache) => cache.write(path, obj),
+            &Tilecache::MBTilesCache(ref cache) => cache.write(path, obj),
+            &Tilecache::S3Cache(ref cache) => cache.write(path, obj),
+            &Tilecache::MemoryCache(ref cache) => cache.write(path, obj),
         }
     }
     fn exists(&self, path: &str) -> bool {
         match self {
             &Tilecache::Nocache(ref cache) => cache.exists(path),
             &Tilecache::Filecache(ref cache) => cache.exists(path),
+            &Tilecache::MBTilesCache(ref cache) => cache.exists(path),
+            &Tilecache::S3Cache(ref cache) => cache.exists(path),
+            &Tilecache::MemoryCache(ref cache) => cache.exists(path),
+        }
+    }
+    fn remove(&self, path: &str) -> Result<(), io::Error> {
+        match self {
+            &Tilecache::Nocache(ref cache) => cache.remove(path),
+            &Tilecache::Filecache(ref cache) => cache.remove(path),
+            &Tilecache::MBTilesCache(ref cache) => cache.remove(path),
+            &Tilecache::S3Cache(ref cache) => cache.remove(path),
+            &Tilecache::MemoryCache(ref cache) => cache.remove(path),
+        }
+    }
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        match self {
+            &Tilecache::Nocache(ref cache) => cache.modified(path),
+            &Tilecache::Filecache(ref cache) => cache.modified(path),
+            &Tilecache::MBTilesCache(ref cache) => cache.modified(path),
+            &Tilecache::S3Cache(ref cache) => cache.modified(path),
+            &Tilecache::MemoryCache(ref cache) => cache.modified(path),
+        }
+    }
+    fn file_size(&self, path: &str) -> Option<u64> {
+        match self {
+            &Tilecache::Nocache(ref cache) => cache.file_size(path),
+            &Tilecache::Filecache(ref cache) => cache.file_size(path),
+            &Tilecache::MBTilesCache(ref cache) => cache.file_size(path),
+            &Tilecache::S3Cache(ref cache) => cache.file_size(path),
+            &Tilecache::MemoryCache(ref cache) => cache.file_size(path),
+        }
+    }
+    fn purge(&self,
+             tileset: &str,
+             grid: &Grid,
+             extent: &Extent,
+             minzoom: u8,
+             maxzoom: u8)
+             -> Result<(), io::Error> {
+        match self {
+            &Tilecache::Nocache(ref cache) => cache.purge(tileset, grid, extent, minzoom, maxzoom),
+            &Tilecache::Filecache(ref cache) => {
+                cache.purge(tileset, grid, extent, minzoom, maxzoom)
+            }
+            &Tilecache::MBTilesCache(ref cache) => {
+                cache.purge(tileset, grid, extent, minzoom, maxzoom)
+            }
+            &Tilecache::S3Cache(ref cache) => cache.purge(tileset, grid, extent, minzoom, maxzoom),
+            &Tilecache::MemoryCache(ref cache) => {
+                cache.purge(tileset, grid, extent, minzoom, maxzoom)
+            }
         }
     }
 }
 
 impl<'a> Config<'a, Tilecache, ApplicationCfg> for Tilecache {
     fn from_config(config: &ApplicationCfg) -> Result<Self, String> {
-        config
-            .cache
-            .as_ref()
-            .map(|cache| {
-                     let fc = Filecache {
-                         basepath: cache.file.base.clone(),
-                         baseurl: cache.file.baseurl.clone(),
-                     };
-                     Tilecache::Filecache(fc)
-                 })
-            .or(Some(Tilecache::Nocache(Nocache)))
-            .ok_or("".to_string())
+        let cache = match config.cache {
+            Some(ref cache) => cache,
+            None => return Ok(Tilecache::Nocache(Nocache)),
+        };
+        match cache.strategy.as_ref().map(|s| s.as_str()).unwrap_or("file") {
+            "file" => {
+                let file = cache.file.as_ref().ok_or("cache.file missing")?;
+                let shard_levels = file.shard_levels.unwrap_or(0);
+                if shard_levels > 4 {
+                    return Err("cache.file.shard_levels must be between 0 and 4".to_string());
+                }
+                Ok(Tilecache::Filecache(Filecache {
+                                            basepath: file.base.clone(),
+                                            baseurl: file.baseurl.clone(),
+                                            max_age: cache.max_age_seconds.map(Duration::from_secs),
+                                            delete_expired: cache.delete_expired.unwrap_or(false),
+                                            shard_levels: shard_levels,
+                                            dedup: cache.dedup.unwrap_or(false),
+                                        }))
+            }
+            "mbtiles" => {
+                let path = cache.path.as_ref().ok_or("cache.path missing")?;
+                MBTilesCache::open(path).map(Tilecache::MBTilesCache)
+            }
+            "s3" => {
+                let bucket = cache.bucket.as_ref().ok_or("cache.bucket missing")?;
+                let region = cache.region.as_ref().ok_or("cache.region missing")?;
+                let credentials = match (&cache.access_key, &cache.secret_key) {
+                    (&Some(ref key), &Some(ref secret)) => Some((key.clone(), secret.clone())),
+                    (&None, &None) => None,
+                    _ => {
+                        return Err("cache.access_key and cache.secret_key must be set together"
+                                       .to_string())
+                    }
+                };
+                S3Cache::new(bucket,
+                             region,
+                             cache.prefix.clone(),
+                             credentials,
+                             cache.baseurl.clone())
+                    .map(Tilecache::S3Cache)
+            }
+            "memory" => {
+                let size_mb = cache.size_mb.ok_or("cache.size_mb missing")?;
+                Ok(Tilecache::MemoryCache(MemoryCache::new(size_mb as usize * 1024 * 1024)))
+            }
+            strategy => Err(format!("Unknown cache strategy '{}'", strategy)),
+        }
     }
     fn gen_config() -> String {
         let toml = r#"
 #[cache.file]
 #base = "/tmp/mvtcache"
 #baseurl = "http://example.com/tiles"
+# Distribute tiles into hash bucket directories (0-4)
+#shard_levels = 0
 "#;
         toml.to_string()
     }
@@ -0,0 +1,344 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use cache::cache::Cache;
+use core::grid::{Extent, Grid};
+use futures::{Future, Stream};
+use mvt::tile::Tile;
+use rusoto_core::{HttpClient, Region};
+use rusoto_core::request::BufferedHttpResponse;
+use rusoto_credential::StaticProvider;
+use rusoto_s3::{S3, S3Client, Delete, DeleteObjectError, DeleteObjectRequest,
+                DeleteObjectsError, DeleteObjectsRequest, GetObjectError, GetObjectRequest,
+                HeadObjectError, HeadObjectRequest, ListObjectsV2Error, ListObjectsV2Request,
+                ObjectIdentifier, PutObjectError, PutObjectRequest};
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+
+/// Maximal number of retries for transient S3 errors
+const MAX_RETRIES: u32 = 3;
+
+/// Maximal number of objects deleted with one request
+const MAX_DELETE_OBJECTS: usize = 1000;
+
+/// Tile cache stored in an S3 bucket
+pub struct S3Cache {
+    pub bucket: String,
+    pub prefix: Option<String>,
+    pub baseurl: Option<String>,
+    client: S3Client,
+}
+
+impl S3Cache {
+    /// Create S3 client. Credentials are taken from environment, profile or
+    /// instance metadata unless given explicitly.
+    pub fn new(bucket: &str,
+               region: &str,
+               prefix: Option<String>,
+               credentials: Option<(String, String)>,
+               baseurl: Option<String>)
+               -> Result<S3Cache, String> {
+        let region = Region::from_str(region).map_err(|e| format!("{}", e))?;
+        let client = match credentials {
+            Some((access_key, secret_key)) => {
+                let http = HttpClient::new().map_err(|e| format!("{}", e))?;
+                S3Client::new_with(http,
+                                   StaticProvider::new_minimal(access_key, secret_key),
+                                   region)
+            }
+            None => S3Client::new(region),
+        };
+        Ok(S3Cache {
+               bucket: bucket.to_string(),
+               prefix: prefix,
+               baseurl: baseurl,
+               client: client,
+           })
+    }
+    /// Object key of cache path
+    pub fn key(&self, path: &str) -> String {
+        match self.prefix {
+            Some(ref prefix) if !prefix.is_empty() => {
+                format!("{}/{}", prefix.trim_right_matches('/'), path)
+            }
+            _ => path.to_string(),
+        }
+    }
+    /// Content-Type and Content-Encoding of cache object
+    fn content_type(path: &str, obj: &[u8]) -> (&'static str, Option<&'static str>) {
+        if path.ends_with(".pbf") {
+            let encoding = if Tile::is_gzipped(obj) {
+                Some("gzip")
+            } else {
+                None
+            };
+            ("application/x-protobuf", encoding)
+        } else if path.ends_with(".json") {
+            ("application/json", None)
+        } else {
+            ("application/octet-stream", None)
+        }
+    }
+    /// Keys of all objects starting with `prefix`
+    fn list_keys(&self, prefix: &str) -> Result<Vec<String>, io::Error> {
+        let mut keys = Vec::new();
+        let mut continuation_token: Option<String> = None;
+        loop {
+            let output = with_retry(|| {
+                let request = ListObjectsV2Request {
+                    bucket: self.bucket.clone(),
+                    prefix: Some(prefix.to_string()),
+                    continuation_token: continuation_token.clone(),
+                    ..Default::default()
+                };
+                self.client.list_objects_v2(request).sync()
+            },
+                                    |e| match e {
+                                        &ListObjectsV2Error::HttpDispatch(_) => true,
+                                        &ListObjectsV2Error::Unknown(ref resp) => {
+                                            is_server_error(resp)
+                                        }
+                                        _ => false,
+                                    })
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{}", e)))?;
+            keys.extend(output
+                            .contents
+                            .unwrap_or_default()
+                            .into_iter()
+                            .filter_map(|object| object.key));
+            match output.next_continuation_token {
+                Some(token) if output.is_truncated == Some(true) => {
+                    continuation_token = Some(token)
+                }
+                _ => return Ok(keys),
+            }
+        }
+    }
+    /// Delete objects with one request
+    fn delete_keys(&self, keys: &[String]) -> Result<(), io::Error> {
+        debug!("S3Cache.purge {} objects in s3://{}", keys.len(), self.bucket);
+        with_retry(|| {
+            let request = DeleteObjectsRequest {
+                bucket: self.bucket.clone(),
+                delete: Delete {
+                    objects: keys.iter()
+                        .map(|key| {
+                                 ObjectIdentifier {
+                                     key: key.clone(),
+                                     version_id: None,
+                                 }
+                             })
+                        .collect(),
+                    quiet: Some(true),
+                },
+                ..Default::default()
+            };
+            self.client.delete_objects(request).sync()
+        },
+                   |e| match e {
+                       &DeleteObjectsError::HttpDispatch(_) => true,
+                       &DeleteObjectsError::Unknown(ref resp) => is_server_error(resp),
+                       _ => false,
+                   })
+            .map(|_| ())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{}", e)))
+    }
+}
+
+/// Column and row of tile path `x/y.pbf`
+fn tile_xy(path: &str) -> Option<(u32, u32)> {
+    let mut parts = path.splitn(2, '/');
+    let xtile = parts.next().and_then(|x| x.parse::<u32>().ok());
+    let ytile = parts
+        .next()
+        .and_then(|y| if y.ends_with(".pbf") {
+                      y.trim_right_matches(".pbf").parse::<u32>().ok()
+                  } else {
+                      None
+                  });
+    match (xtile, ytile) {
+        (Some(xtile), Some(ytile)) => Some((xtile, ytile)),
+        _ => None,
+    }
+}
+
+fn is_server_error(response: &BufferedHttpResponse) -> bool {
+    response.status.is_server_error()
+}
+
+fn is_not_found(response: &BufferedHttpResponse) -> bool {
+    response.status.as_u16() == 404
+}
+
+/// Retry S3 request with exponential backoff on transient errors
+fn with_retry<T, E, F, R>(mut request: F, is_transient: R) -> Result<T, E>
+    where F: FnMut() -> Result<T, E>,
+          R: Fn(&E) -> bool,
+          E: fmt::Display
+{
+    let mut attempt = 0;
+    loop {
+        match request() {
+            Err(ref e) if attempt < MAX_RETRIES && is_transient(e) => {
+                let delay = 100 * 2u64.pow(attempt);
+                warn!("S3 request failed: {} - retrying in {}ms", e, delay);
+                thread::sleep(Duration::from_millis(delay));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+impl Cache for S3Cache {
+    fn info(&self) -> String {
+        format!("Tile cache S3 bucket: s3://{}/{}",
+                self.bucket,
+                self.prefix.clone().unwrap_or_default())
+    }
+    fn baseurl(&self) -> String {
+        self.baseurl
+            .clone()
+            .unwrap_or("http://localhost:6767".to_string())
+    }
+    fn read<F>(&self, path: &str, mut read: F) -> bool
+        where F: FnMut(&mut Read)
+    {
+        let key = self.key(path);
+        debug!("S3Cache.read s3://{}/{}", self.bucket, key);
+        let result = with_retry(|| {
+            let request = GetObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.clone(),
+                ..Default::default()
+            };
+            self.client.get_object(request).sync()
+        },
+                                |e| match e {
+                                    &GetObjectError::HttpDispatch(_) => true,
+                                    &GetObjectError::Unknown(ref resp) => is_server_error(resp),
+                                    _ => false,
+                                });
+        match result {
+            Ok(output) => {
+                let data = match output.body.map(|body| body.concat2().wait()) {
+                    Some(Ok(data)) => data,
+                    Some(Err(e)) => {
+                        error!("Error reading s3://{}/{}: {}", self.bucket, key, e);
+                        return false;
+                    }
+                    None => Vec::new(),
+                };
+                read(&mut &data[..]);
+                true
+            }
+            // Cache miss
+            Err(GetObjectError::NoSuchKey(_)) => false,
+            Err(GetObjectError::Unknown(ref resp)) if is_not_found(resp) => false,
+            Err(e) => {
+                error!("Error reading s3://{}/{}: {}", self.bucket, key, e);
+                false
+            }
+        }
+    }
+    fn write(&self, path: &str, obj: &[u8]) -> Result<(), io::Error> {
+        let key = self.key(path);
+        debug!("S3Cache.write s3://{}/{}", self.bucket, key);
+        let (content_type, content_encoding) = Self::content_type(path, obj);
+        with_retry(|| {
+            let request = PutObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.clone(),
+                body: Some(obj.to_vec().into()),
+                content_type: Some(content_type.to_string()),
+                content_encoding: content_encoding.map(|enc| enc.to_string()),
+                ..Default::default()
+            };
+            self.client.put_object(request).sync()
+        },
+                   |e| match e {
+                       &PutObjectError::HttpDispatch(_) => true,
+                       &PutObjectError::Unknown(ref resp) => is_server_error(resp),
+                       _ => false,
+                   })
+            .map(|_| ())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{}", e)))
+    }
+    fn exists(&self, path: &str) -> bool {
+        let key = self.key(path);
+        let result = with_retry(|| {
+            let request = HeadObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.clone(),
+                ..Default::default()
+            };
+            self.client.head_object(request).sync()
+        },
+                                |e| match e {
+                                    &HeadObjectError::HttpDispatch(_) => true,
+                                    &HeadObjectError::Unknown(ref resp) => is_server_error(resp),
+                                    _ => false,
+                                });
+        match result {
+            Ok(_) => true,
+            Err(HeadObjectError::NoSuchKey(_)) => false,
+            Err(HeadObjectError::Unknown(ref resp)) if is_not_found(resp) => false,
+            Err(e) => {
+                error!("Error checking s3://{}/{}: {}", self.bucket, key, e);
+                false
+            }
+        }
+    }
+    fn remove(&self, path: &str) -> Result<(), io::Error> {
+        let key = self.key(path);
+        debug!("S3Cache.remove s3://{}/{}", self.bucket, key);
+        with_retry(|| {
+            let request = DeleteObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.clone(),
+                ..Default::default()
+            };
+            self.client.delete_object(request).sync()
+        },
+                   |e| match e {
+                       &DeleteObjectError::HttpDispatch(_) => true,
+                       &DeleteObjectError::Unknown(ref resp) => is_server_error(resp),
+                       _ => false,
+                   })
+            .map(|_| ())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{}", e)))
+    }    /// Delete existing objects below the prefix of each zoom level, instead of
+    /// deleting every tile of the zoom level range
+    fn purge(&self,
+             tileset: &str,
+             grid: &Grid,
+             extent: &Extent,
+             minzoom: u8,
+             maxzoom: u8)
+             -> Result<(), io::Error> {
+        for zoom in minzoom..maxzoom + 1 {
+            if zoom > grid.maxzoom() {
+                break;
+            }
+            let (minx, miny, maxx, maxy) = grid.extent_to_tile_range(extent, zoom);
+            let prefix = self.key(&format!("{}/{}/", tileset, zoom));
+            let keys: Vec<String> = self.list_keys(&prefix)?
+                .into_iter()
+                .filter(|key| match tile_xy(&key[prefix.len()..]) {
+                            Some((x, y)) => x >= minx && x <= maxx && y >= miny && y <= maxy,
+                            None => false,
+                        })
+                .collect();
+            for chunk in keys.chunks(MAX_DELETE_OBJECTS) {
+                self.delete_keys(chunk)?;
+            }
+        }
+        Ok(())
+    }
+}
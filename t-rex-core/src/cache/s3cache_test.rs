@@ -0,0 +1,58 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use cache::cache::Cache;
+use cache::s3cache::S3Cache;
+use core::grid::Grid;
+
+
+#[test]
+fn test_s3_key() {
+    let cache = S3Cache::new("tiles", "eu-central-1", Some("cache/".to_string()), None, None)
+        .unwrap();
+    assert_eq!(cache.key("tileset/0/1/2.pbf"), "cache/tileset/0/1/2.pbf");
+    let cache = S3Cache::new("tiles", "eu-central-1", None, None, None).unwrap();
+    assert_eq!(cache.key("tileset/0/1/2.pbf"), "tileset/0/1/2.pbf");
+
+    assert!(S3Cache::new("tiles", "no-region", None, None, None).is_err());
+}
+
+#[test]
+#[ignore]
+fn test_s3cache() {
+    use std::env;
+
+    let bucket = env::var("S3BUCKET").expect("S3BUCKET undefined");
+    let region = env::var("S3REGION").unwrap_or("eu-central-1".to_string());
+    let cache = S3Cache::new(&bucket, &region, Some("t_rex_test".to_string()), None, None)
+        .unwrap();
+    let path = "tileset/0/1/2.pbf";
+    let obj = "0123456789";
+
+    // Cache miss
+    assert_eq!(cache.read("tileset/0/0/missing.pbf", |_| {}), false);
+    assert!(!cache.exists("tileset/0/0/missing.pbf"));
+
+    // Write into cache
+    assert!(cache.write(path, obj.as_bytes()).is_ok());
+    assert!(cache.exists(path));
+
+    // Read from cache
+    let mut s = String::new();
+    assert_eq!(cache.read(path, |f| { let _ = f.read_to_string(&mut s); }),
+               true);
+    assert_eq!(&s, "0123456789");
+
+    // Purge tiles below zoom level prefix
+    let grid = Grid::web_mercator();
+    assert!(cache.write("tileset/1/1/0.pbf", obj.as_bytes()).is_ok());
+    assert!(cache.write("tileset/1/0/1.pbf", obj.as_bytes()).is_ok());
+    let extent = grid.tile_extent_xyz(1, 0, 1);
+    assert!(cache.purge("tileset", &grid, &extent, 0, 2).is_ok());
+    assert!(!cache.exists("tileset/1/1/0.pbf"));
+    assert!(cache.exists("tileset/1/0/1.pbf"));
+    assert!(cache.purge("tileset", &grid, &grid.extent, 0, 2).is_ok());
+    assert!(!cache.exists("tileset/1/0/1.pbf"));
+}
@@ -6,8 +6,12 @@
 use toml::Value;
 use std::io::prelude::*;
 use std::fs::File;
+use std::env;
+use std::collections::BTreeMap;
 use core::grid::Extent;
-use serde::Deserialize;
+use core::error::TrexError;
+use serde::{Deserialize, Deserializer};
+use serde::de;
 
 
 pub trait Config<'a, T, C: Deserialize<'a>> {
@@ -24,7 +28,9 @@ pub trait Config<'a, T, C: Deserialize<'a>> {
 #[derive(Deserialize, Debug)]
 pub struct ApplicationCfg {
     pub service: ServiceCfg,
-    pub datasource: DatasourceCfg,
+    /// Datasources from `[datasource]` or `[[datasource]]` blocks
+    #[serde(rename = "datasource", deserialize_with = "one_or_many")]
+    pub datasources: Vec<DatasourceCfg>,
     pub grid: GridCfg,
     #[serde(rename = "tileset")]
     pub tilesets: Vec<TilesetCfg>,
@@ -40,13 +46,47 @@ pub struct ServiceCfg {
 #[derive(Deserialize, Debug)]
 pub struct ServiceMvtCfg {
     pub viewer: bool,
+    /// MVT coordinate extent (default 4096)
+    pub extent: Option<u32>,
+    /// Include layers without features in tiles (default false)
+    pub keep_empty_layers: Option<bool>,
+    /// Upper feature count limit of all layer queries (default 100000)
+    pub max_features: Option<u32>,
+    /// Default `buffer_size` of layers by geometry type
+    pub buffer: Option<BufferCfg>,
+}
+
+/// Buffer sizes in pixels by geometry type, e.g. `{ line = 64, polygon = 64, point = 0 }`
+#[derive(Deserialize, Clone, Default, Debug)]
+pub struct BufferCfg {
+    pub point: Option<u32>,
+    pub line: Option<u32>,
+    pub polygon: Option<u32>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct DatasourceCfg {
+    /// Name referenced by layers (optional for a single datasource)
+    pub name: Option<String>,
     #[serde(rename = "type")]
     pub dstype: String,
-    pub url: String,
+    /// PostGIS connection URL
+    pub url: Option<String>,
+    /// PostGIS connection parameters (alternative to `url`)
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub dbname: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub sslmode: Option<String>,
+    /// File path (GeoJSON or SpatiaLite datasource)
+    pub path: Option<String>,
+    pub pool_size: Option<u16>,
+    /// Number of rows fetched per round trip when streaming features
+    pub fetch_size: Option<u32>,
+    /// Abort queries running longer than this (in milliseconds)
+    pub query_timeout_ms: Option<u32>,
+    pub use_postgis_mvt: Option<bool>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -56,6 +96,8 @@ pub struct GridCfg {
     /// The width and height of an individual tile, in pixels.
     pub width: Option<u16>,
     pub height: Option<u16>,
+    /// Tile width and height in pixels, also applied to predefined grids (default 256)
+    pub tile_size: Option<u16>,
     /// The geographical extent covered by the grid, in ground units (e.g. meters, degrees, feet, etc.).
     /// Must be specified as 4 floating point numbers ordered as minx, miny, maxx, maxy.
     /// The (minx,miny) point defines the origin of the grid, i.e. the pixel at the bottom left of the
@@ -76,14 +118,20 @@ pub struct GridCfg {
     pub resolutions: Vec<f64>,
     /// Grid origin
     pub origin: Option<String>,
+    /// Tile requests use TMS numbering with origin bottom-left (default: XYZ with origin top-left)
+    pub tms: Option<bool>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct TilesetCfg {
     pub name: String,
     pub extent: Option<Extent>,
-    //? pub minzoom: Option<u8>,
-    //? pub maxzoom: Option<u8>,
+    pub minzoom: Option<u8>,
+    pub maxzoom: Option<u8>,
+    /// Serve requests above maxzoom with the content of maxzoom instead of 404
+    pub overzoom: Option<bool>,
+    /// Retrieve layers from the same table with a single query
+    pub batch_layers: Option<bool>,
     //? pub center: [0.0, 0.0, 2],
     #[serde(rename = "layer")]
     pub layers: Vec<LayerCfg>,
@@ -98,47 +146,139 @@ pub struct LayerQueryCfg {
     pub sql: Option<String>,
 }
 
+/// Feature count limit for all zoom levels or per zoom level
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum QueryLimitCfg {
+    Limit(u32),
+    /// Limit from zoom level upwards, e.g. `{ 0 = 100, 12 = 1000 }`
+    Zoom(BTreeMap<String, u32>),
+}
+
+/// Attribute columns or type hints of attributes
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum FieldsCfg {
+    Names(Vec<String>),
+    /// Attribute types overriding detected types, e.g. `{ population = "int" }`
+    Types(BTreeMap<String, String>),
+}
+
 #[derive(Deserialize, Debug)]
 pub struct LayerCfg {
     pub name: String,
+    /// Datasource name (default: first datasource)
+    pub datasource: Option<String>,
     pub geometry_field: Option<String>,
     pub geometry_type: Option<String>,
     /// Spatial reference system (PostGIS SRID)
     pub srid: Option<i32>,
     pub fid_field: Option<String>,
+    /// Attribute columns included as feature properties (default: all)
+    pub fields: Option<FieldsCfg>,
+    /// JSON columns with members encoded as separate attributes
+    #[serde(default)]
+    pub flatten_json: Vec<String>,
+    /// Levels of nested JSON objects included in flattened attributes (default 1)
+    pub json_flatten_depth: Option<u8>,
     // Input for derived queries
     pub table_name: Option<String>,
-    pub query_limit: Option<u32>,
+    pub query_limit: Option<QueryLimitCfg>,
+    /// SQL ORDER BY expression defining the feature order in tiles
+    pub order_by: Option<String>,
+    /// SQL condition selecting the features of the table
+    pub filter: Option<String>,
+    /// Aggregate features to cluster centroids with a `count` attribute, if a tile
+    /// would contain more features
+    pub cluster_threshold: Option<u32>,
+    /// Cluster grid cell size in pixels (default 32)
+    pub cluster_size: Option<u32>,
+    /// Zoom level range of layer (default: range of queries)
+    pub minzoom: Option<u8>,
+    pub maxzoom: Option<u8>,
+    /// Map scale range of layer, in addition to zoom level range
+    pub minscaledenominator: Option<f64>,
+    pub maxscaledenominator: Option<f64>,
     // Explicit queries
     #[serde(default)]
     pub query: Vec<LayerQueryCfg>,
+    /// Replace geometries with a point on their surface, e.g. as label anchor of polygons
+    pub label_point: Option<bool>,
     /// Simplify geometry (lines and polygons)
     pub simplify: Option<bool>,
-    /// Tile buffer size in pixels
+    /// Simplification algorithm: "dp" (Douglas–Peucker, default) or "vw" (Visvalingam–Whyatt)
+    pub simplify_algorithm: Option<String>,
+    /// Simplification tolerance in tile coordinate units (default 1)
+    pub tolerance: Option<f64>,
+    /// Tile buffer size in pixels, expanding the query envelope and the clipping rectangle
     pub buffer_size: Option<u32>,
+    /// Clip geometries to tile extent expanded by clip_buffer (in tile coordinate units, default 0)
+    pub clip_buffer: Option<u32>,
+    /// Intersect geometries with the buffered tile extent, with coordinates snapped to the tile grid
+    pub intersect: Option<bool>,
+    /// Snap tile coordinates to multiples of precision (in tile coordinate units)
+    pub precision: Option<u32>,
     // Inline style
     pub style: Option<Value>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct CacheCfg {
-    pub file: CacheFileCfg,
+    /// Cache strategy: file (default), mbtiles, s3, memory
+    pub strategy: Option<String>,
+    pub file: Option<CacheFileCfg>,
+    /// MBTiles file path
+    pub path: Option<String>,
+    /// S3 bucket name
+    pub bucket: Option<String>,
+    /// S3 region (e.g. eu-central-1)
+    pub region: Option<String>,
+    /// Object key prefix within S3 bucket
+    pub prefix: Option<String>,
+    /// S3 credentials (default: environment, profile or instance metadata)
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    /// Base URL of S3 tile cache published in metadata
+    pub baseurl: Option<String>,
+    /// Expiration time of file cache entries (default: never expire)
+    pub max_age_seconds: Option<u64>,
+    /// Delete expired file cache entries on access
+    pub delete_expired: Option<bool>,
+    /// Store identical file cache tiles only once (default false)
+    pub dedup: Option<bool>,
+    /// Store gzip compressed tiles (default true)
+    pub gzip: Option<bool>,
+    /// Memory cache size in MB
+    pub size_mb: Option<u32>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct CacheFileCfg {
     pub base: String,
     pub baseurl: Option<String>,
+    /// Number of hash bucket directory levels for tile files (0-4, default 0)
+    pub shard_levels: Option<u8>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct WebserverCfg {
     pub bind: Option<String>,
     pub port: Option<u16>,
+    /// Number of HTTP worker threads (default: number of CPUs)
     pub threads: Option<u8>,
     // Cache-Control headers set by web server
     // https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Cache-Control#Expiration
     pub cache_control_max_age: Option<u32>,
+    // Origins allowed for cross-origin requests (default: all)
+    pub allowed_origins: Option<Vec<String>>,
+    /// Serve generated map viewer page at / (default: false)
+    pub viewer: Option<bool>,
+    /// Reload configuration file with POST /reload (default: false)
+    pub reload: Option<bool>,
+    /// URL path prefix of all routes, e.g. `/tiles`
+    pub base_path: Option<String>,
+    /// Last-Modified date of tiles not read from a file cache (HTTP date)
+    pub last_modified: Option<String>,
 }
 
 pub const DEFAULT_CONFIG: &'static str = r#"
@@ -161,29 +301,86 @@ name = ""
 [webserver]
 bind = "127.0.0.1"
 port = 6767
-threads = 4
 "#;
 
+/// Deserialize a single table or an array of tables
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where D: Deserializer<'de>,
+          T: Deserialize<'de>
+{
+    let value = Value::deserialize(deserializer)?;
+    let values = match value {
+        Value::Array(_) => value.try_into(),
+        _ => value.try_into().map(|v| vec![v]),
+    };
+    values.map_err(de::Error::custom)
+}
+
 /// Load and parse the config file into an config struct.
-pub fn read_config<'a, T: Deserialize<'a>>(path: &str) -> Result<T, String> {
+pub fn read_config<'a, T: Deserialize<'a>>(path: &str) -> Result<T, TrexError> {
     let mut file = match File::open(path) {
         Ok(file) => file,
         Err(_) => {
-            return Err("Could not find config file!".to_string());
+            return Err(TrexError::Config("Could not find config file!".to_string()));
         }
     };
     let mut config_toml = String::new();
     if let Err(err) = file.read_to_string(&mut config_toml) {
-        return Err(format!("Error while reading config: [{}]", err));
+        return Err(TrexError::Config(format!("Error while reading config: [{}]", err)));
     };
 
     parse_config(config_toml, path)
 }
 
 /// Parse the configuration into an config struct.
-pub fn parse_config<'a, T: Deserialize<'a>>(config_toml: String, path: &str) -> Result<T, String> {
-    config_toml
+pub fn parse_config<'a, T: Deserialize<'a>>(config_toml: String,
+                                            path: &str)
+                                            -> Result<T, TrexError> {
+    let mut cfg = config_toml
         .parse::<Value>()
-        .and_then(|cfg| cfg.try_into::<T>())
-        .map_err(|err| format!("{} - {}", path, err))
+        .map_err(|err| TrexError::Config(format!("{} - {}", path, err)))?;
+    interpolate_env_vars(&mut cfg)
+        .map_err(|err| TrexError::Config(format!("{} - {}", path, err)))?;
+    cfg.try_into::<T>()
+        .map_err(|err| TrexError::Config(format!("{} - {}", path, err)))
+}
+
+/// Replace `${ENV_VAR}` references in string values with environment variables.
+fn interpolate_env_vars(value: &mut Value) -> Result<(), String> {
+    match value {
+        &mut Value::String(ref mut s) => {
+            *s = expand_env_vars(s)?;
+        }
+        &mut Value::Array(ref mut values) => {
+            for v in values.iter_mut() {
+                interpolate_env_vars(v)?;
+            }
+        }
+        &mut Value::Table(ref mut table) => {
+            for (_, v) in table.iter_mut() {
+                interpolate_env_vars(v)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+pub fn expand_env_vars(s: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(len) => start + len,
+            None => break,
+        };
+        let name = &rest[start + 2..end];
+        let value = env::var(name)
+            .map_err(|_| format!("Environment variable `{}` is not defined", name))?;
+        result.push_str(&rest[..start]);
+        result.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
 }
@@ -0,0 +1,77 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use std::error::Error;
+use std::fmt;
+
+
+/// t-rex error with the subsystem where it occured
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrexError {
+    /// Invalid or unreadable configuration
+    Config(String),
+    /// Feature retrieval from datasource failed
+    Datasource(String),
+    /// Datasource not reachable or connection lost
+    Connection(String),
+    /// Datasource query aborted after timeout
+    Timeout(String),
+    /// Tile cache could not be opened, read or written
+    Cache(String),
+    /// Tile content could not be decoded or encoded
+    Encoding(String),
+    /// Invalid grid or tile coordinates
+    Grid(String),
+    /// Tile coordinates outside of the tile matrix of the grid
+    TileOutOfRange(String),
+    /// Tileset not found in configuration
+    UnknownTileset(String),
+}
+
+impl fmt::Display for TrexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &TrexError::UnknownTileset(ref name) => write!(f, "Unknown tileset '{}'", name),
+            &TrexError::Config(ref msg) |
+            &TrexError::Datasource(ref msg) |
+            &TrexError::Connection(ref msg) |
+            &TrexError::Timeout(ref msg) |
+            &TrexError::Cache(ref msg) |
+            &TrexError::Encoding(ref msg) |
+            &TrexError::Grid(ref msg) |
+            &TrexError::TileOutOfRange(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for TrexError {
+    fn description(&self) -> &str {
+        match self {
+            &TrexError::Config(_) => "configuration error",
+            &TrexError::Datasource(_) => "datasource error",
+            &TrexError::Connection(_) => "datasource connection error",
+            &TrexError::Timeout(_) => "datasource timeout",
+            &TrexError::Cache(_) => "cache error",
+            &TrexError::Encoding(_) => "tile encoding error",
+            &TrexError::Grid(_) => "grid error",
+            &TrexError::TileOutOfRange(_) => "tile out of range",
+            &TrexError::UnknownTileset(_) => "unknown tileset",
+        }
+    }
+}
+
+/// String errors of `Config::from_config` are configuration errors
+impl From<String> for TrexError {
+    fn from(msg: String) -> TrexError {
+        TrexError::Config(msg)
+    }
+}
+
+/// For functions still returning string errors
+impl From<TrexError> for String {
+    fn from(err: TrexError) -> String {
+        err.to_string()
+    }
+}
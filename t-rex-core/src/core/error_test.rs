@@ -0,0 +1,56 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use core::error::TrexError;
+use core::config::{parse_config, read_config, ApplicationCfg};
+use core::grid::Grid;
+use cache::Tilecache;
+use std::error::Error;
+
+
+#[test]
+fn test_config_error() {
+    let config: Result<ApplicationCfg, _> = read_config("wrongfile");
+    assert_eq!(config.err(),
+               Some(TrexError::Config("Could not find config file!".to_string())));
+
+    let config: Result<ApplicationCfg, _> = parse_config("[grid".to_string(), "config.toml");
+    match config {
+        Err(TrexError::Config(msg)) => assert!(msg.starts_with("config.toml - ")),
+        _ => panic!("Config error expected"),
+    }
+}
+
+#[test]
+fn test_cache_error() {
+    match Tilecache::from_output("/nonexistent/t_rex/tiles.mbtiles") {
+        Err(TrexError::Cache(msg)) => assert!(msg.starts_with("Error opening MBTiles file")),
+        _ => panic!("Cache error expected"),
+    }
+}
+
+#[test]
+fn test_grid_error() {
+    let grid = Grid::web_mercator();
+    let err = grid.normalize_tile(0, 0, 23).unwrap_err();
+    assert_eq!(err,
+               TrexError::Grid("Zoom level 23 exceeds grid maximum zoom level 22".to_string()));
+    assert_eq!(err.description(), "grid error");
+    let err = grid.normalize_tile(0, 2, 1).unwrap_err();
+    assert_eq!(err, TrexError::TileOutOfRange("Tile 1/0/2 out of range".to_string()));
+    assert_eq!(err.description(), "tile out of range");
+}
+
+#[test]
+fn test_error_conversion() {
+    assert_eq!(TrexError::UnknownTileset("osm".to_string()).to_string(),
+               "Unknown tileset 'osm'");
+
+    // String errors of `from_config`
+    let err: TrexError = "Invalid layer".to_string().into();
+    assert_eq!(err, TrexError::Config("Invalid layer".to_string()));
+    let msg: String = err.into();
+    assert_eq!(msg, "Invalid layer");
+}
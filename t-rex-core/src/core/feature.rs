@@ -4,6 +4,7 @@
 //
 
 use core::geom::GeometryType;
+use serde_json::{self, Map, Value};
 
 /// Supported feature attribute value types
 #[derive(Clone,PartialEq,Debug)]
@@ -17,8 +18,143 @@ pub enum FeatureAttrValType {
     Bool(bool),
 }
 
+impl FeatureAttrValType {
+    /// JSON representation of attribute value
+    pub fn to_json(&self) -> Value {
+        match self {
+            &FeatureAttrValType::String(ref v) => json!(v),
+            &FeatureAttrValType::Float(v) => json!(v),
+            &FeatureAttrValType::Double(v) => json!(v),
+            &FeatureAttrValType::Int(v) => json!(v),
+            &FeatureAttrValType::UInt(v) => json!(v),
+            &FeatureAttrValType::SInt(v) => json!(v),
+            &FeatureAttrValType::Bool(v) => json!(v),
+        }
+    }
+}
+
+/// Attribute type hint of layer configuration
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum FieldType {
+    String,
+    Float,
+    Double,
+    Int,
+    UInt,
+    Bool,
+}
+
+impl FieldType {
+    pub fn from_name(name: &str) -> Option<FieldType> {
+        match name {
+            "string" => Some(FieldType::String),
+            "float" => Some(FieldType::Float),
+            "double" => Some(FieldType::Double),
+            "int" => Some(FieldType::Int),
+            "uint" => Some(FieldType::UInt),
+            "bool" => Some(FieldType::Bool),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            &FieldType::String => "string",
+            &FieldType::Float => "float",
+            &FieldType::Double => "double",
+            &FieldType::Int => "int",
+            &FieldType::UInt => "uint",
+            &FieldType::Bool => "bool",
+        }
+    }
+    /// TileJSON field type (Number, Boolean or String)
+    pub fn tilejson_type(&self) -> &'static str {
+        match self {
+            &FieldType::String => "String",
+            &FieldType::Bool => "Boolean",
+            _ => "Number",
+        }
+    }
+}
+
+impl FeatureAttrValType {
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            &FeatureAttrValType::String(ref v) => v.trim().parse::<f64>().ok(),
+            &FeatureAttrValType::Float(v) => Some(v as f64),
+            &FeatureAttrValType::Double(v) => Some(v),
+            &FeatureAttrValType::Int(v) |
+            &FeatureAttrValType::SInt(v) => Some(v as f64),
+            &FeatureAttrValType::UInt(v) => Some(v as f64),
+            &FeatureAttrValType::Bool(v) => Some(if v { 1.0 } else { 0.0 }),
+        }
+    }
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            &FeatureAttrValType::Int(v) |
+            &FeatureAttrValType::SInt(v) => Some(v),
+            &FeatureAttrValType::UInt(v) if v <= i64::max_value() as u64 => Some(v as i64),
+            &FeatureAttrValType::String(ref v) if v.trim().parse::<i64>().is_ok() => {
+                v.trim().parse::<i64>().ok()
+            }
+            _ => {
+                match self.as_f64() {
+                    Some(v) if v.is_finite() && v.abs() < i64::max_value() as f64 => {
+                        Some(v.round() as i64)
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+    /// Convert value to attribute type, `None` if not convertible
+    pub fn convert(&self, field_type: FieldType) -> Option<FeatureAttrValType> {
+        match field_type {
+            FieldType::String => {
+                let value = match self {
+                    &FeatureAttrValType::String(ref v) => v.clone(),
+                    &FeatureAttrValType::Float(v) => v.to_string(),
+                    &FeatureAttrValType::Double(v) => v.to_string(),
+                    &FeatureAttrValType::Int(v) |
+                    &FeatureAttrValType::SInt(v) => v.to_string(),
+                    &FeatureAttrValType::UInt(v) => v.to_string(),
+                    &FeatureAttrValType::Bool(v) => v.to_string(),
+                };
+                Some(FeatureAttrValType::String(value))
+            }
+            FieldType::Float => self.as_f64().map(|v| FeatureAttrValType::Float(v as f32)),
+            FieldType::Double => self.as_f64().map(|v| FeatureAttrValType::Double(v)),
+            FieldType::Int => self.as_i64().map(|v| FeatureAttrValType::Int(v)),
+            FieldType::UInt => {
+                match self {
+                    &FeatureAttrValType::UInt(v) => Some(FeatureAttrValType::UInt(v)),
+                    _ => {
+                        match self.as_i64() {
+                            Some(v) if v >= 0 => Some(FeatureAttrValType::UInt(v as u64)),
+                            _ => None,
+                        }
+                    }
+                }
+            }
+            FieldType::Bool => {
+                let value = match self {
+                    &FeatureAttrValType::String(ref v) => {
+                        match v.trim().to_lowercase().as_str() {
+                            "true" | "t" | "yes" | "1" => Some(true),
+                            "false" | "f" | "no" | "0" => Some(false),
+                            _ => None,
+                        }
+                    }
+                    _ => self.as_f64().map(|v| v != 0.0),
+                };
+                value.map(|v| FeatureAttrValType::Bool(v))
+            }
+        }
+    }
+}
+
 pub trait Feature {
     fn fid(&self) -> Option<u64>;
+    /// Attribute values. NULL values are omitted, since MVT has no null value.
     fn attributes(&self) -> Vec<FeatureAttr>; //TODO: return tuples
     fn geometry(&self) -> Result<GeometryType, String>;
 }
@@ -29,6 +165,61 @@ pub struct FeatureAttr {
     pub value: FeatureAttrValType,
 }
 
+impl FeatureAttr {
+    /// Split attribute containing a JSON object into an attribute `<key>_<member>` per member.
+    /// Members of nested objects are joined with dots up to `depth` levels, deeper objects
+    /// and null values are skipped. Returns `None` if the value is not a JSON object.
+    pub fn flatten_json(&self, depth: u8) -> Option<Vec<FeatureAttr>> {
+        let json = match self.value {
+            FeatureAttrValType::String(ref v) => v,
+            _ => return None,
+        };
+        match serde_json::from_str::<Value>(json) {
+            Ok(Value::Object(ref members)) => {
+                let mut attrs = Vec::new();
+                flatten_json_members(&format!("{}_", self.key), members, depth, &mut attrs);
+                Some(attrs)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn flatten_json_members(prefix: &str,
+                        members: &Map<String, Value>,
+                        depth: u8,
+                        attrs: &mut Vec<FeatureAttr>) {
+    for (key, value) in members {
+        let name = format!("{}{}", prefix, key);
+        let value = match value {
+            &Value::Null => continue,
+            &Value::Bool(v) => FeatureAttrValType::Bool(v),
+            &Value::Number(ref n) => {
+                if let Some(v) = n.as_i64() {
+                    FeatureAttrValType::Int(v)
+                } else if let Some(v) = n.as_u64() {
+                    FeatureAttrValType::UInt(v)
+                } else {
+                    FeatureAttrValType::Double(n.as_f64().unwrap_or(0.0))
+                }
+            }
+            &Value::String(ref v) => FeatureAttrValType::String(v.clone()),
+            // Arrays have no MVT equivalent
+            &Value::Array(_) => FeatureAttrValType::String(value.to_string()),
+            &Value::Object(ref nested) => {
+                if depth > 1 {
+                    flatten_json_members(&format!("{}.", name), nested, depth - 1, attrs);
+                }
+                continue;
+            }
+        };
+        attrs.push(FeatureAttr {
+                       key: name,
+                       value: value,
+                   });
+    }
+}
+
 
 /// Basic Feature implementation
 pub struct FeatureStruct {
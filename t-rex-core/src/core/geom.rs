@@ -4,6 +4,7 @@
 //
 
 use postgis::ewkb;
+use serde_json::Value;
 
 
 // Aliases for rust-postgis geometry types
@@ -44,7 +45,117 @@ impl GeometryType {
             &GeometryType::MultiPoint(ref p) => p.points.len() == 0,
             &GeometryType::MultiLineString(ref p) => p.lines.len() == 0,
             &GeometryType::MultiPolygon(ref p) => p.polygons.len() == 0,
+            &GeometryType::GeometryCollection(ref p) => p.geometries.len() == 0,
             _ => false,
         }
     }
+    /// Split geometry collections into their members (nested collections are flattened)
+    pub fn flatten(self) -> Vec<GeometryType> {
+        match self {
+            GeometryType::GeometryCollection(collection) => {
+                collection
+                    .geometries
+                    .into_iter()
+                    .flat_map(|geom| GeometryType::from(geom).flatten())
+                    .collect()
+            }
+            geom => vec![geom],
+        }
+    }
+}
+
+fn point_coords(p: &Point) -> Value {
+    json!([p.x, p.y])
+}
+
+fn line_coords(line: &LineString) -> Value {
+    Value::Array(line.points.iter().map(point_coords).collect())
+}
+
+fn polygon_coords(poly: &Polygon) -> Value {
+    Value::Array(poly.rings.iter().map(line_coords).collect())
+}
+
+fn geojson_geometry(geomtype: &str, coordinates: Value) -> Value {
+    json!({"type": geomtype, "coordinates": coordinates})
+}
+
+fn collection_geojson(collection: &GeometryCollection) -> Value {
+    let geometries: Vec<Value> = collection
+        .geometries
+        .iter()
+        .map(|geom| match geom {
+                 &ewkb::GeometryT::Point(ref g) => geojson_geometry("Point", point_coords(g)),
+                 &ewkb::GeometryT::LineString(ref g) => {
+                     geojson_geometry("LineString", line_coords(g))
+                 }
+                 &ewkb::GeometryT::Polygon(ref g) => geojson_geometry("Polygon", polygon_coords(g)),
+                 &ewkb::GeometryT::MultiPoint(ref g) => {
+                     geojson_geometry("MultiPoint",
+                                      Value::Array(g.points.iter().map(point_coords).collect()))
+                 }
+                 &ewkb::GeometryT::MultiLineString(ref g) => {
+                     geojson_geometry("MultiLineString",
+                                      Value::Array(g.lines.iter().map(line_coords).collect()))
+                 }
+                 &ewkb::GeometryT::MultiPolygon(ref g) => {
+                     geojson_geometry("MultiPolygon",
+                                      Value::Array(g.polygons.iter().map(polygon_coords).collect()))
+                 }
+                 &ewkb::GeometryT::GeometryCollection(ref g) => collection_geojson(g),
+             })
+        .collect();
+    json!({"type": "GeometryCollection", "geometries": geometries})
+}
+
+impl GeometryType {
+    /// GeoJSON geometry object with coordinates in geometry CRS
+    pub fn to_geojson(&self) -> Value {
+        match self {
+            &GeometryType::Point(ref g) => geojson_geometry("Point", point_coords(g)),
+            &GeometryType::LineString(ref g) => geojson_geometry("LineString", line_coords(g)),
+            &GeometryType::Polygon(ref g) => geojson_geometry("Polygon", polygon_coords(g)),
+            &GeometryType::MultiPoint(ref g) => {
+                geojson_geometry("MultiPoint",
+                                 Value::Array(g.points.iter().map(point_coords).collect()))
+            }
+            &GeometryType::MultiLineString(ref g) => {
+                geojson_geometry("MultiLineString",
+                                 Value::Array(g.lines.iter().map(line_coords).collect()))
+            }
+            &GeometryType::MultiPolygon(ref g) => {
+                geojson_geometry("MultiPolygon",
+                                 Value::Array(g.polygons.iter().map(polygon_coords).collect()))
+            }
+            &GeometryType::GeometryCollection(ref g) => collection_geojson(g),
+        }
+    }
+}
+
+impl From<ewkb::GeometryT<Point>> for GeometryType {
+    fn from(geom: ewkb::GeometryT<Point>) -> Self {
+        match geom {
+            ewkb::GeometryT::Point(g) => GeometryType::Point(g),
+            ewkb::GeometryT::LineString(g) => GeometryType::LineString(g),
+            ewkb::GeometryT::Polygon(g) => GeometryType::Polygon(g),
+            ewkb::GeometryT::MultiPoint(g) => GeometryType::MultiPoint(g),
+            ewkb::GeometryT::MultiLineString(g) => GeometryType::MultiLineString(g),
+            ewkb::GeometryT::MultiPolygon(g) => GeometryType::MultiPolygon(g),
+            ewkb::GeometryT::GeometryCollection(g) => GeometryType::GeometryCollection(g),
+        }
+    }
+}
+
+impl From<GeometryType> for ewkb::GeometryT<Point> {
+    fn from(geom: GeometryType) -> Self {
+        match geom {
+            GeometryType::Point(g) => ewkb::GeometryT::Point(g),
+            GeometryType::LineString(g) => ewkb::GeometryT::LineString(g),
+            GeometryType::Polygon(g) => ewkb::GeometryT::Polygon(g),
+            GeometryType::MultiPoint(g) => ewkb::GeometryT::MultiPoint(g),
+            GeometryType::MultiLineString(g) => ewkb::GeometryT::MultiLineString(g),
+            GeometryType::MultiPolygon(g) => ewkb::GeometryT::MultiPolygon(g),
+            GeometryType::GeometryCollection(g) => ewkb::GeometryT::GeometryCollection(g),
+        }
+    }
 }
@@ -25,3 +25,33 @@ fn test_geom_creation() {
     };
     assert_eq!(p.x, 960000.0);
 }
+
+#[test]
+fn test_flatten_collection() {
+    let line = ewkb::LineString {
+        points: vec![Point::new(0.0, 0.0, None), Point::new(1.0, 1.0, None)],
+        srid: None,
+    };
+    let inner = ewkb::GeometryCollection {
+        geometries: vec![ewkb::GeometryT::LineString(line)],
+        srid: None,
+    };
+    let collection = ewkb::GeometryCollection {
+        geometries: vec![ewkb::GeometryT::Point(Point::new(1.0, 2.0, None)),
+                         ewkb::GeometryT::GeometryCollection(inner)],
+        srid: None,
+    };
+    let geoms = GeometryType::GeometryCollection(collection).flatten();
+    assert_eq!(geoms.len(), 2);
+    match geoms[0] {
+        GeometryType::Point(ref p) => assert_eq!((p.x, p.y), (1.0, 2.0)),
+        _ => panic!("Point expected"),
+    }
+    match geoms[1] {
+        GeometryType::LineString(ref l) => assert_eq!(l.points.len(), 2),
+        _ => panic!("LineString expected"),
+    }
+
+    let geoms = GeometryType::new_point(1.0, 2.0).flatten();
+    assert_eq!(geoms.len(), 1);
+}
@@ -5,9 +5,11 @@
 
 use core::Config;
 use core::config::GridCfg;
+use core::error::TrexError;
 use core::enum_serializer::EnumString;
 use serde;
 use serde::de::{Deserialize, Deserializer};
+use std::f64::consts::PI;
 use std::fmt;
 
 
@@ -81,9 +83,43 @@ impl EnumString<Unit> for Unit {
 
 enum_string_serialization!(Unit UnitVisitor);
 
+impl Unit {
+    /// Length of a grid unit in meters (degrees at the equator, see WMTS specification)
+    pub fn meters_per_unit(&self) -> f64 {
+        match *self {
+            Unit::M => 1.0,
+            Unit::DD => 6378137.0 * 2.0 * PI / 360.0,
+            Unit::Ft => 0.3048,
+        }
+    }
+}
+
+/// Tile matrix of a zoom level (OGC WMTS)
+#[derive(Serialize, Debug)]
+pub struct TileMatrix {
+    pub identifier: String,
+    pub scale_denominator: f64,
+    /// Top left corner in CRS axis order
+    pub top_left_corner: (f64, f64),
+    pub tile_width: u16,
+    pub tile_height: u16,
+    pub matrix_width: u32,
+    pub matrix_height: u32,
+}
+
+/// Tile matrix set definition for WMTS capabilities
+#[derive(Serialize, Debug)]
+pub struct TileMatrixSet {
+    pub identifier: String,
+    pub supported_crs: String,
+    pub tile_matrices: Vec<TileMatrix>,
+}
+
 // Credits: MapCache by Thomas Bonfort (http://mapserver.org/mapcache/)
 #[derive(Deserialize, Debug)]
 pub struct Grid {
+    /// Name of predefined grid
+    pub name: Option<String>,
     /// The width and height of an individual tile, in pixels.
     width: u16,
     height: u16,
@@ -106,12 +142,15 @@ pub struct Grid {
     resolutions: Vec<f64>,
     /// Grid origin
     pub origin: Origin,
+    /// Tile rows of requests are numbered in TMS scheme (origin bottom-left) instead of XYZ
+    pub tms: bool,
 }
 
 impl Grid {
     /// WGS84 grid
     pub fn wgs84() -> Grid {
         Grid {
+            name: Some("wgs84".to_string()),
             width: 256,
             height: 256,
             extent: Extent {
@@ -141,12 +180,14 @@ impl Grid {
                               1.07288360595703e-5,
                               5.36441802978516e-6],
             origin: Origin::BottomLeft,
+            tms: false,
         }
     }
 
     /// Web Mercator grid (Google maps compatible)
     pub fn web_mercator() -> Grid {
         Grid {
+            name: Some("web_mercator".to_string()),
             width: 256,
             height: 256,
             extent: Extent {
@@ -182,9 +223,99 @@ impl Grid {
                               0.0746455354347424,
                               0.0373227677173712],
             origin: Origin::BottomLeft,
+            tms: false,
+        }
+    }
+
+    /// British National Grid (EPSG:27700) as used by OS tile services
+    pub fn bng() -> Grid {
+        Grid {
+            name: Some("bng".to_string()),
+            width: 256,
+            height: 256,
+            extent: Extent {
+                minx: -238375.0,
+                miny: 0.0,
+                maxx: 900000.0,
+                maxy: 1376256.0,
+            },
+            srid: 27700,
+            units: Unit::M,
+            resolutions: vec![896.0,
+                              448.0,
+                              224.0,
+                              112.0,
+                              56.0,
+                              28.0,
+                              14.0,
+                              7.0,
+                              3.5,
+                              1.75,
+                              0.875,
+                              0.4375,
+                              0.21875,
+                              0.109375],
+            origin: Origin::TopLeft,
+            tms: false,
         }
     }
 
+    /// Swiss LV95 grid (EPSG:2056) of the swisstopo WMTS
+    pub fn lv95() -> Grid {
+        Grid {
+            name: Some("lv95".to_string()),
+            width: 256,
+            height: 256,
+            extent: Extent {
+                minx: 2420000.0,
+                miny: 1030000.0,
+                maxx: 2900000.0,
+                maxy: 1350000.0,
+            },
+            srid: 2056,
+            units: Unit::M,
+            resolutions: vec![4000.0,
+                              3750.0,
+                              3500.0,
+                              3250.0,
+                              3000.0,
+                              2750.0,
+                              2500.0,
+                              2250.0,
+                              2000.0,
+                              1750.0,
+                              1500.0,
+                              1250.0,
+                              1000.0,
+                              750.0,
+                              650.0,
+                              500.0,
+                              250.0,
+                              100.0,
+                              50.0,
+                              20.0,
+                              10.0,
+                              5.0,
+                              2.5,
+                              2.0,
+                              1.5,
+                              1.0,
+                              0.5,
+                              0.25,
+                              0.1],
+            origin: Origin::TopLeft,
+            tms: false,
+        }
+    }
+
+    /// Tile width in pixels
+    pub fn tile_size(&self) -> u16 {
+        self.width
+    }
+    /// Spatial reference system of grid extent and tile envelopes
+    pub fn srid(&self) -> i32 {
+        self.srid
+    }
     pub fn nlevels(&self) -> u8 {
         self.resolutions.len() as u8
     }
@@ -194,12 +325,13 @@ impl Grid {
     pub fn pixel_width(&self, zoom: u8) -> f64 {
         self.resolutions[zoom as usize] //TODO: assumes grid unit 'm'
     }
+    /// OGC scale denominator of zoom level
     pub fn scale_denominator(&self, zoom: u8) -> f64 {
         let pixel_screen_width = 0.00028;
         // https://github.com/mapnik/mapnik/wiki/ScaleAndPpi#scale-denominator
         // Mapnik calculates it's default at about 90.7 PPI, which originates from an assumed standard pixel size
         // of 0.28 millimeters as defined by the OGC (Open Geospatial Consortium) SLD (Styled Layer Descriptor) Specification.
-        self.pixel_width(zoom) / pixel_screen_width
+        self.pixel_width(zoom) * self.units.meters_per_unit() / pixel_screen_width
     }
     /// Extent of a given tile in the grid given its x, y, and z in TMS adressing scheme
     pub fn tile_extent(&self, xtile: u32, ytile: u32, zoom: u8) -> Extent {
@@ -226,15 +358,24 @@ impl Grid {
             }
         }
     }
-    /// reverse y tile for XYZ adressing scheme
+    /// Flip tile row between numbering from top and from bottom
+    pub fn flip_y(&self, ytile: u32, zoom: u8) -> u32 {
+        let (_, maxy) = self.level_limit(zoom);
+        maxy.saturating_sub(ytile).saturating_sub(1) // y = maxy-ytile-1
+    }
+    /// Grid tile row of a tile row in XYZ adressing scheme (origin top-left)
     pub fn ytile_from_xyz(&self, ytile: u32, zoom: u8) -> u32 {
-        let res = self.resolutions[zoom as usize];
-        let unitheight = self.height as f64 * res;
-        // TODO: cache maxy for each resolution
-        let maxy = ((self.extent.maxy - self.extent.minx - 0.01 * unitheight) / unitheight)
-            .ceil() as u32;
-        let y = maxy.saturating_sub(ytile).saturating_sub(1); // y = maxy-ytile-1
-        y
+        match self.origin {
+            Origin::BottomLeft => self.flip_y(ytile, zoom),
+            Origin::TopLeft => ytile,
+        }
+    }
+    /// Grid tile row of a tile row in TMS adressing scheme (origin bottom-left)
+    pub fn ytile_from_tms(&self, ytile: u32, zoom: u8) -> u32 {
+        match self.origin {
+            Origin::BottomLeft => ytile,
+            Origin::TopLeft => self.flip_y(ytile, zoom),
+        }
     }
     /// Extent of a given tile in XYZ adressing scheme
     pub fn tile_extent_xyz(&self, xtile: u32, ytile: u32, zoom: u8) -> Extent {
@@ -249,10 +390,88 @@ impl Grid {
 
         let maxy = ((self.extent.maxy - self.extent.miny - 0.01 * unitheight) / unitheight)
             .ceil() as u32;
-        let maxx = ((self.extent.maxx - self.extent.miny - 0.01 * unitwidth) / unitwidth)
+        let maxx = ((self.extent.maxx - self.extent.minx - 0.01 * unitwidth) / unitwidth)
             .ceil() as u32;
         (maxx, maxy)
     }
+    /// Check whether tile is within the tile matrix of the zoom level
+    pub fn tile_in_range(&self, xtile: u32, ytile: u32, zoom: u8) -> bool {
+        if zoom > self.maxzoom() {
+            return false;
+        }
+        let (maxx, maxy) = self.level_limit(zoom);
+        xtile < maxx && ytile < maxy
+    }
+    /// Grid covering all longitudes, where tile columns wrap around at the antimeridian
+    pub fn is_global(&self) -> bool {
+        let width = self.extent.maxx - self.extent.minx;
+        match self.srid {
+            3857 | 900913 => (width - 2.0 * 20037508.3427892480).abs() < 1.0,
+            4326 => (width - 360.0).abs() < 0.000001,
+            _ => false,
+        }
+    }
+    /// Tile within the tile matrix of the zoom level, with columns of global grids wrapped
+    /// around. Zoom levels without grid resolution are grid errors.
+    pub fn normalize_tile(&self,
+                          xtile: u32,
+                          ytile: u32,
+                          zoom: u8)
+                          -> Result<(u32, u32), TrexError> {
+        if zoom > self.maxzoom() {
+            return Err(TrexError::Grid(format!("Zoom level {} exceeds grid maximum zoom level {}",
+                                               zoom,
+                                               self.maxzoom())));
+        }
+        let (maxx, maxy) = self.level_limit(zoom);
+        let out_of_range = || {
+            TrexError::TileOutOfRange(format!("Tile {}/{}/{} out of range", zoom, xtile, ytile))
+        };
+        if ytile >= maxy {
+            return Err(out_of_range());
+        }
+        if xtile < maxx {
+            Ok((xtile, ytile))
+        } else if self.is_global() && maxx > 0 {
+            Ok((xtile % maxx, ytile))
+        } else {
+            Err(out_of_range())
+        }
+    }
+    /// Tile matrix set of all zoom levels
+    pub fn tile_matrix_set(&self) -> TileMatrixSet {
+        let tile_matrices = (0..self.nlevels())
+            .map(|zoom| {
+                let (matrix_width, matrix_height) = self.level_limit(zoom);
+                let res = self.resolutions[zoom as usize];
+                let top = match self.origin {
+                    Origin::TopLeft => self.extent.maxy,
+                    Origin::BottomLeft => {
+                        self.extent.miny + matrix_height as f64 * self.height as f64 * res
+                    }
+                };
+                // Geographic CRS have latitude first
+                let top_left_corner = match self.units {
+                    Unit::DD => (top, self.extent.minx),
+                    _ => (self.extent.minx, top),
+                };
+                TileMatrix {
+                    identifier: zoom.to_string(),
+                    scale_denominator: self.scale_denominator(zoom),
+                    top_left_corner: top_left_corner,
+                    tile_width: self.width,
+                    tile_height: self.height,
+                    matrix_width: matrix_width,
+                    matrix_height: matrix_height,
+                }
+            })
+            .collect();
+        TileMatrixSet {
+            identifier: self.name.clone().unwrap_or(format!("EPSG:{}", self.srid)),
+            supported_crs: format!("urn:ogc:def:crs:EPSG::{}", self.srid),
+            tile_matrices: tile_matrices,
+        }
+    }
     /// Tile index limits covering extent
     pub fn tile_limits(&self, extent: Extent, tolerance: i32) -> Vec<ExtentInt> {
         // Based on mapcache_grid_compute_limits
@@ -311,28 +530,89 @@ impl Grid {
             })
             .collect()
     }
+    /// Inclusive tile range (minx, miny, maxx, maxy) covering extent in XYZ adressing scheme
+    pub fn extent_to_tile_range(&self, extent: &Extent, zoom: u8) -> (u32, u32, u32, u32) {
+        const EPSILON: f64 = 0.0000001;
+        let res = self.resolutions[zoom as usize];
+        let unitheight = self.height as f64 * res;
+        let unitwidth = self.width as f64 * res;
+        let (level_maxx, level_maxy) = self.level_limit(zoom);
+        // clamp to valid tile matrix bounds
+        let clamp = |tile: f64, limit: u32| if tile < 0.0 {
+            0
+        } else if tile >= limit as f64 {
+            limit.saturating_sub(1)
+        } else {
+            tile as u32
+        };
+
+        let minx = ((extent.minx - self.extent.minx) / unitwidth + EPSILON).floor();
+        let maxx = ((extent.maxx - self.extent.minx) / unitwidth - EPSILON).ceil() - 1.0;
+        // Rows counted from top
+        let (miny, maxy) = match self.origin {
+            Origin::BottomLeft => {
+                let miny = ((extent.miny - self.extent.miny) / unitheight + EPSILON).floor();
+                let maxy = ((extent.maxy - self.extent.miny) / unitheight - EPSILON).ceil() - 1.0;
+                // reverse y: max row becomes min row
+                (self.flip_y(clamp(maxy, level_maxy), zoom),
+                 self.flip_y(clamp(miny, level_maxy), zoom))
+            }
+            Origin::TopLeft => {
+                let miny = ((self.extent.maxy - extent.maxy) / unitheight + EPSILON).floor();
+                let maxy = ((self.extent.maxy - extent.miny) / unitheight - EPSILON).ceil() - 1.0;
+                (clamp(miny, level_maxy), clamp(maxy, level_maxy))
+            }
+        };
+        (clamp(minx, level_maxx), miny, clamp(maxx, level_maxx), maxy)
+    }
 }
 
 impl<'a> Config<'a, Grid, GridCfg> for Grid {
     fn from_config(grid_cfg: &GridCfg) -> Result<Self, String> {
         match grid_cfg.predefined {
             Some(ref gridname) => {
-                match gridname.as_str() {
-                    "wgs84" => Ok(Grid::wgs84()),
-                    "web_mercator" => Ok(Grid::web_mercator()),
-                    _ => Err(format!("Unkown grid '{}'", gridname)),
+                let mut grid = match gridname.as_str() {
+                    "wgs84" => Grid::wgs84(),
+                    "web_mercator" => Grid::web_mercator(),
+                    "bng" => Grid::bng(),
+                    "lv95" => Grid::lv95(),
+                    _ => return Err(format!("Unkown grid '{}'", gridname)),
+                };
+                if let Some(tile_size) = grid_cfg.tile_size {
+                    // Keep the tile matrix of each level covering the same extent
+                    let factor = grid.width as f64 / tile_size as f64;
+                    grid.resolutions = grid.resolutions.iter().map(|res| res * factor).collect();
+                    grid.width = tile_size;
+                    grid.height = tile_size;
+                }
+                grid.tms = grid_cfg.tms.unwrap_or(false);
+                // Explicit SRID, e.g. 900913 for legacy Web Mercator data
+                if let Some(srid) = grid_cfg.srid {
+                    grid.srid = srid;
                 }
+                Ok(grid)
             }
             None => {
+                let resolutions = grid_cfg.resolutions.clone();
+                if resolutions.is_empty() {
+                    return Err("grid.resolutions missing".to_string());
+                }
+                if resolutions.windows(2).any(|r| r[1] >= r[0]) {
+                    return Err("grid.resolutions must be ordered from largest to smallest"
+                                   .to_string());
+                }
+                let units = grid_cfg.units.clone().ok_or("grid.units missing")?;
+                let origin = grid_cfg.origin.clone().ok_or("grid.origin missing")?;
                 Ok(Grid {
-                       width: grid_cfg.width.expect("grid.width missing"),
-                       height: grid_cfg.height.expect("grid.height missing"),
-                       extent: grid_cfg.extent.clone().expect("grid.extent missing"),
-                       srid: grid_cfg.srid.expect("grid.srid missing"),
-                       units: Unit::from_str(&grid_cfg.units.clone().expect("grid.units missing"))?,
-                       resolutions: grid_cfg.resolutions.clone(),
-                       origin:
-                           Origin::from_str(&grid_cfg.origin.clone().expect("grid.origin missing"))?,
+                       name: None,
+                       width: grid_cfg.width.or(grid_cfg.tile_size).ok_or("grid.width missing")?,
+                       height: grid_cfg.height.or(grid_cfg.tile_size).ok_or("grid.height missing")?,
+                       extent: grid_cfg.extent.clone().ok_or("grid.extent missing")?,
+                       srid: grid_cfg.srid.ok_or("grid.srid missing")?,
+                       units: Unit::from_str(&units)?,
+                       resolutions: resolutions,
+                       origin: Origin::from_str(&origin)?,
+                       tms: grid_cfg.tms.unwrap_or(false),
                    })
             }
         }
@@ -340,8 +620,12 @@ impl<'a> Config<'a, Grid, GridCfg> for Grid {
     fn gen_config() -> String {
         let toml = r#"
 [grid]
-# Predefined grids: web_mercator, wgs84
+# Predefined grids: web_mercator, wgs84, bng, lv95
 predefined = "web_mercator"
+# Tile size in pixels (default 256)
+#tile_size = 512
+# Tile requests in TMS scheme (default XYZ)
+#tms = false
 "#;
         toml.to_string()
     }
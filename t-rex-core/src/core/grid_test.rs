@@ -6,6 +6,7 @@
 use toml;
 use core::Config;
 use core::config::GridCfg;
+use core::error::TrexError;
 use core::grid::{Grid, Origin, Extent, ExtentInt};
 
 
@@ -99,16 +100,87 @@ fn test_bbox() {
                });
 }
 
+#[test]
+fn test_wgs84_tile_extent() {
+    // EPSG:4326 tile matrix with 2x1 tiles at zoom 0
+    let grid = Grid::wgs84();
+    assert_eq!(grid.level_limit(0), (2, 1));
+    assert_eq!(grid.level_limit(1), (4, 2));
+    assert!(grid.tile_in_range(1, 0, 0));
+    assert!(!grid.tile_in_range(2, 0, 0));
+    assert!(!grid.tile_in_range(0, 1, 0));
+
+    assert_eq!(grid.tile_extent(1, 0, 0),
+               Extent {
+                   minx: 0.0,
+                   miny: -90.0,
+                   maxx: 180.0,
+                   maxy: 90.0,
+               });
+    // Tiles of 90 degrees at zoom 1, origin bottom left
+    assert_eq!(grid.tile_extent(0, 0, 1),
+               Extent {
+                   minx: -180.0,
+                   miny: -90.0,
+                   maxx: -90.0,
+                   maxy: 0.0,
+               });
+    assert_eq!(grid.tile_extent(3, 1, 1),
+               Extent {
+                   minx: 90.0,
+                   miny: 0.0,
+                   maxx: 180.0,
+                   maxy: 90.0,
+               });
+    // XYZ tile rows are numbered from the top
+    assert_eq!(grid.tile_extent_xyz(1, 0, 0), grid.tile_extent(1, 0, 0));
+    assert_eq!(grid.tile_extent_xyz(2, 0, 1),
+               Extent {
+                   minx: 0.0,
+                   miny: 0.0,
+                   maxx: 90.0,
+                   maxy: 90.0,
+               });
+    assert_eq!(grid.tile_extent_xyz(2, 1, 1), grid.tile_extent(2, 0, 1));
+
+    let limits = grid.tile_limits(grid.extent.clone(), 0);
+    assert_eq!(limits[0],
+               ExtentInt {
+                   minx: 0,
+                   miny: 0,
+                   maxx: 2,
+                   maxy: 1,
+               });
+    assert_eq!(limits[1],
+               ExtentInt {
+                   minx: 0,
+                   miny: 0,
+                   maxx: 4,
+                   maxy: 2,
+               });
+}
+
 #[test]
 fn test_grid_calculations() {
     let grid = Grid::web_mercator();
 
     assert_eq!(grid.pixel_width(10), 152.8740565703525);
     assert_eq!(grid.scale_denominator(10), 545978.7734655447);
+    assert_eq!(grid.scale_denominator(0).round(), 559082264.0);
+
+    // Scale of degree grids is calculated at the equator
+    let wgs84 = Grid::wgs84();
+    assert_eq!(wgs84.scale_denominator(0).round(), 279541132.0);
 
     assert_eq!(grid.level_limit(0), (1, 1));
     assert_eq!(grid.level_limit(10), (1024, 1024));
 
+    assert!(grid.tile_in_range(0, 0, 0));
+    assert!(!grid.tile_in_range(1, 0, 0));
+    assert!(grid.tile_in_range(1023, 1023, 10));
+    assert!(!grid.tile_in_range(0, 1024, 10));
+    assert!(!grid.tile_in_range(0, 0, 23));
+
     let limits = grid.tile_limits(grid.tile_extent(0, 0, 0), 0);
     assert_eq!(limits[0],
                ExtentInt {
@@ -166,6 +238,40 @@ fn test_grid_calculations() {
                });
 }
 
+#[test]
+fn test_extent_to_tile_range() {
+    let grid = Grid::web_mercator();
+
+    let extent = grid.tile_extent(0, 0, 0);
+    assert_eq!(grid.extent_to_tile_range(&extent, 0), (0, 0, 0, 0));
+    assert_eq!(grid.extent_to_tile_range(&extent, 1), (0, 0, 1, 1));
+    assert_eq!(grid.extent_to_tile_range(&extent, 10), (0, 0, 1023, 1023));
+
+    // Inverse of tile_extent_xyz
+    let extent = grid.tile_extent_xyz(133, 90, 8);
+    assert_eq!(grid.extent_to_tile_range(&extent, 8), (133, 90, 133, 90));
+    let extent = grid.tile_extent_xyz(486, 332, 10);
+    assert_eq!(grid.extent_to_tile_range(&extent, 10), (486, 332, 486, 332));
+    assert_eq!(grid.extent_to_tile_range(&extent, 9), (243, 166, 243, 166));
+
+    // Antimeridian edge
+    let extent = Extent {
+        minx: 19000000.0,
+        miny: -1000.0,
+        maxx: 20037508.342789248,
+        maxy: 1000.0,
+    };
+    assert_eq!(grid.extent_to_tile_range(&extent, 2), (3, 1, 3, 2));
+    let extent = Extent {
+        minx: -20100000.0,
+        miny: -1000.0,
+        maxx: 20100000.0,
+        maxy: 1000.0,
+    };
+    assert_eq!(grid.extent_to_tile_range(&extent, 2), (0, 1, 3, 2));
+    assert_eq!(grid.extent_to_tile_range(&extent, 0), (0, 0, 0, 0));
+}
+
 #[test]
 fn test_grid_from_config() {
     use core::parse_config;
@@ -204,6 +310,7 @@ fn test_grid_from_config() {
                    maxy: 1350000.0,
                });
     assert_eq!(grid.origin, Origin::TopLeft);
+    assert_eq!(grid.tile_matrix_set().identifier, "EPSG:2056");
 
     let extent = grid.tile_extent(10, 4, 17); // lake of Zurich
     assert_eq!(extent,
@@ -214,16 +321,209 @@ fn test_grid_from_config() {
                    maxy: 1247600.,
                });
     //BBOX ZH: (2669255.48 1223902.28, 2716899.60125 1283304.23625)
+    // XYZ rows of grids with top left origin are grid rows
     let extent = grid.tile_extent_xyz(10, 4, 17);
-    assert_eq!(extent,
+    assert_eq!(extent, grid.tile_extent(10, 4, 17));
+    assert!(!grid.tms);
+}
+
+#[test]
+fn test_grid_srid() {
+    use core::parse_config;
+
+    assert_eq!(Grid::web_mercator().srid(), 3857);
+    assert_eq!(Grid::wgs84().srid(), 4326);
+
+    let toml = r#"
+        #[grid]
+        predefined = "web_mercator"
+        "#;
+    let config: GridCfg = parse_config(toml.to_string(), "").unwrap();
+    assert_eq!(Grid::from_config(&config).unwrap().srid(), 3857);
+
+    // Explicit SRID overrides SRID of predefined grid
+    let toml = r#"
+        #[grid]
+        predefined = "web_mercator"
+        srid = 900913
+        "#;
+    let config: GridCfg = parse_config(toml.to_string(), "").unwrap();
+    let grid = Grid::from_config(&config).unwrap();
+    assert_eq!(grid.srid(), 900913);
+    assert_eq!(grid.extent, Grid::web_mercator().extent);
+
+    let toml = r#"
+        #[grid]
+        width = 256
+        height = 256
+        extent = { minx = 2420000.0, miny = 1030000.0, maxx = 2900000.0, maxy = 1350000.0 }
+        srid = 2056
+        units = "M"
+        resolutions = [4000.0,3750.0,3500.0]
+        origin = "TopLeft"
+        "#;
+    let config: GridCfg = parse_config(toml.to_string(), "").unwrap();
+    assert_eq!(Grid::from_config(&config).unwrap().srid(), 2056);
+}
+
+#[test]
+fn test_tile_size() {
+    use core::parse_config;
+
+    let toml = r#"
+        #[grid]
+        predefined = "web_mercator"
+        tile_size = 512
+        "#;
+    let config: GridCfg = parse_config(toml.to_string(), "").unwrap();
+    let grid512 = Grid::from_config(&config).unwrap();
+    assert_eq!(grid512.tile_size(), 512);
+    let grid256 = Grid::web_mercator();
+    assert_eq!(grid256.tile_size(), 256);
+
+    // Zoom level 0 is a single tile covering the world
+    let extent = grid512.tile_extent(0, 0, 0);
+    assert!((extent.minx - grid512.extent.minx).abs() < 0.001);
+    assert!((extent.miny - grid512.extent.miny).abs() < 0.001);
+    assert!((extent.maxx - grid512.extent.maxx).abs() < 0.001);
+    assert!((extent.maxy - grid512.extent.maxy).abs() < 0.001);
+    assert_eq!(grid512.level_limit(0), (1, 1));
+
+    let extent = grid256.tile_extent(0, 0, 0);
+    let limits256 = grid256.tile_limits(extent.clone(), 0);
+    let limits512 = grid512.tile_limits(extent, 0);
+    for zoom in 0..grid256.nlevels() as usize {
+        assert_eq!(limits512[zoom], limits256[zoom]);
+        assert_eq!(grid512.tile_extent(3, 5, zoom as u8),
+                   grid256.tile_extent(3, 5, zoom as u8));
+    }
+    assert_eq!(grid512.level_limit(10), (1024, 1024));
+    assert_eq!(grid512.pixel_width(10), grid256.pixel_width(10) / 2.0);
+}
+
+#[test]
+fn test_bng_grid() {
+    use core::parse_config;
+
+    let toml = r#"
+        #[grid]
+        predefined = "bng"
+        "#;
+    let config: GridCfg = parse_config(toml.to_string(), "").unwrap();
+    let grid = Grid::from_config(&config).unwrap();
+    assert_eq!(grid.srid, 27700);
+    assert_eq!(grid.origin, Origin::TopLeft);
+    assert_eq!(grid.maxzoom(), 13);
+
+    assert_eq!(grid.level_limit(0), (5, 6));
+    assert!(grid.tile_in_range(4, 5, 0));
+    assert!(!grid.tile_in_range(5, 0, 0));
+
+    assert_eq!(grid.tile_extent(0, 0, 0),
                Extent {
-                   minx: 2676000.,
-                   miny: -109951160275600.,
-                   maxx: 2701600.,
-                   maxy: -109951160250000.,
+                   minx: -238375.0,
+                   miny: 1146880.0,
+                   maxx: -8999.0,
+                   maxy: 1376256.0,
+               });
+    assert_eq!(grid.tile_extent(130, 700, 7),
+               Extent {
+                   minx: -5415.0,
+                   miny: 120064.0,
+                   maxx: -3623.0,
+                   maxy: 121856.0,
+               });
+}
+
+#[test]
+fn test_lv95_grid() {
+    use core::parse_config;
+
+    let toml = r#"
+        #[grid]
+        predefined = "lv95"
+        "#;
+    let config: GridCfg = parse_config(toml.to_string(), "").unwrap();
+    let grid = Grid::from_config(&config).unwrap();
+    assert_eq!(grid.srid, 2056);
+    assert_eq!(grid.maxzoom(), 28);
+    assert_eq!(grid.pixel_width(17), 100.0);
+
+    assert_eq!(grid.level_limit(17), (19, 13));
+    assert_eq!(grid.tile_extent(5, 3, 17),
+               Extent {
+                   minx: 2548000.0,
+                   miny: 1247600.0,
+                   maxx: 2573600.0,
+                   maxy: 1273200.0,
                });
 }
 
+#[test]
+fn test_tile_matrix_set() {
+    let tms = Grid::web_mercator().tile_matrix_set();
+    assert_eq!(tms.identifier, "web_mercator");
+    assert_eq!(tms.supported_crs, "urn:ogc:def:crs:EPSG::3857");
+    assert_eq!(tms.tile_matrices.len(), 23);
+    let matrix = &tms.tile_matrices[3];
+    assert_eq!(matrix.identifier, "3");
+    assert_eq!(matrix.scale_denominator.round(), 69885283.0);
+    assert_eq!(matrix.top_left_corner.0, -20037508.342789248);
+    assert!((matrix.top_left_corner.1 - 20037508.342789248).abs() < 0.001);
+    assert_eq!((matrix.tile_width, matrix.tile_height), (256, 256));
+    assert_eq!((matrix.matrix_width, matrix.matrix_height), (8, 8));
+
+    // Latitude first
+    let tms = Grid::wgs84().tile_matrix_set();
+    assert_eq!(tms.tile_matrices[0].top_left_corner, (90.0, -180.0));
+    assert_eq!((tms.tile_matrices[0].matrix_width, tms.tile_matrices[0].matrix_height),
+               (2, 1));
+
+    // Top left origin
+    let tms = Grid::lv95().tile_matrix_set();
+    assert_eq!(tms.tile_matrices[17].top_left_corner, (2420000.0, 1350000.0));
+    assert_eq!((tms.tile_matrices[17].matrix_width, tms.tile_matrices[17].matrix_height),
+               (19, 13));
+}
+
+#[test]
+fn test_grid_config_errors() {
+    use core::parse_config;
+
+    let grid_from_config = |toml: &str| {
+        let config: GridCfg = parse_config(toml.to_string(), "").unwrap();
+        Grid::from_config(&config)
+    };
+    let base = r#"
+        width = 256
+        height = 256
+        extent = { minx = 2420000.0, miny = 1030000.0, maxx = 2900000.0, maxy = 1350000.0 }
+        srid = 2056
+        units = "M"
+        origin = "TopLeft"
+        "#;
+
+    assert_eq!(grid_from_config(base).err(),
+               Some("grid.resolutions missing".to_string()));
+
+    let toml = format!("{}resolutions = [4000.0,3750.0,3750.0,3500.0]", base);
+    assert_eq!(grid_from_config(&toml).err(),
+               Some("grid.resolutions must be ordered from largest to smallest".to_string()));
+
+    let toml = format!("{}resolutions = [4000.0,3750.0,3500.0]", base);
+    assert_eq!(grid_from_config(&toml).unwrap().nlevels(), 3);
+
+    let toml = r#"
+        width = 256
+        height = 256
+        units = "M"
+        origin = "TopLeft"
+        resolutions = [4000.0,3750.0,3500.0]
+        "#;
+    assert_eq!(grid_from_config(toml).err(),
+               Some("grid.extent missing".to_string()));
+}
+
 
 mod web_mercator {
 
@@ -342,3 +642,100 @@ mod web_mercator {
     }
 
 }
+
+#[test]
+fn test_grid_origin() {
+    use core::parse_config;
+
+    let toml = r#"
+        #[grid]
+        width = 256
+        height = 256
+        extent = { minx = 0.0, miny = 0.0, maxx = 1024.0, maxy = 1024.0 }
+        srid = 2056
+        units = "M"
+        resolutions = [4.0,2.0,1.0]
+        origin = "BottomLeft"
+        "#;
+    let config: GridCfg = parse_config(toml.to_string(), "").unwrap();
+    let bottom_left = Grid::from_config(&config).unwrap();
+    let config: GridCfg = parse_config(toml.replace("BottomLeft", "TopLeft"), "").unwrap();
+    let top_left = Grid::from_config(&config).unwrap();
+
+    // Same tile rows are vertically mirrored
+    for ytile in 0..4 {
+        let bottom = bottom_left.tile_extent(1, ytile, 2);
+        let top = top_left.tile_extent(1, ytile, 2);
+        assert_eq!((top.minx, top.maxx), (bottom.minx, bottom.maxx));
+        assert_eq!((top.miny, top.maxy), (1024.0 - bottom.maxy, 1024.0 - bottom.miny));
+    }
+    assert_eq!(top_left.tile_extent(1, 0, 2),
+               Extent {
+                   minx: 256.0,
+                   miny: 768.0,
+                   maxx: 512.0,
+                   maxy: 1024.0,
+               });
+    // XYZ requests address the same tiles independent of the grid origin
+    assert_eq!(top_left.tile_extent_xyz(1, 0, 2),
+               bottom_left.tile_extent_xyz(1, 0, 2));
+}
+
+#[test]
+fn test_flip_y() {
+    use core::parse_config;
+
+    let grid = Grid::web_mercator();
+    assert_eq!(grid.flip_y(0, 0), 0);
+    assert_eq!(grid.flip_y(0, 1), 1);
+    assert_eq!(grid.flip_y(90, 8), 165);
+    assert_eq!(grid.flip_y(grid.flip_y(90, 8), 8), 90);
+
+    // XYZ (x,y,z) is TMS (x,flipped y,z)
+    assert_eq!(grid.ytile_from_xyz(90, 8), 165);
+    assert_eq!(grid.ytile_from_tms(165, 8), 165);
+    assert_eq!(grid.tile_extent_xyz(133, 90, 8), grid.tile_extent(133, 165, 8));
+
+    // Top left origin
+    let grid = Grid::lv95();
+    assert_eq!(grid.level_limit(17), (19, 13));
+    assert_eq!(grid.ytile_from_xyz(3, 17), 3);
+    assert_eq!(grid.ytile_from_tms(3, 17), 9);
+    assert_eq!(grid.extent_to_tile_range(&grid.tile_extent(5, 3, 17), 17),
+               (5, 3, 5, 3));
+
+    let toml = r#"
+        #[grid]
+        predefined = "web_mercator"
+        tms = true
+        "#;
+    let config: GridCfg = parse_config(toml.to_string(), "").unwrap();
+    let grid = Grid::from_config(&config).unwrap();
+    assert!(grid.tms);
+}
+
+#[test]
+fn test_normalize_tile() {
+    let grid = Grid::web_mercator();
+    assert!(grid.is_global());
+    assert_eq!(grid.normalize_tile(1, 1, 1), Ok((1, 1)));
+    assert_eq!(grid.normalize_tile(1, 2, 1),
+               Err(TrexError::TileOutOfRange("Tile 1/1/2 out of range".to_string())));
+    assert_eq!(grid.normalize_tile(0, 0, 23),
+               Err(TrexError::Grid("Zoom level 23 exceeds grid maximum zoom level 22".to_string())));
+    // Columns wrap around the antimeridian
+    assert_eq!(grid.normalize_tile(2, 1, 1), Ok((0, 1)));
+    assert_eq!(grid.normalize_tile(5, 0, 0), Ok((0, 0)));
+
+    let grid = Grid::wgs84();
+    assert!(grid.is_global());
+    assert_eq!(grid.normalize_tile(2, 0, 0), Ok((0, 0)));
+    assert!(grid.normalize_tile(0, 1, 0).is_err());
+
+    // No wraparound for regional grids
+    let grid = Grid::lv95();
+    assert!(!grid.is_global());
+    assert_eq!(grid.normalize_tile(18, 12, 17), Ok((18, 12)));
+    assert_eq!(grid.normalize_tile(19, 0, 17),
+               Err(TrexError::TileOutOfRange("Tile 17/19/0 out of range".to_string())));
+}
@@ -4,36 +4,85 @@
 //
 
 use core::Config;
-use core::config::LayerCfg;
+use core::config::{LayerCfg, QueryLimitCfg, FieldsCfg, BufferCfg};
+use core::feature::FieldType;
+use mvt::geom_encoder::SimplifyAlgorithm;
 use service::glstyle_converter::toml_style_to_gljson;
-use std::collections::HashMap;
-use datasource::PostgisInput;
+use std::collections::{BTreeMap, HashMap};
+use datasource::Datasource;
 
 
-#[derive(Debug)]
+/// Default upper feature count limit of layer queries
+pub const DEFAULT_MAX_FEATURES: u32 = 100000;
+
+#[derive(Clone, Debug)]
 pub struct LayerQuery {
     pub minzoom: Option<u8>,
     pub maxzoom: Option<u8>,
     pub sql: Option<String>,
 }
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub struct Layer {
     pub name: String,
+    /// Datasource name (default: first datasource)
+    pub datasource: Option<String>,
     pub geometry_field: Option<String>,
     pub geometry_type: Option<String>,
     /// Spatial reference system (PostGIS SRID)
     pub srid: Option<i32>,
     pub fid_field: Option<String>,
+    /// Attribute columns included as feature properties (default: all)
+    pub fields: Option<Vec<String>>,
+    /// Attribute types overriding the types reported by the datasource
+    pub field_types: BTreeMap<String, FieldType>,
+    /// JSON columns with members encoded as separate attributes
+    pub flatten_json: Vec<String>,
+    /// Levels of nested JSON objects included in flattened attributes (default 1)
+    pub json_flatten_depth: Option<u8>,
     // Input for derived queries
     pub table_name: Option<String>,
     pub query_limit: Option<u32>,
+    /// Feature count limits from zoom level upwards, overriding query_limit
+    pub query_limits: BTreeMap<u8, u32>,
+    /// Upper feature count limit, capping query_limit (service wide setting)
+    pub max_features: Option<u32>,
+    /// SQL ORDER BY expression defining the feature order in tiles
+    pub order_by: Option<String>,
+    /// SQL condition selecting the features of the table
+    pub filter: Option<String>,
+    /// Aggregate features to cluster centroids with a `count` attribute, if a tile
+    /// would contain more features
+    pub cluster_threshold: Option<u32>,
+    /// Cluster grid cell size in pixels (default 32)
+    pub cluster_size: Option<u32>,
+    /// Zoom level range of layer (default: range of queries)
+    pub minzoom: Option<u8>,
+    pub maxzoom: Option<u8>,
+    /// Map scale range of layer, in addition to zoom level range
+    pub minscaledenominator: Option<f64>,
+    pub maxscaledenominator: Option<f64>,
     // Explicit queries
     pub query: Vec<LayerQuery>,
+    /// Replace geometries with a point on their surface, e.g. as label anchor of polygons
+    pub label_point: Option<bool>,
     /// Simplify geometry (lines and polygons)
     pub simplify: Option<bool>,
-    /// Tile buffer size in pixels
+    /// Simplification algorithm (default: Douglas–Peucker)
+    pub simplify_algorithm: Option<SimplifyAlgorithm>,
+    /// Simplification tolerance in tile coordinate units (default 1),
+    /// a minimal area in square tile units for Visvalingam–Whyatt
+    pub tolerance: Option<f64>,
+    /// Tile buffer size in pixels, expanding the query envelope and the clipping rectangle
     pub buffer_size: Option<u32>,
+    /// Clip geometries to tile extent expanded by clip_buffer (in tile coordinate units, default 0).
+    /// Overridden by `buffer_size`.
+    pub clip_buffer: Option<u32>,
+    /// Intersect geometries with the buffered tile extent, with coordinates snapped to the tile grid
+    /// (PostGIS default: true for layers with `buffer_size`). `false` disables clipping.
+    pub intersect: Option<bool>,
+    /// Snap tile coordinates to multiples of precision (in tile coordinate units)
+    pub precision: Option<u32>,
     // Inline style
     pub style: Option<String>,
 }
@@ -55,26 +104,115 @@ impl Layer {
         }
     }
     pub fn minzoom(&self) -> u8 {
-        self.query
-            .iter()
-            .map(|q| q.minzoom())
-            .min()
-            .unwrap_or(0)
+        self.minzoom
+            .unwrap_or(self.query
+                           .iter()
+                           .map(|q| q.minzoom())
+                           .min()
+                           .unwrap_or(0))
     }
     pub fn maxzoom(&self) -> u8 {
-        self.query
+        self.maxzoom
+            .unwrap_or(self.query
+                           .iter()
+                           .map(|q| q.maxzoom())
+                           .max()
+                           .unwrap_or(22))
+    }
+    /// Set `buffer_size` to the default of the geometry type, unless configured
+    pub fn set_default_buffer(&mut self, defaults: &BufferCfg) {
+        if self.buffer_size.is_some() {
+            return;
+        }
+        self.buffer_size = match self.geometry_type.as_ref().map(|t| t.as_str()) {
+            Some("POINT") | Some("MULTIPOINT") => defaults.point,
+            Some("LINESTRING") | Some("MULTILINESTRING") => defaults.line,
+            Some("POLYGON") | Some("MULTIPOLYGON") => defaults.polygon,
+            _ => None,
+        };
+    }
+    /// Buffer of the clipping rectangle in tile coordinate units, if geometries are clipped.
+    /// `tile_size` is the tile extent in tile coordinate units, `grid_tile_size` in pixels.
+    pub fn clip_rect_buffer(&self, tile_size: u32, grid_tile_size: u32) -> Option<u32> {
+        if self.intersect == Some(false) {
+            return None;
+        }
+        // Geometries within the buffer of the feature query are kept
+        match self.buffer_size {
+            Some(pixels) => Some(pixels * tile_size / grid_tile_size),
+            None => Some(self.clip_buffer.unwrap_or(0)),
+        }
+    }
+    /// Check whether layer is visible at zoom level
+    pub fn is_visible(&self, zoom: u8) -> bool {
+        zoom >= self.minzoom() && zoom <= self.maxzoom()
+    }
+    /// Check whether layer is visible at map scale
+    pub fn is_visible_at_scale(&self, scale_denominator: f64) -> bool {
+        // SLD semantics: min inclusive, max exclusive
+        self.minscaledenominator.map_or(true, |min| scale_denominator >= min) &&
+        self.maxscaledenominator.map_or(true, |max| scale_denominator < max)
+    }
+    /// Feature count limit at zoom level
+    pub fn query_limit(&self, level: u8) -> Option<u32> {
+        let limit = self.query_limits
             .iter()
-            .map(|q| q.maxzoom())
-            .max()
-            .unwrap_or(22)
+            .rev()
+            .find(|&(zoom, _)| *zoom <= level)
+            .map(|(_, limit)| *limit)
+            .or(self.query_limit);
+        match (limit, self.max_features) {
+            (Some(limit), Some(max)) => Some(limit.min(max)),
+            (limit, max) => limit.or(max),
+        }
+    }
+    /// ORDER BY clause appended to feature queries
+    pub fn order_by_clause(&self) -> String {
+        match self.order_by {
+            Some(ref order_by) => format!(" ORDER BY {}", order_by),
+            None => String::new(),
+        }
+    }
+    /// Filter condition appended to the WHERE clause of table queries
+    pub fn filter_clause(&self) -> String {
+        match self.filter {
+            Some(ref filter) => format!(" AND ({})", filter),
+            None => String::new(),
+        }
+    }
+    /// Layers with the same batch key read the same table with the same geometry processing
+    /// and zoom range, differing only in their filter, attribute fields and tile encoding options.
+    /// Returns `None` for layers with custom queries or clustering.
+    pub fn batch_key(&self) -> Option<String> {
+        if self.table_name.is_none() || self.query.iter().any(|q| q.sql.is_some()) ||
+           self.cluster_threshold.is_some() {
+            return None;
+        }
+        let table = (&self.datasource,
+                     &self.table_name,
+                     &self.geometry_field,
+                     &self.geometry_type,
+                     self.srid,
+                     &self.fid_field);
+        let geometry = (self.label_point,
+                        self.simplify,
+                        self.simplify_algorithm,
+                        self.buffer_size,
+                        self.intersect);
+        let range = (self.minzoom(),
+                     self.maxzoom(),
+                     self.minscaledenominator,
+                     self.maxscaledenominator);
+        Some(format!("{:?}", (table, geometry, range, &self.order_by)))
     }
     // SQL query for zoom level
+    // Nested zoom ranges: the query with the narrower range takes precedence
     pub fn query(&self, level: u8) -> Option<&String> {
         let mut queries = self.query
             .iter()
             .map(|ref q| (q.minzoom(), q.maxzoom(), q.sql.as_ref().and_then(|sql| Some(sql))))
             .collect::<Vec<_>>();
-        queries.sort_by_key(|ref t| t.0);
+        queries.sort_by_key(|ref t| (t.0, 255 - t.1));
         let query = queries
             .iter()
             .rev()
@@ -94,7 +232,7 @@ impl Layer {
         metadata.insert("srs", "+proj=merc +a=6378137 +b=6378137 +lat_ts=0.0 +lon_0=0.0 +x_0=0.0 +y_0=0.0 +k=1.0 +units=m +nadgrids=@null +wktext +no_defs +over".to_string());
         metadata
     }
-    pub fn gen_runtime_config_from_input(&self, input: &PostgisInput) -> String {
+    pub fn gen_runtime_config_from_input(&self, input: &Datasource) -> String {
         let extent = input.layer_extent(self);
         let mut lines = vec!["\n[[tileset]]".to_string()];
         lines.push(format!(r#"name = "{}""#, self.name));
@@ -110,14 +248,33 @@ impl Layer {
 
         let mut cfg = lines.join("\n") + "\n";
         cfg.push_str(&self.gen_runtime_config());
-        if self.query(0).is_none() {
-            let query = input.build_query_sql(self, 3857, None, true).unwrap();
-            cfg.push_str(&format!("#sql = \"\"\"{}\"\"\"\n", query))
+        if let &Datasource::Postgis(ref pg) = input {
+            if self.query(0).is_none() {
+                let query = pg.build_query_sql(self, 3857, None, true).unwrap();
+                cfg.push_str(&format!("#sql = \"\"\"{}\"\"\"\n", query))
+            }
         }
         cfg
     }
 }
 
+/// Check that an SQL expression can't terminate the query or comment out its remainder
+fn validate_sql_expr(expr: &str) -> bool {
+    let mut in_string = false;
+    let mut prev = ' ';
+    for c in expr.chars() {
+        if c == '\'' {
+            in_string = !in_string;
+        } else if !in_string {
+            if c == ';' || (prev == '-' && c == '-') || (prev == '/' && c == '*') {
+                return false;
+            }
+        }
+        prev = c;
+    }
+    !in_string && !expr.trim().is_empty()
+}
+
 impl<'a> Config<'a, Layer, LayerCfg> for Layer {
     fn from_config(layer_cfg: &LayerCfg) -> Result<Self, String> {
         let queries = layer_cfg
@@ -131,6 +288,90 @@ impl<'a> Config<'a, Layer, LayerCfg> for Layer {
                      }
                  })
             .collect();
+        let mut query_limits = BTreeMap::new();
+        let query_limit = match layer_cfg.query_limit {
+            Some(QueryLimitCfg::Limit(limit)) => Some(limit),
+            Some(QueryLimitCfg::Zoom(ref limits)) => {
+                for (zoom, limit) in limits {
+                    let zoom = zoom.parse::<u8>()
+                        .map_err(|_| {
+                                     format!("Layer '{}': invalid zoom level '{}' in query_limit",
+                                             layer_cfg.name,
+                                             zoom)
+                                 })?;
+                    query_limits.insert(zoom, *limit);
+                }
+                None
+            }
+            None => None,
+        };
+        if let (Some(min), Some(max)) =
+            (layer_cfg.minscaledenominator, layer_cfg.maxscaledenominator) {
+            if min >= max {
+                return Err(format!("Layer '{}': minscaledenominator must be smaller than maxscaledenominator",
+                                   layer_cfg.name));
+            }
+        }
+        if layer_cfg.precision == Some(0) {
+            return Err(format!("Layer '{}': precision must be greater than 0", layer_cfg.name));
+        }
+        if layer_cfg.json_flatten_depth == Some(0) {
+            return Err(format!("Layer '{}': json_flatten_depth must be greater than 0",
+                               layer_cfg.name));
+        }
+        if layer_cfg.cluster_size == Some(0) {
+            return Err(format!("Layer '{}': cluster_size must be greater than 0",
+                               layer_cfg.name));
+        }
+        if layer_cfg.cluster_threshold.is_some() {
+            match layer_cfg.geometry_type.as_ref().map(|t| t.as_str()) {
+                None | Some("POINT") | Some("MULTIPOINT") => {}
+                Some(geometry_type) => {
+                    return Err(format!("Layer '{}': clustering is not supported for geometry type {}",
+                                       layer_cfg.name,
+                                       geometry_type))
+                }
+            }
+        }
+        let simplify_algorithm = match layer_cfg.simplify_algorithm {
+            Some(ref name) => {
+                Some(SimplifyAlgorithm::from_name(name)
+                         .ok_or(format!("Layer '{}': invalid simplify_algorithm '{}' - expected \"dp\" or \"vw\"",
+                                        layer_cfg.name,
+                                        name))?)
+            }
+            None => None,
+        };
+        let mut field_types = BTreeMap::new();
+        let fields = match layer_cfg.fields {
+            Some(FieldsCfg::Names(ref names)) => Some(names.clone()),
+            Some(FieldsCfg::Types(ref types)) => {
+                for (field, type_name) in types {
+                    let field_type = FieldType::from_name(type_name)
+                        .ok_or(format!("Layer '{}': invalid type '{}' of field '{}'",
+                                       layer_cfg.name,
+                                       type_name,
+                                       field))?;
+                    field_types.insert(field.clone(), field_type);
+                }
+                None
+            }
+            None => None,
+        };
+        if let Some(ref order_by) = layer_cfg.order_by {
+            if !validate_sql_expr(order_by) {
+                return Err(format!("Layer '{}': invalid order_by expression '{}'",
+                                   layer_cfg.name,
+                                   order_by));
+            }
+        }
+        if let Some(ref filter) = layer_cfg.filter {
+            if !validate_sql_expr(filter) {
+                return Err(format!("Layer '{}': invalid filter expression '{}'",
+                                   layer_cfg.name,
+                                   filter));
+            }
+        }
         let style = match layer_cfg.style {
             Some(ref style) => {
                 let gljson = toml_style_to_gljson(&style);
@@ -140,15 +381,36 @@ impl<'a> Config<'a, Layer, LayerCfg> for Layer {
         };
         Ok(Layer {
                name: layer_cfg.name.clone(),
+               datasource: layer_cfg.datasource.clone(),
                geometry_field: layer_cfg.geometry_field.clone(),
                geometry_type: layer_cfg.geometry_type.clone(),
                srid: layer_cfg.srid,
                fid_field: layer_cfg.fid_field.clone(),
+               fields: fields,
+               field_types: field_types,
+               flatten_json: layer_cfg.flatten_json.clone(),
+               json_flatten_depth: layer_cfg.json_flatten_depth,
                table_name: layer_cfg.table_name.clone(),
-               query_limit: layer_cfg.query_limit,
+               query_limit: query_limit,
+               query_limits: query_limits,
+               max_features: None,
+               order_by: layer_cfg.order_by.clone(),
+               filter: layer_cfg.filter.clone(),
+               cluster_threshold: layer_cfg.cluster_threshold,
+               cluster_size: layer_cfg.cluster_size,
+               minzoom: layer_cfg.minzoom,
+               maxzoom: layer_cfg.maxzoom,
+               minscaledenominator: layer_cfg.minscaledenominator,
+               maxscaledenominator: layer_cfg.maxscaledenominator,
                query: queries,
+               label_point: layer_cfg.label_point,
                simplify: layer_cfg.simplify,
+               simplify_algorithm: simplify_algorithm,
+               tolerance: layer_cfg.tolerance,
                buffer_size: layer_cfg.buffer_size,
+               clip_buffer: layer_cfg.clip_buffer,
+               intersect: layer_cfg.intersect,
+               precision: layer_cfg.precision,
                style: style,
            })
     }
@@ -164,6 +426,9 @@ table_name = "mytable"
 geometry_field = "wkb_geometry"
 geometry_type = "POINT"
 #fid_field = "id"
+#fields = ["name"]
+#minzoom = 0
+#maxzoom = 22
 #simplify = true
 #buffer_size = 10
 #[[tileset.layer.query]]
@@ -177,6 +442,9 @@ geometry_type = "POINT"
     fn gen_runtime_config(&self) -> String {
         let mut lines = vec!["[[tileset.layer]]".to_string()];
         lines.push(format!(r#"name = "{}""#, self.name));
+        if let Some(ref datasource) = self.datasource {
+            lines.push(format!(r#"datasource = "{}""#, datasource));
+        }
         match self.table_name {
             Some(ref table_name) => lines.push(format!(r#"table_name = "{}""#, table_name)),
             _ => lines.push(r#"#table_name = "mytable""#.to_string()),
@@ -199,20 +467,97 @@ geometry_type = "POINT"
             Some(ref fid_field) => lines.push(format!("fid_field = \"{}\"", fid_field)),
             _ => lines.push("#fid_field = \"id\"".to_string()),
         }
+        match self.fields {
+            Some(ref fields) => {
+                let fields: Vec<String> = fields.iter().map(|f| format!("\"{}\"", f)).collect();
+                lines.push(format!("fields = [{}]", fields.join(", ")))
+            }
+            _ if !self.field_types.is_empty() => {
+                let types: Vec<String> = self.field_types
+                    .iter()
+                    .map(|(field, field_type)| format!("{} = \"{}\"", field, field_type.name()))
+                    .collect();
+                lines.push(format!("fields = {{ {} }}", types.join(", ")))
+            }
+            _ => lines.push("#fields = [\"name\"]".to_string()),
+        }
+        if !self.flatten_json.is_empty() {
+            let columns: Vec<String> = self.flatten_json
+                .iter()
+                .map(|f| format!("\"{}\"", f))
+                .collect();
+            lines.push(format!("flatten_json = [{}]", columns.join(", ")));
+        }
+        if let Some(ref json_flatten_depth) = self.json_flatten_depth {
+            lines.push(format!("json_flatten_depth = {}", json_flatten_depth));
+        }
+        match self.minzoom {
+            Some(ref minzoom) => lines.push(format!("minzoom = {}", minzoom)),
+            _ => lines.push("#minzoom = 0".to_string()),
+        }
+        match self.maxzoom {
+            Some(ref maxzoom) => lines.push(format!("maxzoom = {}", maxzoom)),
+            _ => lines.push("#maxzoom = 22".to_string()),
+        }
+        if let Some(ref minscaledenominator) = self.minscaledenominator {
+            lines.push(format!("minscaledenominator = {}", minscaledenominator));
+        }
+        if let Some(ref maxscaledenominator) = self.maxscaledenominator {
+            lines.push(format!("maxscaledenominator = {}", maxscaledenominator));
+        }
         match self.buffer_size {
             Some(ref buffer_size) => lines.push(format!("buffer_size = {}", buffer_size)),
             _ => lines.push(format!("#buffer_size = 10")),
         }
+        if let Some(ref clip_buffer) = self.clip_buffer {
+            lines.push(format!("clip_buffer = {}", clip_buffer));
+        }
+        if let Some(ref intersect) = self.intersect {
+            lines.push(format!("intersect = {}", intersect));
+        }
+        if let Some(ref precision) = self.precision {
+            lines.push(format!("precision = {}", precision));
+        }
         if self.geometry_type != Some("POINT".to_string()) {
             // simplify is ignored for points
             match self.simplify {
                 Some(ref simplify) => lines.push(format!("simplify = {}", simplify)),
                 _ => lines.push(format!("#simplify = true")),
             }
+            if let Some(ref algorithm) = self.simplify_algorithm {
+                lines.push(format!("simplify_algorithm = \"{}\"", algorithm.name()));
+            }
+            match self.tolerance {
+                Some(ref tolerance) => lines.push(format!("tolerance = {}", tolerance)),
+                _ => lines.push(format!("#tolerance = 1.0")),
+            }
+        }
+        if !self.query_limits.is_empty() {
+            let limits: Vec<String> = self.query_limits
+                .iter()
+                .map(|(zoom, limit)| format!("{} = {}", zoom, limit))
+                .collect();
+            lines.push(format!("query_limit = {{ {} }}", limits.join(", ")));
+        } else {
+            match self.query_limit {
+                Some(ref query_limit) => lines.push(format!("query_limit = {}", query_limit)),
+                _ => lines.push("#query_limit = 1000".to_string()),
+            }
+        }
+        if let Some(ref order_by) = self.order_by {
+            lines.push(format!("order_by = {:?}", order_by));
+        }
+        if let Some(ref filter) = self.filter {
+            lines.push(format!("filter = {:?}", filter));
+        }
+        if let Some(ref cluster_threshold) = self.cluster_threshold {
+            lines.push(format!("cluster_threshold = {}", cluster_threshold));
+        }
+        if let Some(ref cluster_size) = self.cluster_size {
+            lines.push(format!("cluster_size = {}", cluster_size));
         }
-        match self.query_limit {
-            Some(ref query_limit) => lines.push(format!("query_limit = {}", query_limit)),
-            _ => lines.push("#query_limit = 1000".to_string()),
+        if let Some(ref label_point) = self.label_point {
+            lines.push(format!("label_point = {}", label_point));
         }
         match self.query(0) {
             Some(ref query) => {
@@ -64,6 +64,29 @@ fn test_toml_decode() {
     assert_eq!(cfg.query(15),
                Some(&"SELECT name,wkb_geometry FROM places_z2".to_string()));
 
+    // Nested zoom ranges with same minzoom
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "roads"
+        table_name = "roads"
+        [[query]]
+        maxzoom = 4
+        sql = "SELECT * FROM roads_z0"
+        [[query]]
+        sql = "SELECT * FROM roads_gen"
+        [[query]]
+        minzoom = 14
+        maxzoom = 22
+        sql = "SELECT * FROM roads"
+        "#;
+    let cfg = layer_from_config(toml).unwrap();
+    assert_eq!(cfg.query(0), Some(&"SELECT * FROM roads_z0".to_string()));
+    assert_eq!(cfg.query(4), Some(&"SELECT * FROM roads_z0".to_string()));
+    assert_eq!(cfg.query(5), Some(&"SELECT * FROM roads_gen".to_string()));
+    assert_eq!(cfg.query(13), Some(&"SELECT * FROM roads_gen".to_string()));
+    assert_eq!(cfg.query(14), Some(&"SELECT * FROM roads".to_string()));
+    assert_eq!(cfg.query(22), Some(&"SELECT * FROM roads".to_string()));
+
     // Minimal config
     let toml = r#"
         #[[tileset.layer]]
@@ -151,6 +174,442 @@ fn test_layers_from_config() {
 
     // errors
     let emptyconfig: Result<TilesetCfg, _> = parse_config("".to_string(), "");
-    assert_eq!(emptyconfig.err(),
+    assert_eq!(emptyconfig.err().map(|err| err.to_string()),
                Some(" - missing field `name`".to_string()));
 }
+
+#[test]
+fn test_layer_views() {
+    use core::parse_config;
+    use core::config::TilesetCfg;
+
+    // Two layers from the same table
+    let toml = r#"
+        name = "ne"
+
+        [[layer]]
+        name = "places"
+        table_name = "ne_10m_populated_places"
+        geometry_field = "wkb_geometry"
+        maxzoom = 8
+
+        [[layer]]
+        name = "capitals"
+        table_name = "ne_10m_populated_places"
+        geometry_field = "wkb_geometry"
+        minzoom = 4
+        [[layer.query]]
+        sql = "SELECT name,wkb_geometry FROM ne_10m_populated_places WHERE featurecla='Admin-0 capital'"
+        "#;
+    let config: TilesetCfg = parse_config(toml.to_string(), "").unwrap();
+    let tileset = Tileset::from_config(&config).unwrap();
+    assert_eq!(tileset.layers.len(), 2);
+    assert_eq!(tileset.layers[0].table_name, tileset.layers[1].table_name);
+
+    let toml = toml.replace(r#"name = "capitals""#, r#"name = "places""#);
+    let config: TilesetCfg = parse_config(toml, "").unwrap();
+    assert_eq!(Tileset::from_config(&config).err(),
+               Some("Tileset 'ne': duplicate layer name 'places'".to_string()));
+}
+
+#[test]
+fn test_layer_zoom_range() {
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        minzoom = 4
+        maxzoom = 10
+        [[query]]
+        minzoom = 2
+        sql = "SELECT name,wkb_geometry FROM places_z2"
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.minzoom(), 4);
+    assert_eq!(layer.maxzoom(), 10);
+    assert!(!layer.is_visible(3));
+    assert!(layer.is_visible(4));
+    assert!(layer.is_visible(10));
+    assert!(!layer.is_visible(12));
+
+    let layer = Layer::new("points");
+    assert!(layer.is_visible(0));
+    assert!(layer.is_visible(22));
+}
+
+#[test]
+fn test_zoom_query_limit() {
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        query_limit = 100
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.query_limit(0), Some(100));
+    assert_eq!(layer.query_limit(22), Some(100));
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        query_limit = { 4 = 100, 12 = 1000 }
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.query_limit(3), None);
+    assert_eq!(layer.query_limit(4), Some(100));
+    assert_eq!(layer.query_limit(11), Some(100));
+    assert_eq!(layer.query_limit(12), Some(1000));
+    assert_eq!(layer.query_limit(22), Some(1000));
+    assert!(layer.gen_runtime_config().contains("query_limit = { 4 = 100, 12 = 1000 }"));
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        query_limit = { low = 100 }
+        "#;
+    assert_eq!(layer_from_config(toml).err(),
+               Some("Layer 'points': invalid zoom level 'low' in query_limit".to_string()));
+}
+
+#[test]
+fn test_max_features() {
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        query_limit = { 4 = 100, 12 = 500000 }
+        "#;
+    let mut layer = layer_from_config(toml).unwrap();
+    layer.max_features = Some(1000);
+    assert_eq!(layer.query_limit(3), Some(1000));
+    assert_eq!(layer.query_limit(4), Some(100));
+    assert_eq!(layer.query_limit(12), Some(1000));
+
+    let mut layer = Layer::new("points");
+    assert_eq!(layer.query_limit(0), None);
+    layer.max_features = Some(1000);
+    assert_eq!(layer.query_limit(0), Some(1000));
+}
+
+#[test]
+fn test_field_types() {
+    use core::feature::FieldType;
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        fields = { population = "int", name = "string" }
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.fields, None);
+    assert_eq!(layer.field_types.get("population"), Some(&FieldType::Int));
+    assert_eq!(layer.field_types.get("name"), Some(&FieldType::String));
+    assert!(layer.gen_runtime_config()
+                .contains(r#"fields = { name = "string", population = "int" }"#));
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        fields = ["name"]
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.fields, Some(vec!["name".to_string()]));
+    assert!(layer.field_types.is_empty());
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        fields = { population = "integer" }
+        "#;
+    assert_eq!(layer_from_config(toml).err(),
+               Some("Layer 'points': invalid type 'integer' of field 'population'".to_string()));
+}
+
+#[test]
+fn test_precision() {
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "roads"
+        precision = 4
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.precision, Some(4));
+    assert!(layer.gen_runtime_config().contains("precision = 4"));
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "roads"
+        precision = 0
+        "#;
+    assert_eq!(layer_from_config(toml).err(),
+               Some("Layer 'roads': precision must be greater than 0".to_string()));
+}
+
+#[test]
+fn test_flatten_json() {
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "pois"
+        flatten_json = ["tags"]
+        json_flatten_depth = 2
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.flatten_json, vec!["tags".to_string()]);
+    assert_eq!(layer.json_flatten_depth, Some(2));
+    let config = layer.gen_runtime_config();
+    assert!(config.contains(r#"flatten_json = ["tags"]"#));
+    assert!(config.contains("json_flatten_depth = 2"));
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "pois"
+        flatten_json = ["tags"]
+        json_flatten_depth = 0
+        "#;
+    assert_eq!(layer_from_config(toml).err(),
+               Some("Layer 'pois': json_flatten_depth must be greater than 0".to_string()));
+}
+
+#[test]
+fn test_cluster_config() {
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "places"
+        table_name = "ne_10m_populated_places"
+        geometry_type = "POINT"
+        cluster_threshold = 1000
+        cluster_size = 64
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.cluster_threshold, Some(1000));
+    assert_eq!(layer.cluster_size, Some(64));
+    // Clustered layers are queried separately
+    assert_eq!(layer.batch_key(), None);
+    let config = layer.gen_runtime_config();
+    assert!(config.contains("cluster_threshold = 1000"));
+    assert!(config.contains("cluster_size = 64"));
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "buildings"
+        geometry_type = "POLYGON"
+        cluster_threshold = 1000
+        "#;
+    assert_eq!(layer_from_config(toml).err(),
+               Some("Layer 'buildings': clustering is not supported for geometry type POLYGON"
+                        .to_string()));
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "places"
+        cluster_threshold = 1000
+        cluster_size = 0
+        "#;
+    assert_eq!(layer_from_config(toml).err(),
+               Some("Layer 'places': cluster_size must be greater than 0".to_string()));
+}
+
+#[test]
+fn test_simplify_algorithm_config() {
+    use mvt::geom_encoder::SimplifyAlgorithm;
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "contours"
+        geometry_type = "LINESTRING"
+        simplify = true
+        simplify_algorithm = "vw"
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.simplify_algorithm, Some(SimplifyAlgorithm::VisvalingamWhyatt));
+    assert!(layer.gen_runtime_config().contains("simplify_algorithm = \"vw\""));
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "contours"
+        simplify_algorithm = "topo"
+        "#;
+    assert_eq!(layer_from_config(toml).err(),
+               Some("Layer 'contours': invalid simplify_algorithm 'topo' - expected \"dp\" or \"vw\""
+                        .to_string()));
+}
+
+#[test]
+fn test_layer_scale_range() {
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        minscaledenominator = 100000
+        maxscaledenominator = 500000.0
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert!(!layer.is_visible_at_scale(50000.0));
+    assert!(layer.is_visible_at_scale(100000.0));
+    assert!(layer.is_visible_at_scale(272989.4));
+    assert!(!layer.is_visible_at_scale(500000.0));
+    assert!(layer.gen_runtime_config().contains("maxscaledenominator = 500000"));
+
+    assert!(Layer::new("points").is_visible_at_scale(559082264.0));
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "points"
+        minscaledenominator = 500000
+        maxscaledenominator = 100000
+        "#;
+    assert_eq!(layer_from_config(toml).err(),
+               Some("Layer 'points': minscaledenominator must be smaller than maxscaledenominator"
+                        .to_string()));
+}
+
+#[test]
+fn test_order_by() {
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "buildings"
+        order_by = "ST_Area(geometry) DESC, name"
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.order_by_clause(), " ORDER BY ST_Area(geometry) DESC, name");
+    assert!(layer.gen_runtime_config().contains(r#"order_by = "ST_Area(geometry) DESC, name""#));
+    assert_eq!(Layer::new("buildings").order_by_clause(), "");
+
+    // Separators within string literals are allowed
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "buildings"
+        order_by = "name = 'a;b--c' DESC"
+        "#;
+    assert!(layer_from_config(toml).is_ok());
+
+    for order_by in &["name; DROP TABLE buildings",
+                      "name -- comment",
+                      "name /* comment */",
+                      "name = 'unterminated",
+                      " "] {
+        let toml = format!(r#"
+            #[[tileset.layer]]
+            name = "buildings"
+            order_by = "{}"
+            "#,
+                           order_by);
+        assert_eq!(layer_from_config(&toml).err(),
+                   Some(format!("Layer 'buildings': invalid order_by expression '{}'",
+                                order_by)));
+    }
+}
+
+#[test]
+fn test_filter() {
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "capitals"
+        table_name = "ne_10m_populated_places"
+        filter = "featurecla = 'Admin-0 capital'"
+        "#;
+    let layer = layer_from_config(toml).unwrap();
+    assert_eq!(layer.filter_clause(), " AND (featurecla = 'Admin-0 capital')");
+    assert!(layer.gen_runtime_config().contains(r#"filter = "featurecla = 'Admin-0 capital'""#));
+    assert_eq!(Layer::new("capitals").filter_clause(), "");
+
+    let toml = r#"
+        #[[tileset.layer]]
+        name = "capitals"
+        filter = "true; DROP TABLE ne_10m_populated_places"
+        "#;
+    assert_eq!(layer_from_config(toml).err(),
+               Some("Layer 'capitals': invalid filter expression 'true; DROP TABLE ne_10m_populated_places'"
+                        .to_string()));
+}
+
+#[test]
+fn test_batch_key() {
+    use core::layer::LayerQuery;
+
+    let mut capitals = Layer::new("capitals");
+    capitals.table_name = Some("ne_10m_populated_places".to_string());
+    capitals.filter = Some("featurecla = 'Admin-0 capital'".to_string());
+    capitals.tolerance = Some(2.0);
+    let mut places = Layer::new("places");
+    places.table_name = Some("ne_10m_populated_places".to_string());
+    // Filter and encoding options don't prevent batching
+    assert!(capitals.batch_key().is_some());
+    assert_eq!(capitals.batch_key(), places.batch_key());
+
+    places.maxzoom = Some(10);
+    assert!(capitals.batch_key() != places.batch_key());
+    places.maxzoom = None;
+    // Fields of all layers are selected in batch queries
+    places.fields = Some(vec!["name".to_string()]);
+    assert_eq!(capitals.batch_key(), places.batch_key());
+    places.fields = None;
+    places.table_name = Some("ne_10m_admin_0_countries".to_string());
+    assert!(capitals.batch_key() != places.batch_key());
+
+    // Layers with custom queries are retrieved on their own
+    let mut layer = Layer::new("query");
+    layer.table_name = Some("ne_10m_populated_places".to_string());
+    layer.query = vec![LayerQuery {
+                           minzoom: None,
+                           maxzoom: None,
+                           sql: Some("SELECT * FROM ne_10m_populated_places".to_string()),
+                       }];
+    assert_eq!(layer.batch_key(), None);
+}
+
+#[test]
+fn test_clip_buffer() {
+    // Plain layers are clipped to the tile extent
+    let mut layer = Layer::new("roads");
+    assert_eq!(layer.clip_rect_buffer(4096, 256), Some(0));
+    layer.clip_buffer = Some(64);
+    assert_eq!(layer.clip_rect_buffer(4096, 256), Some(64));
+    // buffer_size overrides clip_buffer: 10 pixels on a 256 pixel grid
+    layer.buffer_size = Some(10);
+    assert_eq!(layer.clip_rect_buffer(4096, 256), Some(160));
+    assert_eq!(layer.clip_rect_buffer(4096, 512), Some(80));
+    layer.intersect = Some(false);
+    assert_eq!(layer.clip_rect_buffer(4096, 256), None);
+    layer.buffer_size = None;
+    layer.intersect = Some(true);
+    assert_eq!(layer.clip_rect_buffer(4096, 256), Some(64));
+
+    let layer = layer_from_config(r#"
+        name = "roads"
+        clip_buffer = 16
+        "#)
+        .unwrap();
+    assert_eq!(layer.clip_buffer, Some(16));
+}
+
+#[test]
+fn test_plain_layer_clipping() {
+    use core::feature::FeatureStruct;
+    use core::geom::{self, GeometryType};
+    use core::grid::Extent;
+    use mvt::tile::Tile;
+
+    let extent = Extent {
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 256.0,
+        maxy: 256.0,
+    };
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: Vec::new(),
+        geometry: GeometryType::LineString(geom::LineString {
+                                               points: vec![geom::Point::new(128.0, 128.0, None),
+                                                            geom::Point::new(1000.0, 128.0, None)],
+                                               srid: None,
+                                           }),
+    };
+    let mut layer = Layer::new("roads");
+    let encoded = |layer: &Layer| {
+        let mut tile = Tile::new(&extent, 4096, false);
+        let mut mvt_layer = tile.new_layer(layer);
+        tile.add_feature(&mut mvt_layer, &feature);
+        mvt_layer.get_features()[0].get_geometry().to_vec()
+    };
+    // Line ends at the tile border without any buffer configured
+    assert_eq!(encoded(&layer), vec![9, 4096, 4096, 10, 4096, 0]);
+    layer.intersect = Some(false);
+    assert_eq!(encoded(&layer), vec![9, 4096, 4096, 10, 27904, 0]);
+}
@@ -6,19 +6,26 @@
 #[macro_use]
 mod enum_serializer;
 pub mod geom;
+pub mod wkb;
 pub mod screen;
 pub mod grid;
 pub mod layer;
 pub mod feature;
 pub mod config;
+pub mod error;
 
 pub use self::config::{ApplicationCfg, Config, read_config, parse_config};
+pub use self::error::TrexError;
 
 #[cfg(test)]
 mod geom_test;
 #[cfg(test)]
+mod wkb_test;
+#[cfg(test)]
 mod grid_test;
 #[cfg(test)]
 mod layer_test;
 #[cfg(test)]
 mod config_test;
+#[cfg(test)]
+mod error_test;
@@ -0,0 +1,197 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use core::geom::{self, GeometryType};
+use postgis::ewkb;
+
+
+// EWKB type flags
+// https://github.com/postgis/postgis/blob/2.4.0/doc/ZMSgeoms.txt
+const EWKB_Z: u32 = 0x80000000;
+const EWKB_M: u32 = 0x40000000;
+const EWKB_SRID: u32 = 0x20000000;
+
+/// Reader for OGC WKB (including ISO Z/M types) and PostGIS EWKB
+struct WkbReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Byte order of current (sub)geometry
+    little_endian: bool,
+}
+
+impl<'a> WkbReader<'a> {
+    fn read_u8(&mut self) -> Result<u8, String> {
+        let byte = *self.data
+                        .get(self.pos)
+                        .ok_or("Unexpected end of WKB geometry".to_string())?;
+        self.pos += 1;
+        Ok(byte)
+    }
+    fn read_u64(&mut self, len: usize) -> Result<u64, String> {
+        if self.pos + len > self.data.len() {
+            return Err("Unexpected end of WKB geometry".to_string());
+        }
+        let mut val = 0u64;
+        for i in 0..len {
+            let byte = if self.little_endian {
+                self.data[self.pos + len - 1 - i]
+            } else {
+                self.data[self.pos + i]
+            };
+            val = (val << 8) | byte as u64;
+        }
+        self.pos += len;
+        Ok(val)
+    }
+    fn read_u32(&mut self) -> Result<u32, String> {
+        self.read_u64(4).map(|val| val as u32)
+    }
+    fn read_f64(&mut self) -> Result<f64, String> {
+        self.read_u64(8).map(|val| f64::from_bits(val))
+    }
+    fn read_count(&mut self) -> Result<usize, String> {
+        let n = self.read_u32()? as usize;
+        // Each element needs at least 4 bytes, reject counts exceeding the input
+        if n > (self.data.len() - self.pos) / 4 {
+            return Err(format!("Invalid element count {} in WKB geometry", n));
+        }
+        Ok(n)
+    }
+    /// Byte order, geometry type and number of coordinates per vertex of (sub)geometry,
+    /// with SRID if present
+    fn read_header(&mut self) -> Result<(u32, usize, Option<i32>), String> {
+        self.little_endian = match self.read_u8()? {
+            0 => false,
+            1 => true,
+            order => return Err(format!("Invalid WKB byte order {}", order)),
+        };
+        let type_code = self.read_u32()?;
+        let mut dims = 2;
+        if type_code & EWKB_Z != 0 {
+            dims += 1;
+        }
+        if type_code & EWKB_M != 0 {
+            dims += 1;
+        }
+        let srid = if type_code & EWKB_SRID != 0 {
+            Some(self.read_u32()? as i32)
+        } else {
+            None
+        };
+        // ISO WKB dimensions
+        let iso_type = type_code & 0x0FFFFFFF;
+        dims += match iso_type / 1000 {
+            0 => 0, // XY
+            1 | 2 => 1, // XYZ or XYM
+            3 => 2, // XYZM
+            _ => return Err(format!("Unknown WKB geometry type {}", type_code)),
+        };
+        Ok((iso_type % 1000, dims, srid))
+    }
+    fn point(&mut self, dims: usize, srid: Option<i32>) -> Result<geom::Point, String> {
+        let x = self.read_f64()?;
+        let y = self.read_f64()?;
+        // Z and M values are ignored
+        for _ in 2..dims {
+            self.read_f64()?;
+        }
+        Ok(geom::Point::new(x, y, srid))
+    }
+    fn line(&mut self, dims: usize, srid: Option<i32>) -> Result<geom::LineString, String> {
+        let n = self.read_count()?;
+        let points = (0..n)
+            .map(|_| self.point(dims, None))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ewkb::LineString {
+               points: points,
+               srid: srid,
+           })
+    }
+    fn polygon(&mut self, dims: usize, srid: Option<i32>) -> Result<geom::Polygon, String> {
+        let n = self.read_count()?;
+        let rings = (0..n)
+            .map(|_| self.line(dims, None))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ewkb::Polygon {
+               rings: rings,
+               srid: srid,
+           })
+    }
+    /// Members of a multi geometry, each with its own header
+    fn members<T, F>(&mut self, geom_type: u32, mut read: F) -> Result<Vec<T>, String>
+        where F: FnMut(&mut Self, usize) -> Result<T, String>
+    {
+        let n = self.read_count()?;
+        let mut members = Vec::with_capacity(n);
+        for _ in 0..n {
+            let (member_type, dims, _) = self.read_header()?;
+            if member_type != geom_type {
+                return Err(format!("Unexpected geometry type {} in WKB multi geometry",
+                                   member_type));
+            }
+            members.push(read(self, dims)?);
+        }
+        Ok(members)
+    }
+    fn geometry(&mut self,
+                geom_type: u32,
+                dims: usize,
+                srid: Option<i32>)
+                -> Result<GeometryType, String> {
+        match geom_type {
+            1 => self.point(dims, srid).map(|g| GeometryType::Point(g)),
+            2 => self.line(dims, srid).map(|g| GeometryType::LineString(g)),
+            3 => self.polygon(dims, srid).map(|g| GeometryType::Polygon(g)),
+            4 => {
+                let points = self.members(1, |r, dims| r.point(dims, None))?;
+                Ok(GeometryType::MultiPoint(ewkb::MultiPoint {
+                                                points: points,
+                                                srid: srid,
+                                            }))
+            }
+            5 => {
+                let lines = self.members(2, |r, dims| r.line(dims, None))?;
+                Ok(GeometryType::MultiLineString(ewkb::MultiLineString {
+                                                     lines: lines,
+                                                     srid: srid,
+                                                 }))
+            }
+            6 => {
+                let polygons = self.members(3, |r, dims| r.polygon(dims, None))?;
+                Ok(GeometryType::MultiPolygon(ewkb::MultiPolygon {
+                                                  polygons: polygons,
+                                                  srid: srid,
+                                              }))
+            }
+            7 => {
+                let n = self.read_count()?;
+                let mut geometries = Vec::with_capacity(n);
+                for _ in 0..n {
+                    let (geom_type, dims, _) = self.read_header()?;
+                    geometries.push(self.geometry(geom_type, dims, None)?.into());
+                }
+                Ok(GeometryType::GeometryCollection(ewkb::GeometryCollection {
+                                                        geometries: geometries,
+                                                        srid: srid,
+                                                    }))
+            }
+            _ => Err(format!("Unsupported WKB geometry type {}", geom_type)),
+        }
+    }
+}
+
+impl GeometryType {
+    /// Decode WKB or EWKB geometry (as returned by ST_AsBinary or ST_AsEWKB).
+    /// Z and M coordinates are dropped.
+    pub fn from_wkb(data: &[u8]) -> Result<GeometryType, String> {
+        let mut reader = WkbReader {
+            data: data,
+            pos: 0,
+            little_endian: true,
+        };
+        let (geom_type, dims, srid) = reader.read_header()?;
+        reader.geometry(geom_type, dims, srid)
+    }
+}
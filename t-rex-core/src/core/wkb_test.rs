@@ -0,0 +1,152 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use core::geom::GeometryType;
+
+
+/// Minimal WKB writer for test input
+struct WkbWriter {
+    data: Vec<u8>,
+    little_endian: bool,
+}
+
+impl WkbWriter {
+    fn new(little_endian: bool) -> WkbWriter {
+        WkbWriter {
+            data: Vec::new(),
+            little_endian: little_endian,
+        }
+    }
+    fn bytes(&mut self, val: u64, len: usize) -> &mut Self {
+        let bytes: Vec<u8> = (0..len).map(|i| (val >> (8 * (len - 1 - i))) as u8).collect();
+        if self.little_endian {
+            self.data.extend(bytes.iter().rev());
+        } else {
+            self.data.extend(bytes.iter());
+        }
+        self
+    }
+    fn u32(&mut self, val: u32) -> &mut Self {
+        self.bytes(val as u64, 4)
+    }
+    fn f64(&mut self, val: f64) -> &mut Self {
+        self.bytes(val.to_bits(), 8)
+    }
+    fn header(&mut self, type_code: u32) -> &mut Self {
+        self.data.push(if self.little_endian { 1 } else { 0 });
+        self.u32(type_code)
+    }
+    fn coords(&mut self, coords: &[f64]) -> &mut Self {
+        for c in coords {
+            self.f64(*c);
+        }
+        self
+    }
+}
+
+fn geojson(geom: Result<GeometryType, String>) -> String {
+    geom.unwrap().to_geojson().to_string()
+}
+
+#[test]
+fn test_point() {
+    let mut wkb = WkbWriter::new(true);
+    wkb.header(1).coords(&[960000.5, 6002729.5]);
+    let geom = GeometryType::from_wkb(&wkb.data);
+    assert_eq!(&*format!("{:?}", geom),
+               "Ok(Point(Point { x: 960000.5, y: 6002729.5, srid: None }))");
+
+    // Big endian EWKB with SRID
+    let mut wkb = WkbWriter::new(false);
+    wkb.header(0x20000001).u32(3857).coords(&[960000.5, 6002729.5]);
+    let geom = GeometryType::from_wkb(&wkb.data);
+    assert_eq!(&*format!("{:?}", geom),
+               "Ok(Point(Point { x: 960000.5, y: 6002729.5, srid: Some(3857) }))");
+}
+
+#[test]
+fn test_higher_dimensions() {
+    // ISO WKB LineString Z
+    let mut wkb = WkbWriter::new(true);
+    wkb.header(1002).u32(2).coords(&[1.5, 2.5, 100.0, 3.5, 4.5, 200.0]);
+    assert_eq!(geojson(GeometryType::from_wkb(&wkb.data)),
+               r#"{"coordinates":[[1.5,2.5],[3.5,4.5]],"type":"LineString"}"#);
+
+    // ISO WKB Point ZM
+    let mut wkb = WkbWriter::new(true);
+    wkb.header(3001).coords(&[1.5, 2.5, 100.0, 7.0]);
+    assert_eq!(geojson(GeometryType::from_wkb(&wkb.data)),
+               r#"{"coordinates":[1.5,2.5],"type":"Point"}"#);
+
+    // EWKB MultiPolygon ZM with SRID, members without SRID
+    let mut wkb = WkbWriter::new(true);
+    wkb.header(0xE0000006).u32(3857).u32(1);
+    wkb.header(0xC0000003).u32(1).u32(4);
+    wkb.coords(&[0.5, 0.5, 1.0, 9.0, 1.5, 0.5, 1.0, 9.0, 1.5, 1.5, 1.0, 9.0, 0.5, 0.5, 1.0, 9.0]);
+    let geom = GeometryType::from_wkb(&wkb.data).unwrap();
+    match geom {
+        GeometryType::MultiPolygon(ref mp) => {
+            assert_eq!(mp.srid, Some(3857));
+            assert_eq!(mp.polygons[0].rings[0].points.len(), 4);
+        }
+        _ => panic!("MultiPolygon expected"),
+    }
+    assert_eq!(geom.to_geojson().to_string(),
+               r#"{"coordinates":[[[[0.5,0.5],[1.5,0.5],[1.5,1.5],[0.5,0.5]]]],"type":"MultiPolygon"}"#);
+}
+
+#[test]
+fn test_collections() {
+    // Members with mixed byte order
+    let mut wkb = WkbWriter::new(false);
+    wkb.header(4).u32(2);
+    wkb.header(1).coords(&[1.5, 2.5]);
+    let mut member = WkbWriter::new(true);
+    member.header(1).coords(&[3.5, 4.5]);
+    wkb.data.extend(member.data);
+    assert_eq!(geojson(GeometryType::from_wkb(&wkb.data)),
+               r#"{"coordinates":[[1.5,2.5],[3.5,4.5]],"type":"MultiPoint"}"#);
+
+    let mut wkb = WkbWriter::new(true);
+    wkb.header(7).u32(2);
+    wkb.header(1).coords(&[1.5, 2.5]);
+    wkb.header(5).u32(1).header(2).u32(2).coords(&[0.5, 0.5, 1.5, 1.5]);
+    assert_eq!(geojson(GeometryType::from_wkb(&wkb.data)),
+               r#"{"geometries":[{"coordinates":[1.5,2.5],"type":"Point"},{"coordinates":[[[0.5,0.5],[1.5,1.5]]],"type":"MultiLineString"}],"type":"GeometryCollection"}"#);
+
+    // Wrong member type
+    let mut wkb = WkbWriter::new(true);
+    wkb.header(5).u32(1).header(1).coords(&[1.5, 2.5]);
+    assert_eq!(GeometryType::from_wkb(&wkb.data).err(),
+               Some("Unexpected geometry type 1 in WKB multi geometry".to_string()));
+}
+
+#[test]
+fn test_invalid_input() {
+    assert_eq!(GeometryType::from_wkb(&[]).err(),
+               Some("Unexpected end of WKB geometry".to_string()));
+    assert_eq!(GeometryType::from_wkb(&[2, 1, 0, 0, 0]).err(),
+               Some("Invalid WKB byte order 2".to_string()));
+
+    let mut wkb = WkbWriter::new(true);
+    wkb.header(2).u32(0x7FFFFFFF).coords(&[1.5, 2.5]);
+    assert_eq!(GeometryType::from_wkb(&wkb.data).err(),
+               Some("Invalid element count 2147483647 in WKB geometry".to_string()));
+
+    let mut wkb = WkbWriter::new(true);
+    wkb.header(17).coords(&[1.5, 2.5]);
+    assert_eq!(GeometryType::from_wkb(&wkb.data).err(),
+               Some("Unsupported WKB geometry type 17".to_string()));
+
+    // Every truncation of a valid geometry is rejected
+    let mut wkb = WkbWriter::new(true);
+    wkb.header(0x20000007).u32(3857).u32(2);
+    wkb.header(1).coords(&[1.5, 2.5]);
+    wkb.header(3).u32(1).u32(3).coords(&[0.5, 0.5, 1.5, 0.5, 0.5, 0.5]);
+    assert!(GeometryType::from_wkb(&wkb.data).is_ok());
+    for len in 0..wkb.data.len() {
+        assert!(GeometryType::from_wkb(&wkb.data[..len]).is_err());
+    }
+}
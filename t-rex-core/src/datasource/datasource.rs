@@ -7,14 +7,18 @@ use core::layer::Layer;
 use core::grid::Extent;
 use core::grid::Grid;
 use core::feature::Feature;
+use core::error::TrexError;
 
 
 pub trait DatasourceInput {
+    /// Call `read` for each feature of layer within extent.
+    /// Returns the number of features read.
     fn retrieve_features<F>(&self,
                             layer: &Layer,
                             extent: &Extent,
                             zoom: u8,
                             grid: &Grid,
                             read: F)
+                            -> Result<u64, TrexError>
         where F: FnMut(&Feature);
 }
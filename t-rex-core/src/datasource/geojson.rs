@@ -0,0 +1,667 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use datasource::DatasourceInput;
+use core::error::TrexError;
+use core::feature::{Feature, FeatureAttr, FeatureAttrValType};
+use core::geom::{self, GeometryType};
+use core::grid::Extent;
+use core::grid::Grid;
+use core::layer::Layer;
+use core::Config;
+use core::config::DatasourceCfg;
+use postgis::ewkb;
+use serde_json::{self, Value};
+use num_cpus;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::Read;
+
+
+/// Maximal number of grid index cells per axis
+const MAX_GRID_SIZE: usize = 64;
+
+const EARTH_RADIUS: f64 = 6378137.0;
+
+/// Feature of a GeoJSON FeatureCollection
+struct GeoJsonFeature {
+    id: Option<u64>,
+    properties: serde_json::Map<String, Value>,
+    /// Geometry in WGS84
+    geometry: GeometryType,
+    /// Bounding box in WGS84
+    bbox: Extent,
+}
+
+/// Uniform grid of cells referencing the features with an intersecting bounding box
+struct GridIndex {
+    extent: Extent,
+    size: usize,
+    /// Feature indices per cell in row-major order
+    cells: Vec<Vec<usize>>,
+}
+
+/// In-memory datasource reading a GeoJSON FeatureCollection
+pub struct GeoJsonInput {
+    pub path: String,
+    features: Vec<GeoJsonFeature>,
+    index: GridIndex,
+}
+
+struct GeoJsonFeatureRef<'a> {
+    layer: &'a Layer,
+    feature: &'a GeoJsonFeature,
+    srid: i32,
+}
+
+
+fn extent_union(a: &Extent, b: &Extent) -> Extent {
+    Extent {
+        minx: a.minx.min(b.minx),
+        miny: a.miny.min(b.miny),
+        maxx: a.maxx.max(b.maxx),
+        maxy: a.maxy.max(b.maxy),
+    }
+}
+
+fn extent_intersects(a: &Extent, b: &Extent) -> bool {
+    a.minx <= b.maxx && a.maxx >= b.minx && a.miny <= b.maxy && a.maxy >= b.miny
+}
+
+impl GridIndex {
+    fn build(bboxes: &[&Extent]) -> GridIndex {
+        let extent = bboxes
+            .iter()
+            .fold(None, |ext: Option<Extent>, bbox| match ext {
+                Some(ext) => Some(extent_union(&ext, bbox)),
+                None => Some((*bbox).clone()),
+            })
+            .unwrap_or(Extent {
+                           minx: 0.0,
+                           miny: 0.0,
+                           maxx: 0.0,
+                           maxy: 0.0,
+                       });
+        // About one feature per cell for evenly distributed data
+        let size = ((bboxes.len() as f64).sqrt().ceil() as usize).max(1).min(MAX_GRID_SIZE);
+        let mut index = GridIndex {
+            extent: extent,
+            size: size,
+            cells: vec![Vec::new(); size * size],
+        };
+        for (i, bbox) in bboxes.iter().enumerate() {
+            let (mincol, minrow, maxcol, maxrow) = index.cell_range(bbox);
+            for row in minrow..maxrow + 1 {
+                for col in mincol..maxcol + 1 {
+                    index.cells[row * size + col].push(i);
+                }
+            }
+        }
+        index
+    }
+    /// Cell number of a coordinate along one axis
+    fn cell(&self, v: f64, min: f64, max: f64) -> usize {
+        if max <= min {
+            return 0;
+        }
+        let cell = ((v - min) / (max - min) * self.size as f64).floor();
+        if cell < 0.0 {
+            0
+        } else if cell >= self.size as f64 {
+            self.size - 1
+        } else {
+            cell as usize
+        }
+    }
+    /// Column and row range (inclusive) of cells covering extent
+    fn cell_range(&self, extent: &Extent) -> (usize, usize, usize, usize) {
+        (self.cell(extent.minx, self.extent.minx, self.extent.maxx),
+         self.cell(extent.miny, self.extent.miny, self.extent.maxy),
+         self.cell(extent.maxx, self.extent.minx, self.extent.maxx),
+         self.cell(extent.maxy, self.extent.miny, self.extent.maxy))
+    }
+    /// Sorted indices of features in cells intersecting extent
+    fn query(&self, extent: &Extent) -> Vec<usize> {
+        let mut indices = Vec::new();
+        if !extent_intersects(&self.extent, extent) {
+            return indices;
+        }
+        let (mincol, minrow, maxcol, maxrow) = self.cell_range(extent);
+        for row in minrow..maxrow + 1 {
+            for col in mincol..maxcol + 1 {
+                indices.extend_from_slice(&self.cells[row * self.size + col]);
+            }
+        }
+        indices.sort();
+        indices.dedup();
+        indices
+    }
+}
+
+
+// --- Coordinate transformation (WGS84 and Web Mercator only)
+
+fn wgs84_to_web_mercator(lon: f64, lat: f64) -> (f64, f64) {
+    let lat = lat.max(-85.0511287798).min(85.0511287798);
+    (lon.to_radians() * EARTH_RADIUS, (PI / 4.0 + lat.to_radians() / 2.0).tan().ln() * EARTH_RADIUS)
+}
+
+fn web_mercator_to_wgs84(x: f64, y: f64) -> (f64, f64) {
+    ((x / EARTH_RADIUS).to_degrees(), (2.0 * (y / EARTH_RADIUS).exp().atan() - PI / 2.0).to_degrees())
+}
+
+fn identity(x: f64, y: f64) -> (f64, f64) {
+    (x, y)
+}
+
+/// Transformation from WGS84 into SRID
+pub fn transformation_from_wgs84(srid: i32) -> Option<fn(f64, f64) -> (f64, f64)> {
+    match srid {
+        4326 => Some(identity),
+        3857 => Some(wgs84_to_web_mercator),
+        _ => None,
+    }
+}
+
+/// Transformation from SRID into WGS84
+fn transformation_to_wgs84(srid: i32) -> Option<fn(f64, f64) -> (f64, f64)> {
+    match srid {
+        4326 => Some(identity),
+        3857 => Some(web_mercator_to_wgs84),
+        _ => None,
+    }
+}
+
+pub fn transform_extent(extent: &Extent, transform: fn(f64, f64) -> (f64, f64)) -> Extent {
+    let (minx, miny) = transform(extent.minx, extent.miny);
+    let (maxx, maxy) = transform(extent.maxx, extent.maxy);
+    Extent {
+        minx: minx,
+        miny: miny,
+        maxx: maxx,
+        maxy: maxy,
+    }
+}
+
+
+// --- GeoJSON geometry conversion
+
+/// Extend bounding box with all positions of GeoJSON coordinates
+fn coordinates_bbox(coords: &Value, bbox: &mut Option<Extent>) {
+    if let Some(array) = coords.as_array() {
+        match (array.get(0).and_then(|v| v.as_f64()), array.get(1).and_then(|v| v.as_f64())) {
+            (Some(x), Some(y)) => {
+                let pos = Extent {
+                    minx: x,
+                    miny: y,
+                    maxx: x,
+                    maxy: y,
+                };
+                *bbox = Some(match *bbox {
+                                 Some(ref ext) => extent_union(ext, &pos),
+                                 None => pos,
+                             });
+            }
+            _ => {
+                for child in array {
+                    coordinates_bbox(child, bbox);
+                }
+            }
+        }
+    }
+}
+
+fn geometry_bbox(geometry: &Value, bbox: &mut Option<Extent>) {
+    coordinates_bbox(&geometry["coordinates"], bbox);
+    if let Some(geometries) = geometry["geometries"].as_array() {
+        for geometry in geometries {
+            geometry_bbox(geometry, bbox);
+        }
+    }
+}
+
+/// Reader of GeoJSON geometries in WGS84
+struct GeometryReader {
+    srid: Option<i32>,
+}
+
+impl GeometryReader {
+    fn point(&self, coords: &Value) -> Result<geom::Point, String> {
+        match (coords[0].as_f64(), coords[1].as_f64()) {
+            (Some(x), Some(y)) => Ok(geom::Point::new(x, y, self.srid)),
+            _ => Err(format!("Invalid position {}", coords)),
+        }
+    }
+    fn points(&self, coords: &Value) -> Result<Vec<geom::Point>, String> {
+        coords
+            .as_array()
+            .ok_or(format!("Invalid coordinates {}", coords))?
+            .iter()
+            .map(|pos| self.point(pos))
+            .collect()
+    }
+    fn linestring(&self, coords: &Value) -> Result<geom::LineString, String> {
+        Ok(geom::LineString {
+               points: self.points(coords)?,
+               srid: self.srid,
+           })
+    }
+    fn linestrings(&self, coords: &Value) -> Result<Vec<geom::LineString>, String> {
+        coords
+            .as_array()
+            .ok_or(format!("Invalid coordinates {}", coords))?
+            .iter()
+            .map(|line| self.linestring(line))
+            .collect()
+    }
+    fn polygon(&self, coords: &Value) -> Result<geom::Polygon, String> {
+        Ok(geom::Polygon {
+               rings: self.linestrings(coords)?,
+               srid: self.srid,
+           })
+    }
+    fn geometry(&self, geometry: &Value) -> Result<GeometryType, String> {
+        let ref coords = geometry["coordinates"];
+        match geometry["type"].as_str() {
+            Some("Point") => Ok(GeometryType::Point(self.point(coords)?)),
+            Some("MultiPoint") => {
+                Ok(GeometryType::MultiPoint(geom::MultiPoint {
+                                                points: self.points(coords)?,
+                                                srid: self.srid,
+                                            }))
+            }
+            Some("LineString") => Ok(GeometryType::LineString(self.linestring(coords)?)),
+            Some("MultiLineString") => {
+                Ok(GeometryType::MultiLineString(geom::MultiLineString {
+                                                     lines: self.linestrings(coords)?,
+                                                     srid: self.srid,
+                                                 }))
+            }
+            Some("Polygon") => Ok(GeometryType::Polygon(self.polygon(coords)?)),
+            Some("MultiPolygon") => {
+                let polygons = coords
+                    .as_array()
+                    .ok_or(format!("Invalid coordinates {}", coords))?
+                    .iter()
+                    .map(|polygon| self.polygon(polygon))
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok(GeometryType::MultiPolygon(geom::MultiPolygon {
+                                                  polygons: polygons,
+                                                  srid: self.srid,
+                                              }))
+            }
+            Some("GeometryCollection") => {
+                let mut geometries = Vec::new();
+                for member in geometry["geometries"].as_array().unwrap_or(&Vec::new()) {
+                    geometries.push(self.geometry(member)?.into());
+                }
+                Ok(GeometryType::GeometryCollection(geom::GeometryCollection {
+                                                        geometries: geometries,
+                                                        srid: self.srid,
+                                                    }))
+            }
+            _ => Err(format!("Unsupported geometry type {}", geometry["type"])),
+        }
+    }
+}
+
+/// Projection of WGS84 geometries into the grid SRID
+struct GeometryProjection {
+    transform: fn(f64, f64) -> (f64, f64),
+    srid: Option<i32>,
+}
+
+impl GeometryProjection {
+    fn point(&self, point: &geom::Point) -> geom::Point {
+        let (x, y) = (self.transform)(point.x, point.y);
+        geom::Point::new(x, y, self.srid)
+    }
+    fn points(&self, points: &[geom::Point]) -> Vec<geom::Point> {
+        points.iter().map(|p| self.point(p)).collect()
+    }
+    fn linestring(&self, line: &geom::LineString) -> geom::LineString {
+        geom::LineString {
+            points: self.points(&line.points),
+            srid: self.srid,
+        }
+    }
+    fn polygon(&self, polygon: &geom::Polygon) -> geom::Polygon {
+        geom::Polygon {
+            rings: polygon.rings.iter().map(|ring| self.linestring(ring)).collect(),
+            srid: self.srid,
+        }
+    }
+    fn multipoint(&self, multipoint: &geom::MultiPoint) -> geom::MultiPoint {
+        geom::MultiPoint {
+            points: self.points(&multipoint.points),
+            srid: self.srid,
+        }
+    }
+    fn multilinestring(&self, multiline: &geom::MultiLineString) -> geom::MultiLineString {
+        geom::MultiLineString {
+            lines: multiline.lines.iter().map(|line| self.linestring(line)).collect(),
+            srid: self.srid,
+        }
+    }
+    fn multipolygon(&self, multipolygon: &geom::MultiPolygon) -> geom::MultiPolygon {
+        geom::MultiPolygon {
+            polygons: multipolygon.polygons.iter().map(|polygon| self.polygon(polygon)).collect(),
+            srid: self.srid,
+        }
+    }
+    fn collection(&self, collection: &geom::GeometryCollection) -> geom::GeometryCollection {
+        let geometries = collection
+            .geometries
+            .iter()
+            .map(|member| match member {
+                     &ewkb::GeometryT::Point(ref g) => ewkb::GeometryT::Point(self.point(g)),
+                     &ewkb::GeometryT::LineString(ref g) => {
+                         ewkb::GeometryT::LineString(self.linestring(g))
+                     }
+                     &ewkb::GeometryT::Polygon(ref g) => ewkb::GeometryT::Polygon(self.polygon(g)),
+                     &ewkb::GeometryT::MultiPoint(ref g) => {
+                         ewkb::GeometryT::MultiPoint(self.multipoint(g))
+                     }
+                     &ewkb::GeometryT::MultiLineString(ref g) => {
+                         ewkb::GeometryT::MultiLineString(self.multilinestring(g))
+                     }
+                     &ewkb::GeometryT::MultiPolygon(ref g) => {
+                         ewkb::GeometryT::MultiPolygon(self.multipolygon(g))
+                     }
+                     &ewkb::GeometryT::GeometryCollection(ref g) => {
+                         ewkb::GeometryT::GeometryCollection(self.collection(g))
+                     }
+                 })
+            .collect();
+        geom::GeometryCollection {
+            geometries: geometries,
+            srid: self.srid,
+        }
+    }
+    fn geometry(&self, geometry: &GeometryType) -> GeometryType {
+        match geometry {
+            &GeometryType::Point(ref g) => GeometryType::Point(self.point(g)),
+            &GeometryType::LineString(ref g) => GeometryType::LineString(self.linestring(g)),
+            &GeometryType::Polygon(ref g) => GeometryType::Polygon(self.polygon(g)),
+            &GeometryType::MultiPoint(ref g) => GeometryType::MultiPoint(self.multipoint(g)),
+            &GeometryType::MultiLineString(ref g) => {
+                GeometryType::MultiLineString(self.multilinestring(g))
+            }
+            &GeometryType::MultiPolygon(ref g) => GeometryType::MultiPolygon(self.multipolygon(g)),
+            &GeometryType::GeometryCollection(ref g) => {
+                GeometryType::GeometryCollection(self.collection(g))
+            }
+        }
+    }
+}
+
+/// Upper case geometry type name as used in layer configuration
+fn geometry_type_name(geometry: &GeometryType) -> String {
+    match geometry {
+        &GeometryType::Point(_) => "POINT",
+        &GeometryType::LineString(_) => "LINESTRING",
+        &GeometryType::Polygon(_) => "POLYGON",
+        &GeometryType::MultiPoint(_) => "MULTIPOINT",
+        &GeometryType::MultiLineString(_) => "MULTILINESTRING",
+        &GeometryType::MultiPolygon(_) => "MULTIPOLYGON",
+        &GeometryType::GeometryCollection(_) => "GEOMETRYCOLLECTION",
+    }
+    .to_string()
+}
+
+/// Feature id from an `id` or property value.
+/// Non-negative integral numbers and numeric strings are accepted.
+fn fid_value(value: &Value) -> Option<u64> {
+    match value {
+        &Value::Number(ref n) => {
+            n.as_u64().or_else(|| {
+                n.as_f64().and_then(|v| if v >= 0.0 && v.fract() == 0.0 && v < u64::max_value() as f64 {
+                                        Some(v as u64)
+                                    } else {
+                                        None
+                                    })
+            })
+        }
+        &Value::String(ref s) => s.trim().parse::<u64>().ok(),
+        _ => None,
+    }
+}
+
+fn attribute_value(value: &Value) -> Option<FeatureAttrValType> {
+    match value {
+        &Value::Null => None,
+        &Value::Bool(v) => Some(FeatureAttrValType::Bool(v)),
+        &Value::Number(ref n) => {
+            if let Some(v) = n.as_i64() {
+                Some(FeatureAttrValType::Int(v))
+            } else if let Some(v) = n.as_u64() {
+                Some(FeatureAttrValType::UInt(v))
+            } else {
+                n.as_f64().map(|v| FeatureAttrValType::Double(v))
+            }
+        }
+        &Value::String(ref v) => Some(FeatureAttrValType::String(v.clone())),
+        // Arrays and objects are encoded as JSON string
+        v => Some(FeatureAttrValType::String(v.to_string())),
+    }
+}
+
+
+impl<'a> Feature for GeoJsonFeatureRef<'a> {
+    fn fid(&self) -> Option<u64> {
+        match self.layer.fid_field {
+            Some(ref fid_field) => self.feature.properties.get(fid_field).and_then(fid_value),
+            None => self.feature.id,
+        }
+    }
+    fn attributes(&self) -> Vec<FeatureAttr> {
+        self.feature
+            .properties
+            .iter()
+            .filter(|&(key, _)| match self.layer.fields {
+                        Some(ref fields) => fields.contains(key),
+                        None => true,
+                    })
+            .filter_map(|(key, value)| {
+                            attribute_value(value).map(|v| {
+                                                            FeatureAttr {
+                                                                key: key.clone(),
+                                                                value: v,
+                                                            }
+                                                        })
+                        })
+            .collect()
+    }
+    fn geometry(&self) -> Result<GeometryType, String> {
+        let transform = transformation_from_wgs84(self.srid)
+            .ok_or(format!("Unsupported grid SRID {}", self.srid))?;
+        let projection = GeometryProjection {
+            transform: transform,
+            srid: Some(self.srid),
+        };
+        Ok(projection.geometry(&self.feature.geometry))
+    }
+}
+
+
+impl GeoJsonInput {
+    /// Read GeoJSON FeatureCollection from file
+    pub fn open(path: &str) -> Result<GeoJsonInput, String> {
+        let mut file = File::open(path)
+            .map_err(|e| format!("Error opening GeoJSON file '{}': {}", path, e))?;
+        let mut json = String::new();
+        file.read_to_string(&mut json)
+            .map_err(|e| format!("Error reading GeoJSON file '{}': {}", path, e))?;
+        GeoJsonInput::from_str(path, &json)
+    }
+    /// Parse GeoJSON FeatureCollection
+    pub fn from_str(path: &str, json: &str) -> Result<GeoJsonInput, String> {
+        let collection: Value = serde_json::from_str(json)
+            .map_err(|e| format!("Error parsing GeoJSON file '{}': {}", path, e))?;
+        if collection["type"].as_str() != Some("FeatureCollection") {
+            return Err(format!("GeoJSON file '{}' is not a FeatureCollection", path));
+        }
+        let reader = GeometryReader { srid: Some(4326) };
+        let mut features = Vec::new();
+        for feature in collection["features"].as_array().unwrap_or(&Vec::new()) {
+            let mut bbox = None;
+            geometry_bbox(&feature["geometry"], &mut bbox);
+            let bbox = match bbox {
+                Some(bbox) => bbox,
+                None => {
+                    // Features without geometry are skipped
+                    continue;
+                }
+            };
+            let geometry = match reader.geometry(&feature["geometry"]) {
+                Ok(geometry) => geometry,
+                Err(e) => {
+                    warn!("GeoJSON file '{}' - skipping feature: {}", path, e);
+                    continue;
+                }
+            };
+            features.push(GeoJsonFeature {
+                              id: fid_value(&feature["id"]),
+                              properties: feature["properties"]
+                                  .as_object()
+                                  .cloned()
+                                  .unwrap_or_default(),
+                              geometry: geometry,
+                              bbox: bbox,
+                          });
+        }
+        let index = GridIndex::build(&features.iter().map(|f| &f.bbox).collect::<Vec<_>>());
+        info!("GeoJSON file '{}': {} features", path, features.len());
+        Ok(GeoJsonInput {
+               path: path.to_string(),
+               features: features,
+               index: index,
+           })
+    }
+    pub fn pool_size(&self) -> u32 {
+        num_cpus::get() as u32
+    }
+    /// Detect geometry type of layer from first feature
+    pub fn detect_layer_geometry(&self, layer: &mut Layer) {
+        if layer.geometry_type.is_none() {
+            layer.geometry_type = self.features
+                .first()
+                .map(|f| geometry_type_name(&f.geometry));
+        }
+    }
+    /// Property names with TileJSON field type (Number, Boolean or String)
+    pub fn detect_field_types(&self, layer: &Layer) -> Vec<(String, String)> {
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for feature in &self.features {
+            for (key, value) in &feature.properties {
+                if fields.iter().any(|&(ref name, _)| name == key) || value.is_null() {
+                    continue;
+                }
+                if let Some(ref layer_fields) = layer.fields {
+                    if !layer_fields.contains(key) {
+                        continue;
+                    }
+                }
+                let field_type = match value {
+                    &Value::Number(_) => "Number",
+                    &Value::Bool(_) => "Boolean",
+                    _ => "String",
+                };
+                fields.push((key.clone(), field_type.to_string()));
+            }
+        }
+        fields
+    }
+    /// Property names
+    pub fn detect_data_columns(&self, layer: &Layer) -> Vec<(String, String)> {
+        self.detect_field_types(layer)
+            .into_iter()
+            .map(|(name, _)| (name, String::new()))
+            .collect()
+    }
+    /// Extent of all features (in WGS84)
+    pub fn layer_extent(&self) -> Option<Extent> {
+        self.features
+            .iter()
+            .fold(None, |ext: Option<Extent>, f| match ext {
+                Some(ext) => Some(extent_union(&ext, &f.bbox)),
+                None => Some(f.bbox.clone()),
+            })
+    }
+    /// Projected extent
+    pub fn extent_from_wgs84(&self, extent: &Extent, dest_srid: i32) -> Option<Extent> {
+        transformation_from_wgs84(dest_srid).map(|transform| transform_extent(extent, transform))
+    }
+}
+
+impl DatasourceInput for GeoJsonInput {
+    fn retrieve_features<F>(&self,
+                            layer: &Layer,
+                            extent: &Extent,
+                            zoom: u8,
+                            grid: &Grid,
+                            mut read: F)
+                            -> Result<u64, TrexError>
+        where F: FnMut(&Feature)
+    {
+        let bbox = match transformation_to_wgs84(grid.srid()) {
+            Some(transform) => transform_extent(extent, transform),
+            None => {
+                return Err(TrexError::Datasource(format!("Layer '{}': GeoJSON datasource doesn't support grid SRID {}",
+                                                         layer.name,
+                                                         grid.srid())));
+            }
+        };
+        let query_limit = layer.query_limit(zoom).unwrap_or(0) as u64;
+        let mut cnt = 0;
+        // Indices are in file order
+        for i in self.index.query(&bbox) {
+            if !extent_intersects(&self.features[i].bbox, &bbox) {
+                continue;
+            }
+            if cnt == query_limit && query_limit > 0 {
+                info!("Feature count limited (query_limit={})", cnt);
+                break;
+            }
+            let feature = GeoJsonFeatureRef {
+                layer: layer,
+                feature: &self.features[i],
+                srid: grid.srid(),
+            };
+            read(&feature);
+            cnt += 1;
+        }
+        Ok(cnt)
+    }
+}
+
+impl<'a> Config<'a, GeoJsonInput, DatasourceCfg> for GeoJsonInput {
+    fn from_config(ds_cfg: &DatasourceCfg) -> Result<Self, String> {
+        let path = ds_cfg
+            .path
+            .as_ref()
+            .ok_or("GeoJSON datasource: missing field `path`".to_string())?;
+        GeoJsonInput::open(path)
+    }
+
+    fn gen_config() -> String {
+        let toml = r#"
+[datasource]
+type = "geojson"
+# GeoJSON FeatureCollection file in WGS84
+path = "data.geojson"
+"#;
+        toml.to_string()
+    }
+
+    fn gen_runtime_config(&self) -> String {
+        format!(r#"
+[datasource]
+type = "geojson"
+path = "{}"
+"#,
+                self.path)
+    }
+}
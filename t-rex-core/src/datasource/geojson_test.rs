@@ -0,0 +1,187 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use datasource::DatasourceInput;
+use datasource::geojson::GeoJsonInput;
+use core::feature::FeatureAttrValType;
+use core::geom::GeometryType;
+use core::grid::Grid;
+use core::layer::Layer;
+
+
+#[test]
+fn test_retrieve_features() {
+    let geojson = GeoJsonInput::open("src/test/places.geojson").unwrap();
+    let grid = Grid::web_mercator();
+    let layer = Layer::new("places");
+
+    // Tile covering Switzerland
+    let extent = grid.tile_extent_xyz(16, 11, 5);
+    let mut fids = Vec::new();
+    geojson.retrieve_features(&layer, &extent, 5, &grid, |feat| {
+        fids.push(feat.fid().unwrap());
+        if feat.fid() == Some(1) {
+            let geom = feat.geometry().unwrap();
+            match geom {
+                GeometryType::Point(p) => {
+                    assert_eq!(p.srid, Some(3857));
+                    assert!((p.x - 829040.8).abs() < 1.0);
+                    assert!((p.y - 5933590.5).abs() < 1.0);
+                }
+                _ => panic!("Point expected"),
+            }
+        }
+    }).unwrap();
+    assert_eq!(fids, vec![1, 2, 4]);
+
+    // Tile covering New York
+    let extent = grid.tile_extent_xyz(9, 12, 5);
+    let mut fids = Vec::new();
+    geojson.retrieve_features(&layer, &extent, 5, &grid, |feat| {
+        fids.push(feat.fid().unwrap());
+    }).unwrap();
+    assert_eq!(fids, vec![3]);
+
+    // Empty tile
+    let extent = grid.tile_extent_xyz(0, 0, 5);
+    let mut cnt = 0;
+    geojson.retrieve_features(&layer, &extent, 5, &grid, |_| cnt += 1).unwrap();
+    assert_eq!(cnt, 0);
+}
+
+#[test]
+fn test_attributes() {
+    let geojson = GeoJsonInput::open("src/test/places.geojson").unwrap();
+    let grid = Grid::web_mercator();
+    let mut layer = Layer::new("places");
+    layer.query_limit = Some(1);
+    let extent = grid.tile_extent_xyz(0, 0, 0);
+
+    let mut attrs = Vec::new();
+    geojson.retrieve_features(&layer, &extent, 0, &grid, |feat| {
+        attrs = feat.attributes()
+            .into_iter()
+            .map(|attr| (attr.key, attr.value))
+            .collect();
+    }).unwrap();
+    assert_eq!(attrs,
+               vec![("capital".to_string(), FeatureAttrValType::Bool(true)),
+                    ("name".to_string(), FeatureAttrValType::String("Bern".to_string())),
+                    ("population".to_string(), FeatureAttrValType::Int(133115))]);
+
+    layer.fields = Some(vec!["name".to_string()]);
+    layer.fid_field = Some("population".to_string());
+    let mut fid = None;
+    let mut keys = Vec::new();
+    geojson.retrieve_features(&layer, &extent, 0, &grid, |feat| {
+        fid = feat.fid();
+        keys = feat.attributes().into_iter().map(|attr| attr.key).collect();
+    }).unwrap();
+    assert_eq!(fid, Some(133115));
+    assert_eq!(keys, vec!["name"]);
+
+    // Null values are skipped in field type detection
+    layer.fields = None;
+    assert_eq!(geojson.detect_field_types(&layer),
+               vec![("capital".to_string(), "Boolean".to_string()),
+                    ("name".to_string(), "String".to_string()),
+                    ("population".to_string(), "Number".to_string())]);
+}
+
+#[test]
+fn test_layer_extent() {
+    let geojson = GeoJsonInput::open("src/test/places.geojson").unwrap();
+    let mut layer = Layer::new("places");
+    geojson.detect_layer_geometry(&mut layer);
+    assert_eq!(layer.geometry_type, Some("POINT".to_string()));
+
+    let extent = geojson.layer_extent().unwrap();
+    assert_eq!((extent.minx, extent.miny, extent.maxx, extent.maxy),
+               (-74.006, 40.7128, 8.5417, 47.5));
+}
+
+#[test]
+fn test_invalid_input() {
+    assert!(GeoJsonInput::open("src/test/missing.geojson").is_err());
+    assert_eq!(GeoJsonInput::from_str("test.geojson", r#"{"type": "Feature"}"#).err(),
+               Some("GeoJSON file 'test.geojson' is not a FeatureCollection".to_string()));
+}
+
+#[test]
+fn test_fid_values() {
+    let geojson = GeoJsonInput::from_str("ids.geojson",
+                                         r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "id": 7, "properties": {"code": "12"},
+             "geometry": {"type": "Point", "coordinates": [7.0, 46.0]}},
+            {"type": "Feature", "id": "42", "properties": {"code": 3.0},
+             "geometry": {"type": "Point", "coordinates": [7.0, 46.0]}},
+            {"type": "Feature", "id": 5.0, "properties": {"code": -1},
+             "geometry": {"type": "Point", "coordinates": [7.0, 46.0]}},
+            {"type": "Feature", "id": "abc", "properties": {"code": 2.5},
+             "geometry": {"type": "Point", "coordinates": [7.0, 46.0]}},
+            {"type": "Feature", "id": -3, "properties": {},
+             "geometry": {"type": "Point", "coordinates": [7.0, 46.0]}}
+        ]}"#)
+        .unwrap();
+    let grid = Grid::web_mercator();
+    let extent = grid.tile_extent_xyz(0, 0, 0);
+    let mut layer = Layer::new("ids");
+    let mut fids = Vec::new();
+    geojson.retrieve_features(&layer, &extent, 0, &grid, |feat| fids.push(feat.fid())).unwrap();
+    assert_eq!(fids, vec![Some(7), Some(42), Some(5), None, None]);
+
+    layer.fid_field = Some("code".to_string());
+    let mut fids = Vec::new();
+    geojson.retrieve_features(&layer, &extent, 0, &grid, |feat| fids.push(feat.fid())).unwrap();
+    assert_eq!(fids, vec![Some(12), Some(3), None, None, None]);
+}
+
+#[test]
+fn test_grid_index() {
+    // 20x20 points from 0/0 to 19/19
+    let features: Vec<String> = (0..400)
+        .map(|i| {
+                 format!(r#"{{"type": "Feature", "id": {}, "properties": {{}},
+                    "geometry": {{"type": "Point", "coordinates": [{}, {}]}}}}"#,
+                         i,
+                         i % 20,
+                         i / 20)
+             })
+        .collect();
+    let json = format!(r#"{{"type": "FeatureCollection", "features": [{}]}}"#,
+                       features.join(","));
+    let geojson = GeoJsonInput::from_str("grid.geojson", &json).unwrap();
+    let grid = Grid::wgs84();
+    let layer = Layer::new("grid");
+    let extent = ::core::grid::Extent {
+        minx: 4.5,
+        miny: 2.5,
+        maxx: 6.5,
+        maxy: 3.5,
+    };
+    let mut fids = Vec::new();
+    geojson.retrieve_features(&layer, &extent, 0, &grid, |feat| {
+            fids.push(feat.fid().unwrap());
+            match feat.geometry().unwrap() {
+                GeometryType::Point(p) => assert_eq!(p.srid, Some(4326)),
+                _ => panic!("Point expected"),
+            }
+        })
+        .unwrap();
+    assert_eq!(fids, vec![65, 66]);
+
+    // Invalid geometries are skipped
+    let geojson = GeoJsonInput::from_str("invalid.geojson",
+                                         r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {},
+             "geometry": {"type": "Curve", "coordinates": [7.0, 46.0]}},
+            {"type": "Feature", "properties": {},
+             "geometry": {"type": "LineString", "coordinates": [[7.0, 46.0], [8.0, 47.0]]}}
+        ]}"#)
+        .unwrap();
+    let mut layer = Layer::new("invalid");
+    geojson.detect_layer_geometry(&mut layer);
+    assert_eq!(layer.geometry_type, Some("LINESTRING".to_string()));
+}
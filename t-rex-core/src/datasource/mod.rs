@@ -5,18 +5,256 @@
 
 pub mod datasource;
 pub mod postgis;
+pub mod geojson;
+pub mod spatialite;
+pub mod tileproxy;
 #[cfg(test)]
 mod postgis_test;
+#[cfg(test)]
+mod geojson_test;
+#[cfg(test)]
+mod spatialite_test;
+#[cfg(test)]
+mod tileproxy_test;
 
 pub use self::datasource::DatasourceInput;
 pub use self::postgis::PostgisInput;
+pub use self::geojson::GeoJsonInput;
+pub use self::spatialite::SpatialiteInput;
+pub use self::tileproxy::TileProxyInput;
 
 use core::Config;
 use core::config::DatasourceCfg;
+use core::error::TrexError;
+use core::feature::Feature;
+use core::grid::{Extent, Grid};
+use core::layer::Layer;
+use std::collections::BTreeMap;
 
 
 pub enum Datasource {
     Postgis(PostgisInput),
+    GeoJson(GeoJsonInput),
+    Spatialite(SpatialiteInput),
+    TileProxy(TileProxyInput),
+}
+
+impl Datasource {
+    /// Connect to database
+    pub fn connect(&mut self) -> Result<(), String> {
+        match self {
+            &mut Datasource::Postgis(ref mut pg) => *pg = pg.connected(),
+            &mut Datasource::Spatialite(ref mut ds) => *ds = ds.connected()?,
+            &mut Datasource::GeoJson(_) |
+            &mut Datasource::TileProxy(_) => {}
+        }
+        Ok(())
+    }
+    /// Check whether datasource is reachable
+    pub fn check_connection(&self) -> Result<(), String> {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.check_connection(),
+            &Datasource::Spatialite(ref ds) => ds.check_connection(),
+            &Datasource::GeoJson(_) |
+            &Datasource::TileProxy(_) => Ok(()),
+        }
+    }
+    /// Number of pooled connections currently in use
+    pub fn connections_in_use(&self) -> Option<u32> {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.connections_in_use(),
+            &Datasource::GeoJson(_) |
+            &Datasource::Spatialite(_) |
+            &Datasource::TileProxy(_) => None,
+        }
+    }
+    pub fn pool_size(&self) -> u32 {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.pool_size(),
+            &Datasource::GeoJson(ref ds) => ds.pool_size(),
+            &Datasource::Spatialite(ref ds) => ds.pool_size(),
+            &Datasource::TileProxy(ref ds) => ds.pool_size(),
+        }
+    }
+    /// Encoding with ST_AsMVT
+    pub fn native_mvt(&self) -> bool {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.native_mvt(),
+            &Datasource::GeoJson(_) |
+            &Datasource::Spatialite(_) |
+            &Datasource::TileProxy(_) => false,
+        }
+    }
+    /// Layers reading the same table can be retrieved with a single query
+    pub fn batch_queries(&self) -> bool {
+        match self {
+            &Datasource::Postgis(ref pg) => !pg.native_mvt(),
+            &Datasource::GeoJson(_) |
+            &Datasource::Spatialite(_) |
+            &Datasource::TileProxy(_) => false,
+        }
+    }
+    pub fn detect_layer_geometry(&self, layer: &mut Layer) {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.detect_layer_geometry(layer),
+            &Datasource::GeoJson(ref ds) => ds.detect_layer_geometry(layer),
+            &Datasource::Spatialite(ref ds) => ds.detect_layer_geometry(layer),
+            &Datasource::TileProxy(_) => {}
+        }
+    }
+    /// Check layer configuration against connected datasource
+    pub fn check_layer(&self, layer: &Layer) -> Vec<String> {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.check_layer(layer),
+            &Datasource::GeoJson(_) |
+            &Datasource::Spatialite(_) |
+            &Datasource::TileProxy(_) => Vec::new(),
+        }
+    }
+    /// Fill in missing fid_field from table primary key
+    pub fn detect_fid_field(&self, layer: &mut Layer) {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.detect_fid_field(layer),
+            &Datasource::GeoJson(_) |
+            &Datasource::Spatialite(_) |
+            &Datasource::TileProxy(_) => {}
+        }
+    }
+    pub fn prepare_queries(&mut self,
+                           layer: &Layer,
+                           grid_srid: i32,
+                           extent: u32,
+                           grid_tile_size: u32) {
+        match self {
+            &mut Datasource::Postgis(ref mut pg) => {
+                pg.prepare_queries(layer, grid_srid, extent, grid_tile_size)
+            }
+            &mut Datasource::Spatialite(ref mut ds) => ds.prepare_queries(layer, grid_srid),
+            &mut Datasource::GeoJson(_) |
+            &mut Datasource::TileProxy(_) => {}
+        }
+    }
+    pub fn prepare_batch_queries(&mut self, layers: &[&Layer], grid_srid: i32) {
+        match self {
+            &mut Datasource::Postgis(ref mut pg) => pg.prepare_batch_queries(layers, grid_srid),
+            &mut Datasource::GeoJson(_) |
+            &mut Datasource::Spatialite(_) |
+            &mut Datasource::TileProxy(_) => {}
+        }
+    }
+    /// Return column field names and Rust compatible type conversion - without geometry column
+    pub fn detect_data_columns(&self,
+                               layer: &Layer,
+                               sql: Option<&String>)
+                               -> Vec<(String, String)> {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.detect_data_columns(layer, sql),
+            &Datasource::GeoJson(ref ds) => ds.detect_data_columns(layer),
+            &Datasource::Spatialite(ref ds) => ds.detect_data_columns(layer, sql),
+            // Attributes are only known after fetching upstream tiles
+            &Datasource::TileProxy(_) => Vec::new(),
+        }
+    }
+    /// Return data column names with TileJSON field type (Number, Boolean or String)
+    pub fn detect_field_types(&self,
+                              layer: &Layer,
+                              sql: Option<&String>)
+                              -> Vec<(String, String)> {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.detect_field_types(layer, sql),
+            &Datasource::GeoJson(ref ds) => ds.detect_field_types(layer),
+            &Datasource::Spatialite(ref ds) => ds.detect_field_types(layer, sql),
+            &Datasource::TileProxy(_) => Vec::new(),
+        }
+    }
+    /// Detect extent of layer (in WGS84)
+    pub fn layer_extent(&self, layer: &Layer) -> Option<Extent> {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.layer_extent(layer),
+            &Datasource::GeoJson(ref ds) => ds.layer_extent(),
+            &Datasource::Spatialite(ref ds) => ds.layer_extent(layer),
+            &Datasource::TileProxy(_) => None,
+        }
+    }
+    /// Detect extent of layer in SRS `dest_srid`
+    pub fn projected_layer_extent(&self, layer: &Layer, dest_srid: i32) -> Option<Extent> {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.projected_layer_extent(layer, dest_srid),
+            &Datasource::GeoJson(ref ds) => {
+                ds.layer_extent()
+                    .and_then(|ext| ds.extent_from_wgs84(&ext, dest_srid))
+            }
+            &Datasource::Spatialite(ref ds) => ds.projected_layer_extent(layer, dest_srid),
+            &Datasource::TileProxy(_) => None,
+        }
+    }
+    /// Projected extent
+    pub fn extent_from_wgs84(&self, extent: &Extent, dest_srid: i32) -> Option<Extent> {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.extent_from_wgs84(extent, dest_srid),
+            &Datasource::GeoJson(ref ds) => ds.extent_from_wgs84(extent, dest_srid),
+            &Datasource::Spatialite(ref ds) => ds.extent_from_wgs84(extent, dest_srid),
+            &Datasource::TileProxy(ref ds) => ds.extent_from_wgs84(extent, dest_srid),
+        }
+    }
+    /// MVT layer encoded by datasource
+    pub fn retrieve_mvt_layer(&self,
+                              layer: &Layer,
+                              extent: &Extent,
+                              zoom: u8,
+                              grid: &Grid)
+                              -> Result<Option<Vec<u8>>, TrexError> {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.retrieve_mvt_layer(layer, extent, zoom, grid),
+            &Datasource::GeoJson(_) |
+            &Datasource::Spatialite(_) |
+            &Datasource::TileProxy(_) => Ok(None),
+        }
+    }
+    /// Call `read` with layer index and feature for all layers of a batch
+    pub fn retrieve_batch_features<F>(&self,
+                                      layers: &[&Layer],
+                                      extent: &Extent,
+                                      zoom: u8,
+                                      grid: &Grid,
+                                      read: F)
+                                      -> Result<u64, TrexError>
+        where F: FnMut(usize, &Feature)
+    {
+        match self {
+            &Datasource::Postgis(ref pg) => {
+                pg.retrieve_batch_features(layers, extent, zoom, grid, read)
+            }
+            &Datasource::GeoJson(_) |
+            &Datasource::Spatialite(_) |
+            &Datasource::TileProxy(_) => {
+                Err(TrexError::Datasource("Batch queries not supported".to_string()))
+            }
+        }
+    }
+}
+
+impl DatasourceInput for Datasource {
+    fn retrieve_features<F>(&self,
+                            layer: &Layer,
+                            extent: &Extent,
+                            zoom: u8,
+                            grid: &Grid,
+                            read: F)
+                            -> Result<u64, TrexError>
+        where F: FnMut(&Feature)
+    {
+        match self {
+            &Datasource::Postgis(ref pg) => pg.retrieve_features(layer, extent, zoom, grid, read),
+            &Datasource::GeoJson(ref ds) => ds.retrieve_features(layer, extent, zoom, grid, read),
+            &Datasource::Spatialite(ref ds) => {
+                ds.retrieve_features(layer, extent, zoom, grid, read)
+            }
+            &Datasource::TileProxy(ref ds) => {
+                ds.retrieve_features(layer, extent, zoom, grid, read)
+            }
+        }
+    }
 }
 
 impl<'a> Config<'a, Datasource, DatasourceCfg> for Datasource {
@@ -25,6 +263,16 @@ impl<'a> Config<'a, Datasource, DatasourceCfg> for Datasource {
             "postgis" => {
                 PostgisInput::from_config(ds_cfg).and_then(|pg| Ok(Datasource::Postgis(pg)))
             }
+            "geojson" => {
+                GeoJsonInput::from_config(ds_cfg).and_then(|ds| Ok(Datasource::GeoJson(ds)))
+            }
+            "spatialite" => {
+                SpatialiteInput::from_config(ds_cfg)
+                    .and_then(|ds| Ok(Datasource::Spatialite(ds)))
+            }
+            "tileproxy" => {
+                TileProxyInput::from_config(ds_cfg).and_then(|ds| Ok(Datasource::TileProxy(ds)))
+            }
             _ => Err(format!("Unsupported datasource '{}'", ds_cfg.dstype)),
         }
     }
@@ -34,7 +282,117 @@ impl<'a> Config<'a, Datasource, DatasourceCfg> for Datasource {
     fn gen_runtime_config(&self) -> String {
         match self {
             &Datasource::Postgis(ref pg) => pg.gen_runtime_config(),
+            &Datasource::GeoJson(ref ds) => ds.gen_runtime_config(),
+            &Datasource::Spatialite(ref ds) => ds.gen_runtime_config(),
+            &Datasource::TileProxy(ref ds) => ds.gen_runtime_config(),
+        }
+    }
+}
+
+
+/// Named datasources
+pub struct Datasources {
+    pub datasources: BTreeMap<String, Datasource>,
+    /// Datasource of layers without datasource reference (first configured datasource)
+    pub default: Option<String>,
+}
+
+impl Datasources {
+    pub fn new() -> Datasources {
+        Datasources {
+            datasources: BTreeMap::new(),
+            default: None,
+        }
+    }
+    /// Add datasource. The first datasource added is the default datasource.
+    pub fn add(&mut self, name: &str, ds: Datasource) {
+        if self.default.is_none() {
+            self.default = Some(name.to_string());
+        }
+        self.datasources.insert(name.to_string(), ds);
+    }
+    pub fn get(&self, name: &str) -> Option<&Datasource> {
+        self.datasources.get(name)
+    }
+    /// Default datasource
+    pub fn default_datasource(&self) -> Option<&Datasource> {
+        self.default.as_ref().and_then(|name| self.datasources.get(name))
+    }
+    /// Datasource referenced by layer
+    pub fn layer_datasource(&self, layer: &Layer) -> Option<&Datasource> {
+        match layer.datasource {
+            Some(ref name) => self.datasources.get(name),
+            None => self.default_datasource(),
+        }
+    }
+    pub fn layer_datasource_mut(&mut self, layer: &Layer) -> Option<&mut Datasource> {
+        let name = layer.datasource.as_ref().or(self.default.as_ref()).cloned();
+        match name {
+            Some(name) => self.datasources.get_mut(&name),
+            None => None,
+        }
+    }
+    /// Connect all datasources
+    pub fn connect(&mut self) -> Result<(), String> {
+        for (name, ds) in self.datasources.iter_mut() {
+            ds.connect().map_err(|err| format!("Datasource '{}': {}", name, err))?;
+        }
+        Ok(())
+    }
+    /// Check connections of all datasources
+    pub fn check_connections(&self) -> Result<(), String> {
+        for (name, ds) in self.datasources.iter() {
+            ds.check_connection().map_err(|err| format!("Datasource '{}': {}", name, err))?;
+        }
+        Ok(())
+    }
+    /// Smallest connection pool size of PostGIS datasources
+    pub fn connection_pool_size(&self) -> Option<u32> {
+        self.datasources
+            .values()
+            .filter_map(|ds| match ds {
+                            &Datasource::Postgis(ref pg) => Some(pg.pool_size()),
+                            _ => None,
+                        })
+            .min()
+    }
+    /// Maximal pool size of all datasources
+    pub fn pool_size(&self) -> u32 {
+        self.datasources.values().map(|ds| ds.pool_size()).max().unwrap_or(1)
+    }
+}
+
+impl<'a> Config<'a, Datasources, Vec<DatasourceCfg>> for Datasources {
+    fn from_config(ds_cfgs: &Vec<DatasourceCfg>) -> Result<Self, String> {
+        let mut datasources = Datasources::new();
+        for ds_cfg in ds_cfgs {
+            let name = match ds_cfg.name {
+                Some(ref name) => name.clone(),
+                None if ds_cfgs.len() == 1 => "default".to_string(),
+                None => return Err("Datasource: missing field `name`".to_string()),
+            };
+            if datasources.get(&name).is_some() {
+                return Err(format!("Duplicate datasource '{}'", name));
+            }
+            datasources.add(&name, Datasource::from_config(ds_cfg)?);
+        }
+        Ok(datasources)
+    }
+    fn gen_config() -> String {
+        Datasource::gen_config()
+    }
+    fn gen_runtime_config(&self) -> String {
+        if self.datasources.len() == 1 && self.datasources.contains_key("default") {
+            return self.datasources["default"].gen_runtime_config();
         }
+        let mut config = String::new();
+        for (name, ds) in &self.datasources {
+            let ds_config = ds.gen_runtime_config()
+                .replace("[datasource]\n",
+                         &format!("[[datasource]]\nname = \"{}\"\n", name));
+            config.push_str(&ds_config);
+        }
+        config
     }
 }
 
@@ -56,6 +414,7 @@ fn test_datasource_from_config() {
         "#;
     let pg = match ds_from_config(toml).unwrap() {
         Datasource::Postgis(pg) => pg,
+        _ => panic!("PostGIS datasource expected"),
     };
     assert_eq!(pg.connection_url,
                "postgresql://pi@localhost/natural_earth_vectors");
@@ -78,7 +437,7 @@ fn test_datasource_config_errors() {
         type = "postgis"
         "#;
     assert_eq!(ds_from_config(toml).err(),
-               Some(" - missing field `url`".to_string()));
+               Some("PostGIS datasource: missing field `url`".to_string()));
 
     let toml = r#"
         #[datasource]
@@ -89,3 +448,68 @@ fn test_datasource_config_errors() {
                Some(" - invalid type: boolean `true`, expected a string for key `url`"
                         .to_string()));
 }
+
+#[test]
+fn test_named_datasources() {
+    use core::config::ApplicationCfg;
+    use core::parse_config;
+
+    let toml = r#"
+        [service.mvt]
+        viewer = true
+
+        [[datasource]]
+        name = "osm"
+        type = "postgis"
+        url = "postgresql://pi@localhost/osm"
+
+        [[datasource]]
+        name = "natural_earth"
+        type = "postgis"
+        url = "postgresql://pi@localhost/natural_earth_vectors"
+
+        [grid]
+        predefined = "web_mercator"
+
+        [[tileset]]
+        name = "points"
+
+        [[tileset.layer]]
+        name = "points"
+
+        [webserver]
+        bind = "127.0.0.1"
+        port = 6767
+        "#;
+    let config: ApplicationCfg = parse_config(toml.to_string(), "").unwrap();
+    let datasources = Datasources::from_config(&config.datasources).unwrap();
+    assert_eq!(datasources.datasources.len(), 2);
+    assert_eq!(datasources.default, Some("osm".to_string()));
+
+    let mut layer = Layer::new("points");
+    match datasources.layer_datasource(&layer) {
+        Some(&Datasource::Postgis(ref pg)) => {
+            assert_eq!(pg.connection_url, "postgresql://pi@localhost/osm")
+        }
+        _ => panic!("PostGIS datasource expected"),
+    }
+    layer.datasource = Some("natural_earth".to_string());
+    match datasources.layer_datasource(&layer) {
+        Some(&Datasource::Postgis(ref pg)) => {
+            assert_eq!(pg.connection_url,
+                       "postgresql://pi@localhost/natural_earth_vectors")
+        }
+        _ => panic!("PostGIS datasource expected"),
+    }
+    layer.datasource = Some("unknown".to_string());
+    assert!(datasources.layer_datasource(&layer).is_none());
+
+    let runtime_config = datasources.gen_runtime_config();
+    assert!(runtime_config.contains("[[datasource]]\nname = \"osm\""));
+
+    let config: ApplicationCfg = parse_config(toml.replace("\"natural_earth\"", "\"osm\""),
+                                              "")
+            .unwrap();
+    assert_eq!(Datasources::from_config(&config.datasources).err(),
+               Some("Duplicate datasource 'osm'".to_string()));
+}
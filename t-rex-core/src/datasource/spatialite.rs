@@ -0,0 +1,782 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use datasource::DatasourceInput;
+use core::error::TrexError;
+use core::feature::{Feature, FeatureAttr, FeatureAttrValType};
+use core::geom::{self, GeometryType};
+use core::grid::Extent;
+use core::grid::Grid;
+use core::layer::Layer;
+use core::Config;
+use core::config::DatasourceCfg;
+use datasource::postgis::{QueryParam, SqlQuery};
+use postgis::ewkb;
+use r2d2::{self, ManageConnection};
+use rusqlite::{self, Connection};
+use rusqlite::types::{ToSql, Value};
+use num_cpus;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+
+// SpatiaLite BLOB-Geometry markers
+// https://www.gaia-gis.it/gaia-sins/BLOB-Geometry.html
+const BLOB_START: u8 = 0x00;
+const BLOB_MBR_END: u8 = 0x7C;
+const BLOB_ENTITY: u8 = 0x69;
+const BLOB_END: u8 = 0xFE;
+/// Offset of class type (after header and MBR)
+const BLOB_HEADER_SIZE: usize = 39;
+
+/// Reader for SpatiaLite BLOB-Geometries
+struct BlobReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl<'a> BlobReader<'a> {
+    fn read_u8(&mut self) -> Result<u8, String> {
+        let byte = *self.data
+                        .get(self.pos)
+                        .ok_or("Unexpected end of SpatiaLite geometry".to_string())?;
+        self.pos += 1;
+        Ok(byte)
+    }
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos + len > self.data.len() {
+            return Err("Unexpected end of SpatiaLite geometry".to_string());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn read_u64(&mut self, len: usize) -> Result<u64, String> {
+        let bytes = self.read_bytes(len)?;
+        let mut val = 0u64;
+        for i in 0..len {
+            let byte = if self.little_endian {
+                bytes[len - 1 - i]
+            } else {
+                bytes[i]
+            };
+            val = (val << 8) | byte as u64;
+        }
+        Ok(val)
+    }
+    fn read_i32(&mut self) -> Result<i32, String> {
+        self.read_u64(4).map(|val| val as u32 as i32)
+    }
+    fn read_f64(&mut self) -> Result<f64, String> {
+        self.read_u64(8).map(|val| f64::from_bits(val))
+    }
+    fn read_count(&mut self) -> Result<usize, String> {
+        match self.read_i32()? {
+            n if n >= 0 => Ok(n as usize),
+            n => Err(format!("Invalid element count {} in SpatiaLite geometry", n)),
+        }
+    }
+    /// Class type of (sub)geometry with number of coordinates per vertex
+    fn read_class(&mut self) -> Result<(i32, usize), String> {
+        let class = self.read_i32()?;
+        if class >= 1000000 {
+            return Err(format!("Compressed SpatiaLite geometries are not supported (class type {})",
+                               class));
+        }
+        let dims = match class / 1000 {
+            0 => 2, // XY
+            1 | 2 => 3, // XYZ or XYM
+            3 => 4, // XYZM
+            _ => return Err(format!("Unknown SpatiaLite geometry class type {}", class)),
+        };
+        Ok((class % 1000, dims))
+    }
+    /// Class type of an entity within a multi geometry or collection
+    fn read_entity(&mut self) -> Result<(i32, usize), String> {
+        if self.read_u8()? != BLOB_ENTITY {
+            return Err("Invalid entity marker in SpatiaLite geometry".to_string());
+        }
+        self.read_class()
+    }
+    fn point(&mut self, dims: usize, srid: Option<i32>) -> Result<geom::Point, String> {
+        let x = self.read_f64()?;
+        let y = self.read_f64()?;
+        // Z and M values are ignored
+        for _ in 2..dims {
+            self.read_f64()?;
+        }
+        Ok(geom::Point::new(x, y, srid))
+    }
+    fn line(&mut self, dims: usize, srid: Option<i32>) -> Result<geom::LineString, String> {
+        let n = self.read_count()?;
+        let points = (0..n)
+            .map(|_| self.point(dims, None))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ewkb::LineString {
+               points: points,
+               srid: srid,
+           })
+    }
+    fn polygon(&mut self, dims: usize, srid: Option<i32>) -> Result<geom::Polygon, String> {
+        let n = self.read_count()?;
+        let rings = (0..n)
+            .map(|_| self.line(dims, None))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ewkb::Polygon {
+               rings: rings,
+               srid: srid,
+           })
+    }
+    fn entities<T, F>(&mut self, class: i32, mut read: F) -> Result<Vec<T>, String>
+        where F: FnMut(&mut Self, usize) -> Result<T, String>
+    {
+        let n = self.read_count()?;
+        let mut entities = Vec::with_capacity(n);
+        for _ in 0..n {
+            let (entity_class, dims) = self.read_entity()?;
+            if entity_class != class {
+                return Err(format!("Unexpected class type {} in SpatiaLite multi geometry",
+                                   entity_class));
+            }
+            entities.push(read(self, dims)?);
+        }
+        Ok(entities)
+    }
+    fn geometry(&mut self,
+                class: i32,
+                dims: usize,
+                srid: Option<i32>)
+                -> Result<GeometryType, String> {
+        match class {
+            1 => self.point(dims, srid).map(|g| GeometryType::Point(g)),
+            2 => self.line(dims, srid).map(|g| GeometryType::LineString(g)),
+            3 => self.polygon(dims, srid).map(|g| GeometryType::Polygon(g)),
+            4 => {
+                let points = self.entities(1, |r, dims| r.point(dims, None))?;
+                Ok(GeometryType::MultiPoint(ewkb::MultiPoint {
+                                                points: points,
+                                                srid: srid,
+                                            }))
+            }
+            5 => {
+                let lines = self.entities(2, |r, dims| r.line(dims, None))?;
+                Ok(GeometryType::MultiLineString(ewkb::MultiLineString {
+                                                     lines: lines,
+                                                     srid: srid,
+                                                 }))
+            }
+            6 => {
+                let polygons = self.entities(3, |r, dims| r.polygon(dims, None))?;
+                Ok(GeometryType::MultiPolygon(ewkb::MultiPolygon {
+                                                  polygons: polygons,
+                                                  srid: srid,
+                                              }))
+            }
+            7 => {
+                let n = self.read_count()?;
+                let mut geometries = Vec::with_capacity(n);
+                for _ in 0..n {
+                    let (class, dims) = self.read_entity()?;
+                    geometries.push(self.geometry(class, dims, None)?.into());
+                }
+                Ok(GeometryType::GeometryCollection(ewkb::GeometryCollection {
+                                                        geometries: geometries,
+                                                        srid: srid,
+                                                    }))
+            }
+            _ => Err(format!("Unknown SpatiaLite geometry class type {}", class)),
+        }
+    }
+}
+
+impl GeometryType {
+    /// Decode SpatiaLite BLOB-Geometry
+    pub fn from_spatialite_blob(blob: &[u8]) -> Result<GeometryType, String> {
+        if blob.len() < BLOB_HEADER_SIZE + 5 || blob[0] != BLOB_START ||
+           blob[BLOB_HEADER_SIZE - 1] != BLOB_MBR_END ||
+           blob[blob.len() - 1] != BLOB_END {
+            return Err("Invalid SpatiaLite geometry".to_string());
+        }
+        let mut reader = BlobReader {
+            data: &blob[..blob.len() - 1],
+            pos: 2,
+            little_endian: blob[1] == 0x01,
+        };
+        let srid = reader.read_i32()?;
+        // Skip MBR
+        reader.pos = BLOB_HEADER_SIZE;
+        let (class, dims) = reader.read_class()?;
+        reader.geometry(class, dims, Some(srid))
+    }
+}
+
+
+/// Geometry type name of SpatiaLite geometry_columns entry
+fn geometry_type_name(type_code: i32) -> &'static str {
+    match type_code % 1000 {
+        1 => "POINT",
+        2 => "LINESTRING",
+        3 => "POLYGON",
+        4 => "MULTIPOINT",
+        5 => "MULTILINESTRING",
+        6 => "MULTIPOLYGON",
+        7 => "GEOMETRYCOLLECTION",
+        _ => "GEOMETRY",
+    }
+}
+
+struct FeatureRow<'a> {
+    layer: &'a Layer,
+    columns: &'a [String],
+    values: Vec<Value>,
+}
+
+impl<'a> FeatureRow<'a> {
+    /// Index of geometry column (the last one with the geometry field name)
+    fn geometry_index(&self) -> Option<usize> {
+        let geom_name = self.layer.geometry_field.as_ref();
+        self.columns.iter().rposition(|col| Some(col) == geom_name)
+    }
+}
+
+impl<'a> Feature for FeatureRow<'a> {
+    fn fid(&self) -> Option<u64> {
+        self.layer
+            .fid_field
+            .as_ref()
+            .and_then(|fid| self.columns.iter().position(|col| col == fid))
+            .and_then(|idx| match self.values[idx] {
+                          Value::Integer(fid) if fid >= 0 => Some(fid as u64),
+                          _ => None, // NULL or negative value
+                      })
+    }
+    fn attributes(&self) -> Vec<FeatureAttr> {
+        let geom_idx = self.geometry_index();
+        let mut attrs = Vec::new();
+        for (i, col) in self.columns.iter().enumerate() {
+            if Some(i) == geom_idx || Some(col) == self.layer.geometry_field.as_ref() {
+                continue;
+            }
+            if let Some(ref fields) = self.layer.fields {
+                if !fields.contains(col) {
+                    continue;
+                }
+            }
+            let value = match self.values[i] {
+                Value::Integer(v) => FeatureAttrValType::Int(v),
+                Value::Real(v) => FeatureAttrValType::Double(v),
+                Value::Text(ref v) => FeatureAttrValType::String(v.clone()),
+                // Skip NULL values and BLOBs
+                _ => continue,
+            };
+            attrs.push(FeatureAttr {
+                           key: col.clone(),
+                           value: value,
+                       });
+        }
+        attrs
+    }
+    fn geometry(&self) -> Result<GeometryType, String> {
+        let geom = match self.geometry_index().map(|idx| &self.values[idx]) {
+            Some(&Value::Blob(ref blob)) => GeometryType::from_spatialite_blob(blob),
+            Some(&Value::Null) => Err("NULL geometry".to_string()),
+            Some(_) => Err("Geometry column is not a BLOB".to_string()),
+            None => Err("Column not found".to_string()),
+        };
+        if let Err(ref err) = geom {
+            error!("Layer '{}': {}", self.layer.name, err);
+        }
+        geom
+    }
+}
+
+/// Execute feature query and call `read` for each row
+fn read_features<F>(conn: &Connection,
+                    sql: &str,
+                    params: &[&ToSql],
+                    layer: &Layer,
+                    query_limit: u32,
+                    read: &mut F)
+                    -> rusqlite::Result<u64>
+    where F: FnMut(&Feature)
+{
+    let mut stmt = conn.prepare_cached(sql)?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|col| col.to_string()).collect();
+    let mut rows = stmt.query(params)?;
+    let mut cnt: u64 = 0;
+    while let Some(row) = rows.next() {
+        let row = row?;
+        let values = (0..columns.len())
+            .map(|i| row.get_checked(i as i32))
+            .collect::<rusqlite::Result<Vec<Value>>>()?;
+        let feature = FeatureRow {
+            layer: layer,
+            columns: &columns,
+            values: values,
+        };
+        read(&feature);
+        cnt += 1;
+        if cnt == query_limit as u64 {
+            info!("Feature count limited (query_limit={})", cnt);
+            break;
+        }
+    }
+    Ok(cnt)
+}
+
+/// Geometry column entry of geometry_columns
+struct GeometryColumn {
+    geometry_type: i32,
+    srid: i32,
+    spatial_index: bool,
+}
+
+/// r2d2 connection manager opening SpatiaLite enabled SQLite connections
+struct SpatialiteConnectionManager {
+    path: String,
+}
+
+impl ManageConnection for SpatialiteConnectionManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.load_extension_enable()?;
+        let loaded = conn.load_extension("mod_spatialite", None);
+        conn.load_extension_disable()?;
+        loaded.map(|_| conn)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> rusqlite::Result<()> {
+        conn.execute_batch("")
+    }
+
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
+
+pub struct SpatialiteInput {
+    pub path: String,
+    conn_pool: Option<r2d2::Pool<SpatialiteConnectionManager>>,
+    // Queries for all layers and zoom levels
+    queries: BTreeMap<String, BTreeMap<u8, SqlQuery>>,
+}
+
+impl SpatialiteInput {
+    pub fn new(path: &str) -> SpatialiteInput {
+        SpatialiteInput {
+            path: path.to_string(),
+            conn_pool: None,
+            queries: BTreeMap::new(),
+        }
+    }
+    /// New instance with a connection pool on the database with loaded SpatiaLite extension
+    pub fn connected(&self) -> Result<SpatialiteInput, String> {
+        if !Path::new(&self.path).is_file() {
+            return Err(format!("SpatiaLite database '{}' not found", self.path));
+        }
+        let manager = SpatialiteConnectionManager { path: self.path.clone() };
+        // Open a first connection to report the cause of failures
+        manager
+            .connect()
+            .map_err(|e| format!("Error opening SpatiaLite database '{}': {}", self.path, e))?;
+        let pool_size = self.pool_size();
+        debug!("Connection pool size: {}", pool_size);
+        let config = r2d2::Config::builder().pool_size(pool_size).build();
+        let pool = r2d2::Pool::new(config, manager)
+            .map_err(|e| format!("Error opening SpatiaLite database '{}': {}", self.path, e))?;
+        Ok(SpatialiteInput {
+               path: self.path.clone(),
+               conn_pool: Some(pool),
+               queries: BTreeMap::new(),
+           })
+    }
+    pub fn pool_size(&self) -> u32 {
+        num_cpus::get() as u32
+    }
+    /// Check database connection with a trivial query
+    pub fn check_connection(&self) -> Result<(), String> {
+        self.try_with_conn(|conn| conn.execute_batch("SELECT 1"))
+    }
+    fn try_with_conn<T, F>(&self, f: F) -> Result<T, String>
+        where F: FnOnce(&Connection) -> rusqlite::Result<T>
+    {
+        let pool = self.conn_pool
+            .as_ref()
+            .ok_or(format!("SpatiaLite database '{}' not connected", self.path))?;
+        let conn = pool.get()
+            .map_err(|e| format!("SpatiaLite database '{}': {}", self.path, e))?;
+        f(&conn).map_err(|e| format!("SpatiaLite database '{}': {}", self.path, e))
+    }
+    fn with_conn<T, F>(&self, f: F) -> Option<T>
+        where F: FnOnce(&Connection) -> rusqlite::Result<T>
+    {
+        match self.try_with_conn(f) {
+            Ok(res) => Some(res),
+            Err(e) => {
+                error!("{}", e);
+                None
+            }
+        }
+    }
+    fn geometry_column(&self, layer: &Layer) -> Option<GeometryColumn> {
+        let table = layer.table_name.as_ref()?;
+        let field = layer.geometry_field.as_ref()?;
+        let sql = "SELECT geometry_type, srid, spatial_index_enabled FROM geometry_columns WHERE Lower(f_table_name) = Lower(?1) AND Lower(f_geometry_column) = Lower(?2)";
+        self.with_conn(|conn| {
+            conn.query_row(sql, &[table, field], |row| {
+                GeometryColumn {
+                    geometry_type: row.get(0),
+                    srid: row.get(1),
+                    spatial_index: row.get::<_, i32>(2) == 1,
+                }
+            })
+        })
+    }
+    /// Fill in missing geometry type and SRID of layer
+    pub fn detect_layer_geometry(&self, layer: &mut Layer) {
+        if layer.geometry_type.is_some() && layer.srid.is_some() {
+            return;
+        }
+        match self.geometry_column(layer) {
+            Some(geom_col) => {
+                if layer.geometry_type.is_none() {
+                    let geomtype = geometry_type_name(geom_col.geometry_type).to_string();
+                    info!("Layer '{}': detected geometry type {}", layer.name, geomtype);
+                    layer.geometry_type = Some(geomtype);
+                }
+                if layer.srid.is_none() {
+                    info!("Layer '{}': detected SRID {}", layer.name, geom_col.srid);
+                    layer.srid = Some(geom_col.srid);
+                }
+            }
+            None => {
+                error!("Layer '{}': geometry column not found in geometry_columns",
+                       layer.name)
+            }
+        }
+    }
+    /// Return column field names with TileJSON field type (Number, Boolean or String)
+    pub fn detect_field_types(&self,
+                              layer: &Layer,
+                              sql: Option<&String>)
+                              -> Vec<(String, String)> {
+        let query = match sql {
+            Some(sql) => {
+                format!("SELECT * FROM ({}) LIMIT 0",
+                        sql.replace("!bbox!", "NULL")
+                            .replace("!zoom!", "0")
+                            .replace("!pixel_width!", "0")
+                            .replace("!scale_denominator!", "0"))
+            }
+            None => {
+                format!("SELECT * FROM {} LIMIT 0",
+                        layer.table_name.as_ref().unwrap_or(&layer.name))
+            }
+        };
+        let columns = self.with_conn(|conn| {
+                let stmt = conn.prepare(&query)?;
+                Ok(stmt.column_names().iter().map(|col| col.to_string()).collect::<Vec<_>>())
+            })
+            .unwrap_or_default();
+        // Declared column types of table
+        let mut decltypes = BTreeMap::new();
+        if sql.is_none() {
+            let pragma = format!("PRAGMA table_info({})",
+                                 layer.table_name.as_ref().unwrap_or(&layer.name));
+            self.with_conn(|conn| {
+                let mut stmt = conn.prepare(&pragma)?;
+                let mut rows = stmt.query(&[])?;
+                while let Some(row) = rows.next() {
+                    let row = row?;
+                    decltypes.insert(row.get::<_, String>(1),
+                                     row.get::<_, String>(2).to_uppercase());
+                }
+                Ok(())
+            });
+        }
+        columns
+            .into_iter()
+            .filter(|col| Some(col) != layer.geometry_field.as_ref())
+            .filter(|col| match layer.fields {
+                        // Keep fid column for feature ids
+                        Some(ref fields) => {
+                            fields.contains(col) || layer.fid_field.as_ref() == Some(col)
+                        }
+                        None => true,
+                    })
+            .map(|col| {
+                let field_type = match decltypes.get(&col).map(|t| t.as_str()) {
+                    Some(t) if t.contains("INT") || t.contains("REAL") || t.contains("FLOA") ||
+                               t.contains("DOUB") || t.contains("NUMERIC") => "Number",
+                    Some("BOOLEAN") => "Boolean",
+                    _ => "String",
+                };
+                (col, field_type.to_string())
+            })
+            .collect()
+    }
+    /// Return column field names - without geometry column
+    pub fn detect_data_columns(&self,
+                               layer: &Layer,
+                               sql: Option<&String>)
+                               -> Vec<(String, String)> {
+        self.detect_field_types(layer, sql)
+            .into_iter()
+            .map(|(name, _)| (name, String::new()))
+            .collect()
+    }
+    /// Detect extent of layer (in WGS84)
+    pub fn layer_extent(&self, layer: &Layer) -> Option<Extent> {
+        self.projected_layer_extent(layer, 4326)
+    }
+    /// Detect extent of layer in SRS `dest_srid`
+    pub fn projected_layer_extent(&self, layer: &Layer, dest_srid: i32) -> Option<Extent> {
+        let table = layer.table_name.as_ref()?;
+        let field = layer.geometry_field.as_ref()?;
+        if !layer.query.is_empty() || layer.srid.unwrap_or(0) <= 0 {
+            info!("Couldn't detect extent of layer {}, because of custom queries or an unknown SRID",
+                  layer.name);
+            return None;
+        }
+        let sql = format!("SELECT MbrMinX(ext), MbrMinY(ext), MbrMaxX(ext), MbrMaxY(ext) FROM (SELECT ST_Transform(SetSRID(Extent({}), {}), {}) AS ext FROM {})",
+                          field,
+                          layer.srid.unwrap(),
+                          dest_srid,
+                          table);
+        self.extent_query(&sql)
+    }
+    /// Projected extent
+    pub fn extent_from_wgs84(&self, extent: &Extent, dest_srid: i32) -> Option<Extent> {
+        let sql = format!("SELECT MbrMinX(ext), MbrMinY(ext), MbrMaxX(ext), MbrMaxY(ext) FROM (SELECT ST_Transform(BuildMbr({}, {}, {}, {}, 4326), {}) AS ext)",
+                          extent.minx,
+                          extent.miny,
+                          extent.maxx,
+                          extent.maxy,
+                          dest_srid);
+        self.extent_query(&sql)
+    }
+    fn extent_query(&self, sql: &str) -> Option<Extent> {
+        self.with_conn(|conn| {
+                conn.query_row(sql, &[], |row| {
+                    Some(Extent {
+                             minx: row.get_checked(0).ok()?,
+                             miny: row.get_checked(1).ok()?,
+                             maxx: row.get_checked(2).ok()?,
+                             maxy: row.get_checked(3).ok()?,
+                         })
+                })
+            })
+            .and_then(|ext| ext)
+    }
+    /// Build feature query with `!bbox!` placeholder
+    pub fn build_query_sql(&self,
+                           layer: &Layer,
+                           grid_srid: i32,
+                           sql: Option<&String>,
+                           spatial_index: bool)
+                           -> Option<String> {
+        let geom_name = layer.geometry_field.as_ref()?;
+        let layer_srid = layer.srid.unwrap_or(grid_srid);
+        if let Some(sql) = sql {
+            if sql.contains("!bbox!") {
+                return Some(sql.clone());
+            }
+            return Some(format!("SELECT * FROM ({}) AS _q WHERE MbrIntersects({}, !bbox!)",
+                                sql,
+                                geom_name));
+        }
+        let table = layer.table_name.as_ref()?;
+        let mut geom_expr = geom_name.clone();
+        if layer.label_point.unwrap_or(false) {
+            geom_expr = format!("ST_PointOnSurface({})", geom_expr);
+        }
+        if layer_srid != grid_srid {
+            geom_expr = format!("ST_Transform({}, {})", geom_expr, grid_srid);
+        }
+        let derived_geom = geom_expr != *geom_name;
+        if derived_geom {
+            geom_expr = format!("{} AS {}", geom_expr, geom_name);
+        }
+        let select_list = match layer.fields {
+            Some(ref fields) => {
+                let mut cols = fields.clone();
+                if let Some(ref fid) = layer.fid_field {
+                    if !cols.contains(fid) {
+                        cols.push(fid.clone());
+                    }
+                }
+                cols.push(geom_expr);
+                cols.join(", ")
+            }
+            // Derived geometry is selected in addition to the original column
+            None if derived_geom => format!("*, {}", geom_expr),
+            None => "*".to_string(),
+        };
+        let filter = if spatial_index {
+            format!("ROWID IN (SELECT ROWID FROM SpatialIndex WHERE f_table_name = '{}' AND f_geometry_column = '{}' AND search_frame = !bbox!)",
+                    table,
+                    geom_name)
+        } else {
+            format!("MbrIntersects({}, !bbox!)", geom_name)
+        };
+        Some(format!("SELECT {} FROM {} WHERE {}{}",
+                     select_list,
+                     table,
+                     filter,
+                     layer.filter_clause()))
+    }
+    /// Build feature query of zoom level with numbered parameters
+    pub fn build_query(&self,
+                       layer: &Layer,
+                       grid_srid: i32,
+                       zoom: u8,
+                       spatial_index: bool)
+                       -> Option<SqlQuery> {
+        let mut sql = self.build_query_sql(layer, grid_srid, layer.query(zoom), spatial_index)?;
+        sql.push_str(&layer.order_by_clause());
+        if let Some(limit) = layer.query_limit(zoom) {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+        let mut query = SqlQuery {
+            sql: sql,
+            params: Vec::new(),
+        };
+        Self::replace_params(&mut query, &Self::bbox_expr(layer, grid_srid));
+        Some(query)
+    }
+    /// Replace variables (!bbox!, !zoom!, etc.) with numbered parameters
+    fn replace_params(query: &mut SqlQuery, bbox_expr: &str) {
+        let mut numvars = 0;
+        if query.sql.contains("!bbox!") {
+            query.params.push(QueryParam::Bbox);
+            numvars += 4;
+            query.sql = query.sql.replace("!bbox!", bbox_expr);
+        }
+        // replace e.g. !zoom! with ?5
+        for (var, par) in vec![("!zoom!", QueryParam::Zoom),
+                               ("!pixel_width!", QueryParam::PixelWidth),
+                               ("!scale_denominator!", QueryParam::ScaleDenominator)] {
+            if query.sql.contains(var) {
+                query.params.push(par);
+                numvars += 1;
+                query.sql = query.sql.replace(var, &format!("?{}", numvars));
+            }
+        }
+    }
+    pub fn prepare_queries(&mut self, layer: &Layer, grid_srid: i32) {
+        let spatial_index = self.geometry_column(layer)
+            .map(|geom_col| geom_col.spatial_index)
+            .unwrap_or(false);
+        if layer.table_name.is_some() && !spatial_index {
+            warn!("Layer '{}': no spatial index found", layer.name);
+        }
+        let mut queries = BTreeMap::new();
+        for zoom in layer.minzoom()..layer.maxzoom() + 1 {
+            if let Some(query) = self.build_query(layer, grid_srid, zoom, spatial_index) {
+                queries.insert(zoom, query);
+            }
+        }
+        self.queries.insert(layer.name.clone(), queries);
+    }
+    /// Bounding box expression with tile extent parameters ?1 to ?4 in layer SRID
+    fn bbox_expr(layer: &Layer, grid_srid: i32) -> String {
+        let layer_srid = layer.srid.unwrap_or(grid_srid);
+        let mbr = format!("BuildMbr(?1, ?2, ?3, ?4, {})", grid_srid);
+        if layer_srid != grid_srid {
+            format!("ST_Transform({}, {})", mbr, layer_srid)
+        } else {
+            mbr
+        }
+    }
+}
+
+impl DatasourceInput for SpatialiteInput {
+    fn retrieve_features<F>(&self,
+                            layer: &Layer,
+                            extent: &Extent,
+                            zoom: u8,
+                            grid: &Grid,
+                            mut read: F)
+                            -> Result<u64, TrexError>
+        where F: FnMut(&Feature)
+    {
+        let query = match self.queries.get(&layer.name).and_then(|q| q.get(&zoom)) {
+            Some(query) => query,
+            None => return Ok(0),
+        };
+        let pool = self.conn_pool
+            .as_ref()
+            .ok_or(TrexError::Connection(format!("SpatiaLite database '{}' not connected",
+                                                 self.path)))?;
+        let conn = pool.get()
+            .map_err(|e| {
+                         TrexError::Connection(format!("SpatiaLite database '{}': {}",
+                                                       self.path,
+                                                       e))
+                     })?;
+        debug!("Reading features in layer {}", layer.name);
+        let zoom_param = zoom as i32;
+        let pixel_width = grid.pixel_width(zoom);
+        let scale_denominator = grid.scale_denominator(zoom);
+        let mut params: Vec<&ToSql> = Vec::new();
+        for param in &query.params {
+            match param {
+                &QueryParam::Bbox => {
+                    let mut bbox: Vec<&ToSql> =
+                        vec![&extent.minx, &extent.miny, &extent.maxx, &extent.maxy];
+                    params.append(&mut bbox);
+                }
+                &QueryParam::Zoom => params.push(&zoom_param),
+                &QueryParam::PixelWidth => params.push(&pixel_width),
+                &QueryParam::ScaleDenominator => params.push(&scale_denominator),
+            }
+        }
+        let query_limit = layer.query_limit(zoom).unwrap_or(0);
+        let result = read_features(&conn, &query.sql, &params, layer, query_limit, &mut read);
+        match result {
+            Ok(cnt) => {
+                debug!("Feature count: {}", cnt);
+                Ok(cnt)
+            }
+            Err(e) => {
+                error!("Query: {}", query.sql);
+                Err(TrexError::Datasource(format!("Layer '{}': {}", layer.name, e)))
+            }
+        }
+    }
+}
+
+impl<'a> Config<'a, SpatialiteInput, DatasourceCfg> for SpatialiteInput {
+    fn from_config(ds_cfg: &DatasourceCfg) -> Result<Self, String> {
+        let path = ds_cfg
+            .path
+            .as_ref()
+            .ok_or("SpatiaLite datasource: missing field `path`".to_string())?;
+        Ok(SpatialiteInput::new(path))
+    }
+
+    fn gen_config() -> String {
+        let toml = r#"
+[datasource]
+type = "spatialite"
+# SpatiaLite database file
+path = "data.sqlite"
+"#;
+        toml.to_string()
+    }
+
+    fn gen_runtime_config(&self) -> String {
+        format!(r#"
+[datasource]
+type = "spatialite"
+path = "{}"
+"#,
+                self.path)
+    }
+}
@@ -0,0 +1,203 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use datasource::spatialite::SpatialiteInput;
+use datasource::postgis::QueryParam;
+use core::geom::GeometryType;
+use core::layer::{Layer, LayerQuery};
+
+
+/// Build little endian SpatiaLite BLOB-Geometry (MBR not filled in)
+fn blob(srid: i32, class: i32, body: &[u8]) -> Vec<u8> {
+    let mut blob = vec![0x00, 0x01];
+    blob.extend(i32_le(srid));
+    blob.extend(vec![0; 32]);
+    blob.push(0x7C);
+    blob.extend(i32_le(class));
+    blob.extend(body);
+    blob.push(0xFE);
+    blob
+}
+
+fn i32_le(v: i32) -> Vec<u8> {
+    (0..4).map(|i| (v >> (i * 8)) as u8).collect()
+}
+
+fn f64_le(v: f64) -> Vec<u8> {
+    let bits = v.to_bits();
+    (0..8).map(|i| (bits >> (i * 8)) as u8).collect()
+}
+
+fn f64_be(v: f64) -> Vec<u8> {
+    let bits = v.to_bits();
+    (0..8).rev().map(|i| (bits >> (i * 8)) as u8).collect()
+}
+
+fn coords(values: &[f64]) -> Vec<u8> {
+    values.iter().flat_map(|v| f64_le(*v)).collect()
+}
+
+#[test]
+fn test_decode_point() {
+    let geom = GeometryType::from_spatialite_blob(&blob(3857, 1, &coords(&[10.0, 20.0])));
+    assert_eq!(&*format!("{:?}", geom),
+               "Ok(Point(Point { x: 10.0, y: 20.0, srid: Some(3857) }))");
+
+    // Big endian
+    let mut be = vec![0x00, 0x00, 0x00, 0x00, 0x10, 0xE6];
+    be.extend(vec![0; 32]);
+    be.extend(vec![0x7C, 0x00, 0x00, 0x00, 0x01]);
+    be.extend(f64_be(10.0));
+    be.extend(f64_be(20.0));
+    be.push(0xFE);
+    let geom = GeometryType::from_spatialite_blob(&be);
+    assert_eq!(&*format!("{:?}", geom),
+               "Ok(Point(Point { x: 10.0, y: 20.0, srid: Some(4326) }))");
+}
+
+#[test]
+fn test_decode_linestring_z() {
+    let mut body = i32_le(2);
+    body.extend(coords(&[0.0, 0.0, 100.0, 1.0, 2.0, 200.0]));
+    let geom = GeometryType::from_spatialite_blob(&blob(3857, 1002, &body));
+    assert_eq!(&*format!("{:?}", geom),
+               "Ok(LineString(LineStringT { points: [Point { x: 0.0, y: 0.0, srid: None }, Point { x: 1.0, y: 2.0, srid: None }], srid: Some(3857) }))");
+}
+
+#[test]
+fn test_decode_multipolygon() {
+    let mut body = i32_le(1);
+    body.push(0x69);
+    body.extend(i32_le(3));
+    body.extend(i32_le(1)); // rings
+    body.extend(i32_le(4)); // points
+    body.extend(coords(&[0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 0.0]));
+    let geom = GeometryType::from_spatialite_blob(&blob(3857, 6, &body));
+    assert_eq!(&*format!("{:?}", geom),
+               "Ok(MultiPolygon(MultiPolygonT { polygons: [PolygonT { rings: [LineStringT { points: [Point { x: 0.0, y: 0.0, srid: None }, Point { x: 1.0, y: 0.0, srid: None }, Point { x: 1.0, y: 1.0, srid: None }, Point { x: 0.0, y: 0.0, srid: None }], srid: None }], srid: None }], srid: Some(3857) }))");
+}
+
+#[test]
+fn test_decode_collection() {
+    let mut body = i32_le(2);
+    body.push(0x69);
+    body.extend(i32_le(1));
+    body.extend(coords(&[1.0, 2.0]));
+    body.push(0x69);
+    body.extend(i32_le(2));
+    body.extend(i32_le(2));
+    body.extend(coords(&[0.0, 0.0, 1.0, 1.0]));
+    let geom = GeometryType::from_spatialite_blob(&blob(3857, 7, &body)).unwrap();
+    match geom {
+        GeometryType::GeometryCollection(coll) => {
+            assert_eq!(coll.geometries.len(), 2);
+            assert_eq!(coll.srid, Some(3857));
+        }
+        _ => panic!("GeometryCollection expected"),
+    }
+}
+
+#[test]
+fn test_decode_errors() {
+    assert_eq!(GeometryType::from_spatialite_blob(&[0x01, 0x02]).err(),
+               Some("Invalid SpatiaLite geometry".to_string()));
+    // Truncated coordinates
+    assert_eq!(GeometryType::from_spatialite_blob(&blob(3857, 1, &coords(&[10.0]))).err(),
+               Some("Unexpected end of SpatiaLite geometry".to_string()));
+    // Compressed linestring
+    let mut body = i32_le(2);
+    body.extend(coords(&[0.0, 0.0, 1.0, 1.0]));
+    assert_eq!(GeometryType::from_spatialite_blob(&blob(3857, 1000002, &body)).err(),
+               Some("Compressed SpatiaLite geometries are not supported (class type 1000002)"
+                        .to_string()));
+}
+
+#[test]
+fn test_feature_query() {
+    let ds = SpatialiteInput::new("natural_earth.sqlite");
+    let mut layer = Layer::new("points");
+    layer.table_name = Some("ne_10m_populated_places".to_string());
+    layer.geometry_field = Some("geometry".to_string());
+    layer.srid = Some(3857);
+
+    assert_eq!(ds.build_query_sql(&layer, 3857, None, true),
+               Some("SELECT * FROM ne_10m_populated_places WHERE ROWID IN (SELECT ROWID FROM SpatialIndex WHERE f_table_name = 'ne_10m_populated_places' AND f_geometry_column = 'geometry' AND search_frame = !bbox!)".to_string()));
+    assert_eq!(ds.build_query_sql(&layer, 3857, None, false),
+               Some("SELECT * FROM ne_10m_populated_places WHERE MbrIntersects(geometry, !bbox!)"
+                        .to_string()));
+
+    layer.srid = Some(4326);
+    layer.fid_field = Some("id".to_string());
+    layer.fields = Some(vec!["name".to_string()]);
+    assert_eq!(ds.build_query_sql(&layer, 3857, None, false),
+               Some("SELECT name, id, ST_Transform(geometry, 3857) AS geometry FROM ne_10m_populated_places WHERE MbrIntersects(geometry, !bbox!)".to_string()));
+
+    let sql = "SELECT name, geometry FROM places WHERE MbrIntersects(geometry, !bbox!)"
+        .to_string();
+    assert_eq!(ds.build_query_sql(&layer, 3857, Some(&sql), false),
+               Some(sql.clone()));
+}
+
+#[test]
+fn test_label_point_query() {
+    let ds = SpatialiteInput::new("natural_earth.sqlite");
+    let mut layer = Layer::new("countries");
+    layer.table_name = Some("ne_10m_admin_0_countries".to_string());
+    layer.geometry_field = Some("geometry".to_string());
+    layer.srid = Some(3857);
+    layer.label_point = Some(true);
+
+    assert_eq!(ds.build_query_sql(&layer, 3857, None, false),
+               Some("SELECT *, ST_PointOnSurface(geometry) AS geometry FROM ne_10m_admin_0_countries WHERE MbrIntersects(geometry, !bbox!)".to_string()));
+    layer.srid = Some(4326);
+    layer.fields = Some(vec!["name".to_string()]);
+    assert_eq!(ds.build_query_sql(&layer, 3857, None, false),
+               Some("SELECT name, ST_Transform(ST_PointOnSurface(geometry), 3857) AS geometry FROM ne_10m_admin_0_countries WHERE MbrIntersects(geometry, !bbox!)".to_string()));
+}
+
+#[test]
+fn test_user_query_bbox_filter() {
+    let ds = SpatialiteInput::new("natural_earth.sqlite");
+    let mut layer = Layer::new("points");
+    layer.geometry_field = Some("geometry".to_string());
+    layer.srid = Some(3857);
+
+    let sql = "SELECT name, geometry FROM places".to_string();
+    assert_eq!(ds.build_query_sql(&layer, 3857, Some(&sql), false),
+               Some("SELECT * FROM (SELECT name, geometry FROM places) AS _q WHERE MbrIntersects(geometry, !bbox!)".to_string()));
+}
+
+#[test]
+fn test_query_params() {
+    let ds = SpatialiteInput::new("natural_earth.sqlite");
+    let mut layer = Layer::new("points");
+    layer.table_name = Some("ne_10m_populated_places".to_string());
+    layer.geometry_field = Some("geometry".to_string());
+    layer.srid = Some(4326);
+
+    let query = ds.build_query(&layer, 3857, 5, false).unwrap();
+    assert_eq!(query.sql,
+               "SELECT * FROM ne_10m_populated_places WHERE MbrIntersects(geometry, ST_Transform(BuildMbr(?1, ?2, ?3, ?4, 3857), 4326))");
+    assert_eq!(query.params, vec![QueryParam::Bbox]);
+
+    layer.srid = Some(3857);
+    layer.query = vec![LayerQuery {
+                           minzoom: Some(0),
+                           maxzoom: Some(22),
+                           sql: Some("SELECT name, ST_Simplify(geometry, !pixel_width!) AS geometry FROM places WHERE scalerank <= !zoom!".to_string()),
+                       }];
+    let query = ds.build_query(&layer, 3857, 5, false).unwrap();
+    assert_eq!(query.sql,
+               "SELECT * FROM (SELECT name, ST_Simplify(geometry, ?6) AS geometry FROM places WHERE scalerank <= ?5) AS _q WHERE MbrIntersects(geometry, BuildMbr(?1, ?2, ?3, ?4, 3857))");
+    assert_eq!(query.params,
+               vec![QueryParam::Bbox, QueryParam::Zoom, QueryParam::PixelWidth]);
+}
+
+#[test]
+fn test_missing_database() {
+    let ds = SpatialiteInput::new("/nonexistent/data.sqlite");
+    assert_eq!(ds.connected().err(),
+               Some("SpatiaLite database '/nonexistent/data.sqlite' not found".to_string()));
+}
@@ -0,0 +1,315 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use datasource::DatasourceInput;
+use datasource::geojson::{transformation_from_wgs84, transform_extent};
+use core::error::TrexError;
+use core::feature::{Feature, FeatureAttr, FeatureAttrValType};
+use core::geom::{self, GeometryType};
+use core::grid::Extent;
+use core::grid::Grid;
+use core::layer::Layer;
+use core::screen;
+use core::Config;
+use core::config::DatasourceCfg;
+use mvt::tile::Tile;
+use mvt::vector_tile;
+use mvt::geom_encoder::CommandSequence;
+use hyper::Client;
+use hyper::status::StatusCode;
+use num_cpus;
+use std::io::Read;
+use std::time::Duration;
+
+
+/// Timeout for upstream requests
+const UPSTREAM_TIMEOUT_SECS: u64 = 30;
+
+/// Datasource reading features from an upstream XYZ vector tile server
+pub struct TileProxyInput {
+    /// Tile URL template with `{z}`, `{x}` and `{y}` placeholders
+    pub url: String,
+}
+
+struct TileProxyFeatureRef<'a> {
+    layer: &'a Layer,
+    mvt_layer: &'a vector_tile::Tile_Layer,
+    feature: &'a vector_tile::Tile_Feature,
+    /// Extent of the upstream tile
+    extent: &'a Extent,
+    srid: i32,
+}
+
+
+fn attribute_value(value: &vector_tile::Tile_Value) -> Option<FeatureAttrValType> {
+    if value.has_string_value() {
+        Some(FeatureAttrValType::String(value.get_string_value().to_string()))
+    } else if value.has_float_value() {
+        Some(FeatureAttrValType::Float(value.get_float_value()))
+    } else if value.has_double_value() {
+        Some(FeatureAttrValType::Double(value.get_double_value()))
+    } else if value.has_int_value() {
+        Some(FeatureAttrValType::Int(value.get_int_value()))
+    } else if value.has_uint_value() {
+        Some(FeatureAttrValType::UInt(value.get_uint_value()))
+    } else if value.has_sint_value() {
+        Some(FeatureAttrValType::SInt(value.get_sint_value()))
+    } else if value.has_bool_value() {
+        Some(FeatureAttrValType::Bool(value.get_bool_value()))
+    } else {
+        None
+    }
+}
+
+/// Signed ring area in tile coordinates. Exterior rings have a positive area.
+fn ring_area(ring: &[screen::Point]) -> i64 {
+    let mut area = 0i64;
+    for i in 0..ring.len() {
+        let (a, b) = (&ring[i], &ring[(i + 1) % ring.len()]);
+        area += a.x as i64 * b.y as i64 - b.x as i64 * a.y as i64;
+    }
+    area
+}
+
+impl<'a> Feature for TileProxyFeatureRef<'a> {
+    fn fid(&self) -> Option<u64> {
+        if self.feature.has_id() {
+            Some(self.feature.get_id())
+        } else {
+            None
+        }
+    }
+    fn attributes(&self) -> Vec<FeatureAttr> {
+        let keys = self.mvt_layer.get_keys();
+        let values = self.mvt_layer.get_values();
+        self.feature
+            .get_tags()
+            .chunks(2)
+            .filter(|tag| tag.len() == 2)
+            .filter_map(|tag| match (keys.get(tag[0] as usize), values.get(tag[1] as usize)) {
+                            (Some(key), Some(value)) => {
+                                attribute_value(value).map(|v| {
+                                                               FeatureAttr {
+                                                                   key: key.clone(),
+                                                                   value: v,
+                                                               }
+                                                           })
+                            }
+                            _ => None,
+                        })
+            .filter(|attr| match self.layer.fields {
+                        Some(ref fields) => fields.contains(&attr.key),
+                        None => true,
+                    })
+            .collect()
+    }
+    fn geometry(&self) -> Result<GeometryType, String> {
+        let parts = CommandSequence(self.feature.get_geometry().to_vec()).decode();
+        let tile_size = self.mvt_layer.get_extent() as f64;
+        let xscale = (self.extent.maxx - self.extent.minx) / tile_size;
+        let yscale = (self.extent.maxy - self.extent.miny) / tile_size;
+        let srid = Some(self.srid);
+        let point = |p: &screen::Point| {
+            geom::Point::new(self.extent.minx + p.x as f64 * xscale,
+                             self.extent.maxy - p.y as f64 * yscale,
+                             srid)
+        };
+        match self.feature.get_field_type() {
+            vector_tile::Tile_GeomType::POINT => {
+                let mut points: Vec<geom::Point> =
+                    parts.iter().flat_map(|part| part.iter().map(&point)).collect();
+                if points.len() == 1 {
+                    Ok(GeometryType::Point(points.pop().unwrap()))
+                } else {
+                    Ok(GeometryType::MultiPoint(geom::MultiPoint {
+                                                    points: points,
+                                                    srid: srid,
+                                                }))
+                }
+            }
+            vector_tile::Tile_GeomType::LINESTRING => {
+                let mut lines: Vec<geom::LineString> = parts
+                    .iter()
+                    .map(|part| {
+                             geom::LineString {
+                                 points: part.iter().map(&point).collect(),
+                                 srid: srid,
+                             }
+                         })
+                    .collect();
+                if lines.len() == 1 {
+                    Ok(GeometryType::LineString(lines.pop().unwrap()))
+                } else {
+                    Ok(GeometryType::MultiLineString(geom::MultiLineString {
+                                                         lines: lines,
+                                                         srid: srid,
+                                                     }))
+                }
+            }
+            vector_tile::Tile_GeomType::POLYGON => {
+                // Every exterior ring starts a new polygon
+                let mut polygons: Vec<geom::Polygon> = Vec::new();
+                for part in &parts {
+                    let ring = geom::LineString {
+                        points: part.iter().map(&point).collect(),
+                        srid: srid,
+                    };
+                    if ring_area(part) > 0 || polygons.is_empty() {
+                        polygons.push(geom::Polygon {
+                                          rings: vec![ring],
+                                          srid: srid,
+                                      });
+                    } else {
+                        polygons.last_mut().unwrap().rings.push(ring);
+                    }
+                }
+                if polygons.len() == 1 {
+                    Ok(GeometryType::Polygon(polygons.pop().unwrap()))
+                } else {
+                    Ok(GeometryType::MultiPolygon(geom::MultiPolygon {
+                                                      polygons: polygons,
+                                                      srid: srid,
+                                                  }))
+                }
+            }
+            vector_tile::Tile_GeomType::UNKNOWN => {
+                Err(format!("Layer '{}': unknown geometry type of upstream feature",
+                            self.layer.name))
+            }
+        }
+    }
+}
+
+
+impl TileProxyInput {
+    pub fn new(url: &str) -> TileProxyInput {
+        TileProxyInput { url: url.to_string() }
+    }
+    pub fn pool_size(&self) -> u32 {
+        num_cpus::get() as u32
+    }
+    /// URL of upstream tile in XYZ adressing scheme
+    pub fn tile_url(&self, zoom: u8, xtile: u32, ytile: u32) -> String {
+        self.url
+            .replace("{z}", &zoom.to_string())
+            .replace("{x}", &xtile.to_string())
+            .replace("{y}", &ytile.to_string())
+    }
+    /// Fetch upstream tile. Returns `None` for missing or empty tiles.
+    pub fn fetch_tile(&self,
+                      zoom: u8,
+                      xtile: u32,
+                      ytile: u32)
+                      -> Result<Option<vector_tile::Tile>, String> {
+        let url = self.tile_url(zoom, xtile, ytile);
+        let mut client = Client::new();
+        client.set_read_timeout(Some(Duration::from_secs(UPSTREAM_TIMEOUT_SECS)));
+        let mut response = client
+            .get(&url)
+            .send()
+            .map_err(|e| format!("Upstream tile {}: {}", url, e))?;
+        match response.status {
+            StatusCode::Ok => {}
+            StatusCode::NoContent | StatusCode::NotFound => return Ok(None),
+            status => return Err(format!("Upstream tile {}: {}", url, status)),
+        }
+        let mut data = Vec::new();
+        response
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Upstream tile {}: {}", url, e))?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+        if Tile::is_gzipped(&data) {
+            data = Tile::gunzip(&data)
+                .map_err(|e| format!("Upstream tile {}: {}", url, e))?;
+        }
+        Tile::read_from(&mut &data[..])
+            .map(|mvt_tile| Some(mvt_tile))
+            .map_err(|e| format!("Upstream tile {}: {}", url, e))
+    }
+    /// Projected extent
+    pub fn extent_from_wgs84(&self, extent: &Extent, dest_srid: i32) -> Option<Extent> {
+        transformation_from_wgs84(dest_srid).map(|transform| transform_extent(extent, transform))
+    }
+}
+
+impl DatasourceInput for TileProxyInput {
+    /// Read features of the upstream tile at the center of `extent`.
+    /// The upstream layer is selected by `table_name` or the layer name.
+    fn retrieve_features<F>(&self,
+                            layer: &Layer,
+                            extent: &Extent,
+                            zoom: u8,
+                            grid: &Grid,
+                            mut read: F)
+                            -> Result<u64, TrexError>
+        where F: FnMut(&Feature)
+    {
+        // Buffered extents cover neighbour tiles
+        let (minx, miny, maxx, maxy) = grid.extent_to_tile_range(extent, zoom);
+        let (xtile, ytile) = ((minx + maxx) / 2, (miny + maxy) / 2);
+        let mvt_tile = match self.fetch_tile(zoom, xtile, ytile)
+                  .map_err(TrexError::Datasource)? {
+            Some(mvt_tile) => mvt_tile,
+            None => return Ok(0),
+        };
+        let layer_name = layer.table_name.as_ref().unwrap_or(&layer.name);
+        let tile_extent = grid.tile_extent_xyz(xtile, ytile, zoom);
+        let query_limit = layer.query_limit(zoom).unwrap_or(0) as u64;
+        let mut cnt = 0;
+        for mvt_layer in mvt_tile.get_layers().iter().filter(|l| l.get_name() == layer_name.as_str()) {
+            for feature in mvt_layer.get_features() {
+                if cnt == query_limit && query_limit > 0 {
+                    info!("Feature count limited (query_limit={})", cnt);
+                    return Ok(cnt);
+                }
+                let feature = TileProxyFeatureRef {
+                    layer: layer,
+                    mvt_layer: mvt_layer,
+                    feature: feature,
+                    extent: &tile_extent,
+                    srid: grid.srid(),
+                };
+                read(&feature);
+                cnt += 1;
+            }
+        }
+        Ok(cnt)
+    }
+}
+
+impl<'a> Config<'a, TileProxyInput, DatasourceCfg> for TileProxyInput {
+    fn from_config(ds_cfg: &DatasourceCfg) -> Result<Self, String> {
+        let url = ds_cfg
+            .url
+            .as_ref()
+            .ok_or("Tile proxy datasource: missing field `url`".to_string())?;
+        if !(url.contains("{z}") && url.contains("{x}") && url.contains("{y}")) {
+            return Err(format!("Tile proxy datasource: url '{}' must contain {{z}}, {{x}} and {{y}}",
+                               url));
+        }
+        Ok(TileProxyInput::new(url))
+    }
+
+    fn gen_config() -> String {
+        let toml = r#"
+[datasource]
+type = "tileproxy"
+# Upstream vector tiles in XYZ adressing scheme
+url = "http://localhost:6767/osm/{z}/{x}/{y}.pbf"
+"#;
+        toml.to_string()
+    }
+
+    fn gen_runtime_config(&self) -> String {
+        format!(r#"
+[datasource]
+type = "tileproxy"
+url = "{}"
+"#,
+                self.url)
+    }
+}
@@ -0,0 +1,171 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use datasource::DatasourceInput;
+use datasource::tileproxy::TileProxyInput;
+use core::feature::{FeatureAttr, FeatureAttrValType, FeatureStruct};
+use core::geom::{self, GeometryType};
+use core::grid::Grid;
+use core::layer::Layer;
+use mvt::tile::Tile;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+
+/// Upstream tile 1/1/0 with a point layer "places" and a polygon layer "areas"
+fn fixture_tile() -> Vec<u8> {
+    let grid = Grid::web_mercator();
+    let extent = grid.tile_extent_xyz(1, 0, 1);
+    let mut tile = Tile::new(&extent, 4096, true);
+
+    let places = Layer::new("places");
+    let mut mvt_layer = tile.new_layer(&places);
+    let feature = FeatureStruct {
+        fid: Some(1),
+        attributes: vec![FeatureAttr {
+                             key: "name".to_string(),
+                             value: FeatureAttrValType::String("Bern".to_string()),
+                         },
+                         FeatureAttr {
+                             key: "population".to_string(),
+                             value: FeatureAttrValType::Int(133115),
+                         }],
+        geometry: GeometryType::Point(geom::Point::new(829040.8, 5933590.5, None)),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+    tile.add_layer(mvt_layer);
+
+    let areas = Layer::new("areas");
+    let mut mvt_layer = tile.new_layer(&areas);
+    let mut feature = ::mvt::vector_tile::Tile_Feature::new();
+    feature.set_field_type(::mvt::vector_tile::Tile_GeomType::POLYGON);
+    // Square covering the whole tile: MoveTo(0,0) LineTo(4096,0),(4096,4096),(0,4096) ClosePath
+    feature.set_geometry(vec![9, 0, 0, 26, 8192, 0, 0, 8192, 8191, 0, 15]);
+    mvt_layer.mut_features().push(feature);
+    tile.add_layer(mvt_layer);
+
+    let data = Tile::binary_tile(&tile.mvt_tile);
+    Tile::gzip(&data)
+}
+
+/// Serve fixture tiles on a local port. Returns the tile URL template.
+fn fixture_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let tile = fixture_tile();
+    thread::spawn(move || for stream in listener.incoming() {
+                      let mut stream = match stream {
+                          Ok(stream) => stream,
+                          Err(_) => continue,
+                      };
+                      let mut request = Vec::new();
+                      let mut buf = [0; 1024];
+                      while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+                          match stream.read(&mut buf) {
+                              Ok(0) | Err(_) => break,
+                              Ok(n) => request.extend_from_slice(&buf[..n]),
+                          }
+                      }
+                      let request = String::from_utf8_lossy(&request).to_string();
+                      let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+                      let (status, body) = match path.as_str() {
+                          "/tiles/1/1/0.pbf" => ("200 OK", tile.clone()),
+                          "/tiles/1/0/0.pbf" => ("204 No Content", Vec::new()),
+                          "/tiles/1/0/1.pbf" => ("500 Internal Server Error", Vec::new()),
+                          _ => ("404 Not Found", Vec::new()),
+                      };
+                      let header = if status.starts_with("204") {
+                          format!("HTTP/1.1 {}\r\nConnection: close\r\n\r\n", status)
+                      } else {
+                          format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                                  status,
+                                  body.len())
+                      };
+                      let _ = stream.write_all(header.as_bytes());
+                      let _ = stream.write_all(&body);
+                  });
+    format!("http://{}/tiles/{{z}}/{{x}}/{{y}}.pbf", addr)
+}
+
+#[test]
+fn test_tile_url() {
+    let ds = TileProxyInput::new("http://localhost:6767/osm/{z}/{x}/{y}.pbf");
+    assert_eq!(ds.tile_url(5, 16, 11), "http://localhost:6767/osm/5/16/11.pbf");
+}
+
+#[test]
+fn test_retrieve_features() {
+    let ds = TileProxyInput::new(&fixture_server());
+    let grid = Grid::web_mercator();
+
+    let layer = Layer::new("places");
+    let extent = grid.tile_extent_xyz(1, 0, 1);
+    let mut cnt = 0;
+    let n = ds.retrieve_features(&layer, &extent, 1, &grid, |feat| {
+            assert_eq!(feat.fid(), Some(1));
+            let attrs = feat.attributes();
+            assert_eq!(attrs.len(), 2);
+            assert_eq!(attrs[0].key, "name");
+            assert_eq!(attrs[0].value, FeatureAttrValType::String("Bern".to_string()));
+            assert_eq!(attrs[1].value, FeatureAttrValType::Int(133115));
+            match feat.geometry().unwrap() {
+                GeometryType::Point(p) => {
+                    assert_eq!(p.srid, Some(3857));
+                    // Precision of a tile unit at zoom level 1
+                    assert!((p.x - 829040.8).abs() < 5000.0);
+                    assert!((p.y - 5933590.5).abs() < 5000.0);
+                }
+                _ => panic!("Point expected"),
+            }
+            cnt += 1;
+        })
+        .unwrap();
+    assert_eq!((n, cnt), (1, 1));
+
+    // Upstream layer selected by table_name
+    let mut layer = Layer::new("landuse");
+    layer.table_name = Some("areas".to_string());
+    let mut cnt = 0;
+    ds.retrieve_features(&layer, &extent, 1, &grid, |feat| {
+            match feat.geometry().unwrap() {
+                GeometryType::Polygon(p) => {
+                    assert_eq!(p.rings.len(), 1);
+                    assert_eq!(p.rings[0].points.len(), 5);
+                    assert!((p.rings[0].points[0].x - extent.minx).abs() < 1.0);
+                    assert!((p.rings[0].points[0].y - extent.maxy).abs() < 1.0);
+                }
+                _ => panic!("Polygon expected"),
+            }
+            cnt += 1;
+        })
+        .unwrap();
+    assert_eq!(cnt, 1);
+
+    // Unknown upstream layer
+    let layer = Layer::new("roads");
+    assert_eq!(ds.retrieve_features(&layer, &extent, 1, &grid, |_| {}), Ok(0));
+}
+
+#[test]
+fn test_upstream_status() {
+    let ds = TileProxyInput::new(&fixture_server());
+    let grid = Grid::web_mercator();
+    let layer = Layer::new("places");
+
+    // 204 No Content
+    let extent = grid.tile_extent_xyz(0, 0, 1);
+    assert_eq!(ds.retrieve_features(&layer, &extent, 1, &grid, |_| {}), Ok(0));
+    // 404 Not Found
+    let extent = grid.tile_extent_xyz(1, 1, 1);
+    assert_eq!(ds.retrieve_features(&layer, &extent, 1, &grid, |_| {}), Ok(0));
+    // Upstream error
+    let extent = grid.tile_extent_xyz(0, 1, 1);
+    let err = ds.retrieve_features(&layer, &extent, 1, &grid, |_| {})
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("Upstream tile http://127.0.0.1:"));
+    assert!(err.ends_with("/tiles/1/0/1.pbf: 500 Internal Server Error"));
+}
@@ -19,6 +19,15 @@ extern crate postgis;
 extern crate protobuf;
 extern crate flate2;
 extern crate pbr;
+extern crate num_cpus;
+extern crate rusqlite;
+extern crate rusoto_core;
+extern crate rusoto_s3;
+extern crate rusoto_credential;
+extern crate futures;
+extern crate lru_cache;
+extern crate crossbeam;
+extern crate hyper;
 
 pub mod core;
 pub mod datasource;
@@ -0,0 +1,283 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+//! Clip geometries in screen coordinates to the (buffered) tile extent
+
+use core::screen;
+
+
+/// Clipping rectangle in screen coordinates
+#[derive(PartialEq,Clone,Debug)]
+pub struct ClipRect {
+    pub minx: i32,
+    pub miny: i32,
+    pub maxx: i32,
+    pub maxy: i32,
+}
+
+impl ClipRect {
+    /// Tile extent expanded by `buffer` screen units
+    pub fn new(tile_size: u32, buffer: u32) -> ClipRect {
+        ClipRect {
+            minx: -(buffer as i32),
+            miny: -(buffer as i32),
+            maxx: (tile_size + buffer) as i32,
+            maxy: (tile_size + buffer) as i32,
+        }
+    }
+    pub fn contains(&self, point: &screen::Point) -> bool {
+        point.x >= self.minx && point.x <= self.maxx && point.y >= self.miny &&
+        point.y <= self.maxy
+    }
+    /// Clip line segment with Liang–Barsky algorithm
+    fn clip_segment(&self,
+                    p0: &screen::Point,
+                    p1: &screen::Point)
+                    -> Option<(screen::Point, screen::Point)> {
+        let (x0, y0) = (p0.x as f64, p0.y as f64);
+        let (dx, dy) = (p1.x as f64 - x0, p1.y as f64 - y0);
+        let mut t0 = 0.0;
+        let mut t1 = 1.0;
+        let checks = [(-dx, x0 - self.minx as f64),
+                      (dx, self.maxx as f64 - x0),
+                      (-dy, y0 - self.miny as f64),
+                      (dy, self.maxy as f64 - y0)];
+        for &(p, q) in checks.iter() {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else {
+                let r = q / p;
+                if p < 0.0 {
+                    if r > t1 {
+                        return None;
+                    } else if r > t0 {
+                        t0 = r;
+                    }
+                } else {
+                    if r < t0 {
+                        return None;
+                    } else if r < t1 {
+                        t1 = r;
+                    }
+                }
+            }
+        }
+        Some((screen_point(x0 + t0 * dx, y0 + t0 * dy),
+              screen_point(x0 + t1 * dx, y0 + t1 * dy)))
+    }
+    fn edges(&self) -> [Edge; 4] {
+        [Edge::MinX(self.minx as f64),
+         Edge::MaxX(self.maxx as f64),
+         Edge::MinY(self.miny as f64),
+         Edge::MaxY(self.maxy as f64)]
+    }
+}
+
+fn screen_point(x: f64, y: f64) -> screen::Point {
+    screen::Point {
+        x: x.round() as i32,
+        y: y.round() as i32,
+    }
+}
+
+/// Clipping rectangle edge for Sutherland–Hodgman algorithm
+enum Edge {
+    MinX(f64),
+    MaxX(f64),
+    MinY(f64),
+    MaxY(f64),
+}
+
+impl Edge {
+    fn inside(&self, p: &(f64, f64)) -> bool {
+        match *self {
+            Edge::MinX(v) => p.0 >= v,
+            Edge::MaxX(v) => p.0 <= v,
+            Edge::MinY(v) => p.1 >= v,
+            Edge::MaxY(v) => p.1 <= v,
+        }
+    }
+    fn intersection(&self, a: &(f64, f64), b: &(f64, f64)) -> (f64, f64) {
+        match *self {
+            Edge::MinX(v) | Edge::MaxX(v) => {
+                let t = (v - a.0) / (b.0 - a.0);
+                (v, a.1 + t * (b.1 - a.1))
+            }
+            Edge::MinY(v) | Edge::MaxY(v) => {
+                let t = (v - a.1) / (b.1 - a.1);
+                (a.0 + t * (b.0 - a.0), v)
+            }
+        }
+    }
+}
+
+
+pub trait Clip {
+    type Output;
+    /// Clip geometry to rectangle
+    fn clip(&self, rect: &ClipRect) -> Self::Output;
+}
+
+impl Clip for screen::Point {
+    type Output = screen::MultiPoint;
+    fn clip(&self, rect: &ClipRect) -> screen::MultiPoint {
+        let mut points = Vec::new();
+        if rect.contains(self) {
+            points.push(screen::Point {
+                            x: self.x,
+                            y: self.y,
+                        });
+        }
+        screen::MultiPoint { points: points }
+    }
+}
+
+impl Clip for screen::MultiPoint {
+    type Output = screen::MultiPoint;
+    fn clip(&self, rect: &ClipRect) -> screen::MultiPoint {
+        let points = self.points
+            .iter()
+            .filter(|p| rect.contains(p))
+            .map(|p| screen::Point { x: p.x, y: p.y })
+            .collect();
+        screen::MultiPoint { points: points }
+    }
+}
+
+impl Clip for screen::LineString {
+    type Output = screen::MultiLineString;
+    fn clip(&self, rect: &ClipRect) -> screen::MultiLineString {
+        let mut lines = Vec::new();
+        let mut current: Vec<screen::Point> = Vec::new();
+        for i in 1..self.points.len() {
+            let ref p0 = self.points[i - 1];
+            let ref p1 = self.points[i];
+            match rect.clip_segment(p0, p1) {
+                None => {
+                    if current.len() > 1 {
+                        lines.push(screen::LineString { points: current });
+                    }
+                    current = Vec::new();
+                }
+                Some((a, b)) => {
+                    if current.last() != Some(&a) {
+                        if current.len() > 1 {
+                            lines.push(screen::LineString { points: current });
+                        }
+                        current = vec![a];
+                    }
+                    if current.last() != Some(&b) {
+                        current.push(b);
+                    }
+                    if !rect.contains(p1) {
+                        // Line leaves clipping rectangle
+                        if current.len() > 1 {
+                            lines.push(screen::LineString { points: current });
+                        }
+                        current = Vec::new();
+                    }
+                }
+            }
+        }
+        if current.len() > 1 {
+            lines.push(screen::LineString { points: current });
+        }
+        screen::MultiLineString { lines: lines }
+    }
+}
+
+impl Clip for screen::MultiLineString {
+    type Output = screen::MultiLineString;
+    fn clip(&self, rect: &ClipRect) -> screen::MultiLineString {
+        let mut lines = Vec::new();
+        for line in &self.lines {
+            lines.append(&mut line.clip(rect).lines);
+        }
+        screen::MultiLineString { lines: lines }
+    }
+}
+
+/// Clip closed ring with Sutherland–Hodgman algorithm (preserves winding order)
+fn clip_ring(ring: &screen::LineString, rect: &ClipRect) -> Option<screen::LineString> {
+    if ring.points.len() < 4 {
+        return None;
+    }
+    // Closing point is re-added after clipping
+    let mut output: Vec<(f64, f64)> = ring.points[0..ring.points.len() - 1]
+        .iter()
+        .map(|p| (p.x as f64, p.y as f64))
+        .collect();
+    for edge in rect.edges().iter() {
+        if output.is_empty() {
+            break;
+        }
+        let input = output;
+        output = Vec::new();
+        let mut prev = input[input.len() - 1];
+        for &cur in &input {
+            if edge.inside(&cur) {
+                if !edge.inside(&prev) {
+                    output.push(edge.intersection(&prev, &cur));
+                }
+                output.push(cur);
+            } else if edge.inside(&prev) {
+                output.push(edge.intersection(&prev, &cur));
+            }
+            prev = cur;
+        }
+    }
+    let mut points: Vec<screen::Point> = Vec::new();
+    for &(x, y) in &output {
+        let p = screen_point(x, y);
+        if points.last() != Some(&p) {
+            points.push(p);
+        }
+    }
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    if points.len() < 3 {
+        return None;
+    }
+    let first = screen::Point {
+        x: points[0].x,
+        y: points[0].y,
+    };
+    points.push(first);
+    Some(screen::LineString { points: points })
+}
+
+impl Clip for screen::Polygon {
+    type Output = screen::Polygon;
+    fn clip(&self, rect: &ClipRect) -> screen::Polygon {
+        let mut rings = Vec::new();
+        for (i, ring) in self.rings.iter().enumerate() {
+            match clip_ring(ring, rect) {
+                Some(clipped) => rings.push(clipped),
+                None => {
+                    if i == 0 {
+                        // Exterior ring outside of clipping rectangle
+                        break;
+                    }
+                }
+            }
+        }
+        screen::Polygon { rings: rings }
+    }
+}
+
+impl Clip for screen::MultiPolygon {
+    type Output = screen::MultiPolygon;
+    fn clip(&self, rect: &ClipRect) -> screen::MultiPolygon {
+        let polygons = self.polygons
+            .iter()
+            .map(|polygon| polygon.clip(rect))
+            .filter(|polygon| !polygon.rings.is_empty())
+            .collect();
+        screen::MultiPolygon { polygons: polygons }
+    }
+}
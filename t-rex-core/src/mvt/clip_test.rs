@@ -0,0 +1,116 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use core::screen;
+use mvt::clip::{Clip, ClipRect};
+
+
+fn line(coords: &[(i32, i32)]) -> screen::LineString {
+    screen::LineString { points: coords.iter().map(|&(x, y)| screen::Point { x: x, y: y }).collect() }
+}
+
+#[test]
+fn test_clip_points() {
+    let rect = ClipRect::new(4096, 0);
+    assert_eq!(screen::Point { x: 10, y: 10 }.clip(&rect).points,
+               vec![screen::Point { x: 10, y: 10 }]);
+    assert!(screen::Point { x: -1, y: 10 }.clip(&rect).points.is_empty());
+
+    let rect = ClipRect::new(4096, 64);
+    assert_eq!(screen::Point { x: -1, y: 10 }.clip(&rect).points,
+               vec![screen::Point { x: -1, y: 10 }]);
+
+    let multipoint = screen::MultiPoint {
+        points: vec![screen::Point { x: 5, y: 7 }, screen::Point { x: 5000, y: 2 }],
+    };
+    assert_eq!(multipoint.clip(&rect).points,
+               vec![screen::Point { x: 5, y: 7 }]);
+}
+
+#[test]
+fn test_clip_lines() {
+    let rect = ClipRect::new(4096, 0);
+
+    // Line inside
+    let clipped = line(&[(2, 2), (2, 10), (10, 10)]).clip(&rect);
+    assert_eq!(clipped.lines, vec![line(&[(2, 2), (2, 10), (10, 10)])]);
+
+    // Line crossing left border
+    let clipped = line(&[(-10, 10), (10, 10)]).clip(&rect);
+    assert_eq!(clipped.lines, vec![line(&[(0, 10), (10, 10)])]);
+
+    // Line leaving and reentering
+    let clipped = line(&[(10, 10), (-10, 10), (-10, 20), (10, 20)]).clip(&rect);
+    assert_eq!(clipped.lines,
+               vec![line(&[(10, 10), (0, 10)]), line(&[(0, 20), (10, 20)])]);
+
+    // Line outside
+    let clipped = line(&[(-10, 10), (-20, 10)]).clip(&rect);
+    assert!(clipped.lines.is_empty());
+}
+
+#[test]
+fn test_clip_polygons() {
+    let rect = ClipRect::new(4096, 0);
+
+    let polygon = screen::Polygon {
+        rings: vec![line(&[(-10, -10), (10, -10), (10, 10), (-10, 10), (-10, -10)])],
+    };
+    let clipped = polygon.clip(&rect);
+    assert_eq!(clipped.rings,
+               vec![line(&[(0, 0), (10, 0), (10, 10), (0, 10), (0, 0)])]);
+
+    // Hole outside of clipping rectangle is dropped
+    let polygon = screen::Polygon {
+        rings: vec![line(&[(-10, -10), (10, -10), (10, 10), (-10, 10), (-10, -10)]),
+                    line(&[(-8, -8), (-8, -2), (-2, -2), (-2, -8), (-8, -8)])],
+    };
+    assert_eq!(polygon.clip(&rect).rings.len(), 1);
+
+    // Polygon outside
+    let polygon = screen::Polygon {
+        rings: vec![line(&[(-20, -20), (-10, -20), (-10, -10), (-20, -10), (-20, -20)])],
+    };
+    assert!(polygon.clip(&rect).rings.is_empty());
+
+    let multipolygon = screen::MultiPolygon {
+        polygons: vec![polygon,
+                       screen::Polygon {
+                           rings: vec![line(&[(1, 1), (5, 1), (5, 5), (1, 5), (1, 1)])],
+                       }],
+    };
+    assert_eq!(multipolygon.clip(&rect).polygons.len(), 1);
+}
+
+#[test]
+fn test_clip_tile_features() {
+    use core::feature::FeatureStruct;
+    use core::geom::{self, GeometryType};
+    use core::grid::Extent;
+    use core::layer::Layer;
+    use mvt::tile::Tile;
+
+    let extent = Extent {
+        minx: 958826.08,
+        miny: 5987771.04,
+        maxx: 978393.96,
+        maxy: 6007338.92,
+    };
+    let mut tile = Tile::new(&extent, 4096, false);
+    let mut layer = Layer::new("points");
+    layer.buffer_size = Some(0);
+    let mut mvt_layer = tile.new_layer(&layer);
+
+    for x in vec![960000.0, 990000.0] {
+        let feature = FeatureStruct {
+            fid: None,
+            attributes: vec![],
+            geometry: GeometryType::Point(geom::Point::new(x, 6002729.0, Some(3857))),
+        };
+        tile.add_feature(&mut mvt_layer, &feature);
+    }
+    assert_eq!(mvt_layer.get_features().len(), 1);
+    assert_eq!(mvt_layer.get_features()[0].get_geometry(), &[9, 490, 6262]);
+}
@@ -7,6 +7,8 @@
 //! https://github.com/mapbox/vector-tile-spec/tree/master/2.1
 
 use std::vec::Vec;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use core::screen;
 
 /// Command to be executed and the number of times that the command will be executed
@@ -23,11 +25,9 @@ impl CommandInteger {
     fn new(id: Command, count: u32) -> CommandInteger {
         CommandInteger(((id as u32) & 0x7) | (count << 3))
     }
-    #[cfg(test)]
     fn id(&self) -> u32 {
         self.0 & 0x7
     }
-    #[cfg(test)]
     fn count(&self) -> u32 {
         self.0 >> 3
     }
@@ -52,7 +52,6 @@ impl ParameterInteger {
     fn new(value: i32) -> ParameterInteger {
         ParameterInteger(((value << 1) ^ (value >> 31)) as u32)
     }
-    #[cfg(test)]
     fn value(&self) -> i32 {
         ((self.0 >> 1) as i32) ^ (-((self.0 & 1) as i32))
     }
@@ -68,7 +67,7 @@ fn test_paremeters() {
 pub struct CommandSequence(pub Vec<u32>);
 
 impl CommandSequence {
-    fn new() -> CommandSequence {
+    pub fn new() -> CommandSequence {
         CommandSequence(Vec::new())
     }
     pub fn vec(&self) -> Vec<u32> {
@@ -81,6 +80,40 @@ impl CommandSequence {
     fn push(&mut self, value: u32) {
         self.0.push(value);
     }
+    /// Decode into parts with absolute screen coordinates.
+    /// Every MoveTo starts a new part, ClosePath repeats the first point of the part.
+    pub(crate) fn decode(&self) -> Vec<Vec<screen::Point>> {
+        let mut parts: Vec<Vec<screen::Point>> = Vec::new();
+        let (mut x, mut y) = (0i32, 0i32);
+        let mut i = 0;
+        while i < self.0.len() {
+            let cmd = CommandInteger(self.0[i]);
+            i += 1;
+            if cmd.id() == Command::ClosePath as u32 {
+                if let Some(part) = parts.last_mut() {
+                    if let Some(first) = part.first().cloned() {
+                        part.push(first);
+                    }
+                }
+                continue;
+            }
+            for _ in 0..cmd.count() {
+                if i + 1 >= self.0.len() {
+                    break;
+                }
+                x = x.wrapping_add(ParameterInteger(self.0[i]).value());
+                y = y.wrapping_add(ParameterInteger(self.0[i + 1]).value());
+                i += 2;
+                let point = screen::Point { x: x, y: y };
+                if cmd.id() == Command::MoveTo as u32 {
+                    parts.push(vec![point]);
+                } else if let Some(part) = parts.last_mut() {
+                    part.push(point);
+                }
+            }
+        }
+        parts
+    }
 }
 
 #[test]
@@ -100,9 +133,14 @@ fn test_sequence() {
 pub trait EncodableGeom {
     fn encode(&self) -> CommandSequence {
         let mut seq = CommandSequence::new();
-        self.encode_from(&screen::Point::origin(), &mut seq);
+        self.encode_into(&mut seq);
         seq
     }
+    /// Encode into `seq`, replacing its content but keeping its allocation
+    fn encode_into(&self, seq: &mut CommandSequence) {
+        seq.0.clear();
+        self.encode_from(&screen::Point::origin(), seq);
+    }
     fn encode_from(&self, startpos: &screen::Point, seq: &mut CommandSequence);
 }
 
@@ -116,6 +154,9 @@ impl EncodableGeom for screen::Point {
 
 impl EncodableGeom for screen::MultiPoint {
     fn encode_from(&self, startpos: &screen::Point, seq: &mut CommandSequence) {
+        if self.points.is_empty() {
+            return;
+        }
         seq.push(CommandInteger::new(Command::MoveTo, self.points.len() as u32).0);
         let (mut posx, mut posy) = (startpos.x, startpos.y);
         for point in &self.points {
@@ -170,28 +211,412 @@ impl EncodableGeom for screen::MultiLineString {
     }
 }
 
-impl EncodableGeom for screen::Polygon {
-    fn encode_from(&self, startpos: &screen::Point, seq: &mut CommandSequence) {
-        let mut pos = startpos;
-        for line in &self.rings {
+impl screen::Polygon {
+    /// Rings with winding order required by the MVT spec: exterior ring with positive
+    /// and interior rings with negative signed area in screen coordinates.
+    /// Rings with zero area are dropped.
+    pub fn oriented_rings(&self) -> Vec<screen::LineString> {
+        let mut rings = Vec::with_capacity(self.rings.len());
+        for (i, ring) in self.rings.iter().enumerate() {
+            let area = ring.signed_area();
+            if area == 0.0 {
+                if i == 0 {
+                    // Degenerated exterior ring
+                    break;
+                }
+                continue;
+            }
+            let exterior = i == 0;
+            if (area > 0.0) == exterior {
+                rings.push(ring.clone());
+            } else {
+                let mut points = ring.points.clone();
+                points.reverse();
+                rings.push(screen::LineString { points: points });
+            }
+        }
+        rings
+    }
+    /// Encode rings and return last cursor position
+    fn encode_rings_from(&self,
+                         startpos: &screen::Point,
+                         seq: &mut CommandSequence)
+                         -> screen::Point {
+        let mut pos = startpos.clone();
+        for line in &self.oriented_rings() {
             if line.points.len() > 1 {
                 line.encode_ring_from(&pos, seq);
-                pos = &line.points[line.points.len() - 2];
+                pos = line.points[line.points.len() - 2].clone();
             }
         }
+        pos
+    }
+}
+
+impl EncodableGeom for screen::Polygon {
+    fn encode_from(&self, startpos: &screen::Point, seq: &mut CommandSequence) {
+        self.encode_rings_from(startpos, seq);
     }
 }
 
 impl EncodableGeom for screen::MultiPolygon {
     fn encode_from(&self, startpos: &screen::Point, seq: &mut CommandSequence) {
-        let mut pos = startpos;
+        let mut pos = startpos.clone();
         for polygon in &self.polygons {
-            for line in &polygon.rings {
-                if line.points.len() > 1 {
-                    line.encode_ring_from(&pos, seq);
-                    pos = &line.points[line.points.len() - 2];
+            pos = polygon.encode_rings_from(&pos, seq);
+        }
+    }
+}
+
+
+// --- Geometry simplification in screen coordinates
+
+/// Line simplification algorithm
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SimplifyAlgorithm {
+    /// Douglas–Peucker with tolerance as maximal vertex distance
+    DouglasPeucker,
+    /// Visvalingam–Whyatt with tolerance as minimal triangle area
+    VisvalingamWhyatt,
+}
+
+impl SimplifyAlgorithm {
+    pub fn from_name(name: &str) -> Option<SimplifyAlgorithm> {
+        match name {
+            "dp" => Some(SimplifyAlgorithm::DouglasPeucker),
+            "vw" => Some(SimplifyAlgorithm::VisvalingamWhyatt),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            &SimplifyAlgorithm::DouglasPeucker => "dp",
+            &SimplifyAlgorithm::VisvalingamWhyatt => "vw",
+        }
+    }
+    fn simplify_points(&self, points: &[screen::Point], tolerance: f64) -> Vec<screen::Point> {
+        match self {
+            &SimplifyAlgorithm::DouglasPeucker => douglas_peucker(points, tolerance),
+            &SimplifyAlgorithm::VisvalingamWhyatt => visvalingam_whyatt(points, tolerance),
+        }
+    }
+    /// Minimal area of simplified polygon rings
+    fn min_ring_area(&self, tolerance: f64) -> f64 {
+        match self {
+            &SimplifyAlgorithm::DouglasPeucker => tolerance * tolerance,
+            &SimplifyAlgorithm::VisvalingamWhyatt => tolerance,
+        }
+    }
+}
+
+pub trait Simplify: Sized {
+    /// Simplify geometry with given tolerance in screen units (Douglas–Peucker)
+    fn simplify(&self, tolerance: f64) -> Self {
+        self.simplify_with(SimplifyAlgorithm::DouglasPeucker, tolerance)
+    }
+    /// Simplify geometry with given algorithm. The tolerance of Visvalingam–Whyatt
+    /// is an area in square screen units.
+    fn simplify_with(&self, algorithm: SimplifyAlgorithm, tolerance: f64) -> Self;
+}
+
+/// Distance of point p to segment a-b
+fn segment_distance(p: &screen::Point, a: &screen::Point, b: &screen::Point) -> f64 {
+    let (px, py) = (p.x as f64, p.y as f64);
+    let (ax, ay) = (a.x as f64, a.y as f64);
+    let (dx, dy) = (b.x as f64 - ax, b.y as f64 - ay);
+    let len2 = dx * dx + dy * dy;
+    if len2 == 0.0 {
+        return ((px - ax).powi(2) + (py - ay).powi(2)).sqrt();
+    }
+    let t = (((px - ax) * dx + (py - ay) * dy) / len2).max(0.0).min(1.0);
+    ((px - ax - t * dx).powi(2) + (py - ay - t * dy).powi(2)).sqrt()
+}
+
+/// Remove repeated points resulting from coordinate quantization
+fn dedup_points(points: &[screen::Point]) -> Vec<screen::Point> {
+    let mut deduped: Vec<screen::Point> = Vec::with_capacity(points.len());
+    for point in points {
+        if deduped.last() != Some(point) {
+            deduped.push(point.clone());
+        }
+    }
+    deduped
+}
+
+fn douglas_peucker(points: &[screen::Point], tolerance: f64) -> Vec<screen::Point> {
+    let deduped = dedup_points(points);
+    if deduped.len() < 3 {
+        return deduped;
+    }
+    let last = deduped.len() - 1;
+    let mut keep = vec![false; deduped.len()];
+    keep[0] = true;
+    keep[last] = true;
+    let mut stack = vec![(0, last)];
+    while let Some((first, last)) = stack.pop() {
+        let mut maxdist = 0.0;
+        let mut index = first;
+        for i in first + 1..last {
+            let dist = segment_distance(&deduped[i], &deduped[first], &deduped[last]);
+            if dist > maxdist {
+                maxdist = dist;
+                index = i;
+            }
+        }
+        if maxdist > tolerance {
+            keep[index] = true;
+            stack.push((first, index));
+            stack.push((index, last));
+        }
+    }
+    deduped
+        .into_iter()
+        .zip(keep)
+        .filter(|&(_, k)| k)
+        .map(|(p, _)| p)
+        .collect()
+}
+
+/// Area of triangle a-b-c
+fn triangle_area(a: &screen::Point, b: &screen::Point, c: &screen::Point) -> f64 {
+    ((b.x as f64 - a.x as f64) * (c.y as f64 - a.y as f64) -
+     (c.x as f64 - a.x as f64) * (b.y as f64 - a.y as f64))
+        .abs() / 2.0
+}
+
+/// Vertex with effective area, ordered with smallest area first
+#[derive(PartialEq)]
+struct VwVertex {
+    area: f64,
+    index: usize,
+}
+
+impl Eq for VwVertex {}
+
+impl Ord for VwVertex {
+    fn cmp(&self, other: &VwVertex) -> Ordering {
+        other
+            .area
+            .partial_cmp(&self.area)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for VwVertex {
+    fn partial_cmp(&self, other: &VwVertex) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn visvalingam_whyatt(points: &[screen::Point], tolerance: f64) -> Vec<screen::Point> {
+    let deduped = dedup_points(points);
+    if deduped.len() < 3 {
+        return deduped;
+    }
+    let n = deduped.len();
+    // Doubly linked list of remaining vertices
+    let mut prev: Vec<usize> = (0..n).map(|i| if i == 0 { 0 } else { i - 1 }).collect();
+    let mut next: Vec<usize> = (0..n).map(|i| if i == n - 1 { i } else { i + 1 }).collect();
+    let mut areas = vec![0.0; n];
+    let mut keep = vec![true; n];
+    let mut heap = BinaryHeap::with_capacity(n);
+    for i in 1..n - 1 {
+        areas[i] = triangle_area(&deduped[i - 1], &deduped[i], &deduped[i + 1]);
+        heap.push(VwVertex {
+                      area: areas[i],
+                      index: i,
+                  });
+    }
+    // Remove the vertex with the smallest area until all areas reach the tolerance.
+    // Endpoints are never removed.
+    while let Some(VwVertex { area, index }) = heap.pop() {
+        if area >= tolerance {
+            break;
+        }
+        if !keep[index] || area != areas[index] {
+            // Outdated entry of a vertex with recalculated area
+            continue;
+        }
+        keep[index] = false;
+        let (p, nx) = (prev[index], next[index]);
+        next[p] = nx;
+        prev[nx] = p;
+        for &i in &[p, nx] {
+            if i == 0 || i == n - 1 {
+                continue;
+            }
+            // Areas of neighbours don't drop below the removed area
+            areas[i] = triangle_area(&deduped[prev[i]], &deduped[i], &deduped[next[i]])
+                .max(area);
+            heap.push(VwVertex {
+                          area: areas[i],
+                          index: i,
+                      });
+        }
+    }
+    deduped
+        .into_iter()
+        .zip(keep)
+        .filter(|&(_, k)| k)
+        .map(|(p, _)| p)
+        .collect()
+}
+
+impl screen::LineString {
+    /// Signed ring area (shoelace formula)
+    pub fn signed_area(&self) -> f64 {
+        let mut area = 0.0;
+        for i in 1..self.points.len() {
+            let ref p0 = self.points[i - 1];
+            let ref p1 = self.points[i];
+            area += p0.x as f64 * p1.y as f64 - p1.x as f64 * p0.y as f64;
+        }
+        area / 2.0
+    }
+}
+
+impl Simplify for screen::LineString {
+    fn simplify_with(&self, algorithm: SimplifyAlgorithm, tolerance: f64) -> Self {
+        let points = algorithm.simplify_points(&self.points, tolerance);
+        if points.len() < 2 {
+            // Line collapsed to a point
+            return screen::LineString { points: Vec::new() };
+        }
+        screen::LineString { points: points }
+    }
+}
+
+impl Simplify for screen::MultiLineString {
+    fn simplify_with(&self, algorithm: SimplifyAlgorithm, tolerance: f64) -> Self {
+        let lines = self.lines
+            .iter()
+            .map(|line| line.simplify_with(algorithm, tolerance))
+            .filter(|line| !line.points.is_empty())
+            .collect();
+        screen::MultiLineString { lines: lines }
+    }
+}
+
+impl Simplify for screen::Polygon {
+    fn simplify_with(&self, algorithm: SimplifyAlgorithm, tolerance: f64) -> Self {
+        let mut rings = Vec::new();
+        for (i, ring) in self.rings.iter().enumerate() {
+            let simplified =
+                screen::LineString { points: algorithm.simplify_points(&ring.points, tolerance) };
+            // Drop rings collapsing below an area of one tolerance square (DP)
+            // or the tolerance area (VW)
+            if simplified.points.len() < 4 ||
+               simplified.signed_area().abs() < algorithm.min_ring_area(tolerance) {
+                if i == 0 {
+                    // Exterior ring collapsed
+                    break;
+                }
+            } else {
+                rings.push(simplified);
+            }
+        }
+        screen::Polygon { rings: rings }
+    }
+}
+
+impl Simplify for screen::MultiPolygon {
+    fn simplify_with(&self, algorithm: SimplifyAlgorithm, tolerance: f64) -> Self {
+        let polygons = self.polygons
+            .iter()
+            .map(|polygon| polygon.simplify_with(algorithm, tolerance))
+            .filter(|polygon| !polygon.rings.is_empty())
+            .collect();
+        screen::MultiPolygon { polygons: polygons }
+    }
+}
+
+
+// --- Snapping to a coarser grid in screen coordinates
+
+pub trait Snap {
+    /// Round coordinates to multiples of step (in screen units)
+    fn snap(&self, step: i32) -> Self;
+}
+
+fn snap_coord(v: i32, step: i32) -> i32 {
+    (v as f64 / step as f64).round() as i32 * step
+}
+
+/// Snap points and remove repeated points
+fn snap_points(points: &[screen::Point], step: i32) -> Vec<screen::Point> {
+    let mut snapped: Vec<screen::Point> = Vec::with_capacity(points.len());
+    for point in points {
+        let point = point.snap(step);
+        if snapped.last() != Some(&point) {
+            snapped.push(point);
+        }
+    }
+    snapped
+}
+
+impl Snap for screen::Point {
+    fn snap(&self, step: i32) -> Self {
+        screen::Point {
+            x: snap_coord(self.x, step),
+            y: snap_coord(self.y, step),
+        }
+    }
+}
+
+impl Snap for screen::MultiPoint {
+    fn snap(&self, step: i32) -> Self {
+        screen::MultiPoint { points: self.points.iter().map(|p| p.snap(step)).collect() }
+    }
+}
+
+impl Snap for screen::LineString {
+    fn snap(&self, step: i32) -> Self {
+        let points = snap_points(&self.points, step);
+        if points.len() < 2 {
+            // Line collapsed to a point
+            return screen::LineString { points: Vec::new() };
+        }
+        screen::LineString { points: points }
+    }
+}
+
+impl Snap for screen::MultiLineString {
+    fn snap(&self, step: i32) -> Self {
+        let lines = self.lines
+            .iter()
+            .map(|line| line.snap(step))
+            .filter(|line| !line.points.is_empty())
+            .collect();
+        screen::MultiLineString { lines: lines }
+    }
+}
+
+impl Snap for screen::Polygon {
+    fn snap(&self, step: i32) -> Self {
+        let mut rings = Vec::new();
+        for (i, ring) in self.rings.iter().enumerate() {
+            let snapped = screen::LineString { points: snap_points(&ring.points, step) };
+            if snapped.points.len() < 4 {
+                if i == 0 {
+                    // Exterior ring collapsed
+                    break;
                 }
+            } else {
+                rings.push(snapped);
             }
         }
+        screen::Polygon { rings: rings }
+    }
+}
+
+impl Snap for screen::MultiPolygon {
+    fn snap(&self, step: i32) -> Self {
+        let polygons = self.polygons
+            .iter()
+            .map(|polygon| polygon.snap(step))
+            .filter(|polygon| !polygon.rings.is_empty())
+            .collect();
+        screen::MultiPolygon { polygons: polygons }
     }
 }
@@ -107,3 +107,244 @@ fn test_overflow() {
     assert_eq!(multipoint.encode().0,
                &[17, 9, 19, u32::MAX - 1, u32::MAX - 1]);
 }
+
+#[test]
+fn test_simplify() {
+    use mvt::geom_encoder::Simplify;
+
+    let linestring = screen::LineString {
+        points: vec![screen::Point { x: 0, y: 0 },
+                     screen::Point { x: 5, y: 1 },
+                     screen::Point { x: 10, y: 0 },
+                     screen::Point { x: 10, y: 0 },
+                     screen::Point { x: 20, y: 10 }],
+    };
+    assert_eq!(linestring.simplify(1.0).points,
+               vec![screen::Point { x: 0, y: 0 },
+                    screen::Point { x: 10, y: 0 },
+                    screen::Point { x: 20, y: 10 }]);
+    assert_eq!(linestring.simplify(0.5).points.len(), 4);
+
+    // Line collapsing to a point
+    let linestring = screen::LineString {
+        points: vec![screen::Point { x: 3, y: 3 }, screen::Point { x: 3, y: 3 }],
+    };
+    assert!(linestring.simplify(1.0).points.is_empty());
+    let multilinestring = screen::MultiLineString { lines: vec![linestring] };
+    assert!(multilinestring.simplify(1.0).lines.is_empty());
+
+    let polygon = screen::Polygon {
+        rings: vec![screen::LineString {
+                        points: vec![screen::Point { x: 0, y: 0 },
+                                     screen::Point { x: 10, y: 0 },
+                                     screen::Point { x: 10, y: 5 },
+                                     screen::Point { x: 10, y: 10 },
+                                     screen::Point { x: 0, y: 10 },
+                                     screen::Point { x: 0, y: 0 }],
+                    },
+                    // hole with sub-pixel area
+                    screen::LineString {
+                        points: vec![screen::Point { x: 2, y: 2 },
+                                     screen::Point { x: 3, y: 2 },
+                                     screen::Point { x: 3, y: 3 },
+                                     screen::Point { x: 2, y: 2 }],
+                    }],
+    };
+    let simplified = polygon.simplify(1.0);
+    assert_eq!(simplified.rings.len(), 1);
+    assert_eq!(simplified.rings[0].points.len(), 5);
+
+    // Polygon collapsing below threshold area
+    assert!(polygon.simplify(20.0).rings.is_empty());
+}
+
+#[test]
+fn test_simplify_vw() {
+    use mvt::geom_encoder::{Simplify, SimplifyAlgorithm};
+
+    let zigzag = screen::LineString {
+        points: vec![screen::Point { x: 0, y: 0 },
+                     screen::Point { x: 1, y: 1 },
+                     screen::Point { x: 2, y: 0 },
+                     screen::Point { x: 3, y: 1 },
+                     screen::Point { x: 4, y: 0 },
+                     screen::Point { x: 10, y: 0 }],
+    };
+    // Both algorithms reduce the vertex count
+    assert!(zigzag.simplify_with(SimplifyAlgorithm::DouglasPeucker, 1.0).points.len() < 6);
+    let simplified = zigzag.simplify_with(SimplifyAlgorithm::VisvalingamWhyatt, 2.0);
+    assert_eq!(simplified.points,
+               vec![screen::Point { x: 0, y: 0 },
+                    screen::Point { x: 3, y: 1 },
+                    screen::Point { x: 4, y: 0 },
+                    screen::Point { x: 10, y: 0 }]);
+    // Endpoints are kept
+    assert_eq!(zigzag.simplify_with(SimplifyAlgorithm::VisvalingamWhyatt, 10.0).points,
+               vec![screen::Point { x: 0, y: 0 }, screen::Point { x: 10, y: 0 }]);
+    assert_eq!(zigzag.simplify_with(SimplifyAlgorithm::VisvalingamWhyatt, 0.5).points,
+               zigzag.points);
+
+    let polygon = screen::Polygon {
+        rings: vec![screen::LineString {
+                        points: vec![screen::Point { x: 0, y: 0 },
+                                     screen::Point { x: 10, y: 0 },
+                                     screen::Point { x: 10, y: 5 },
+                                     screen::Point { x: 10, y: 10 },
+                                     screen::Point { x: 0, y: 10 },
+                                     screen::Point { x: 0, y: 0 }],
+                    },
+                    // hole with area below tolerance
+                    screen::LineString {
+                        points: vec![screen::Point { x: 2, y: 2 },
+                                     screen::Point { x: 3, y: 2 },
+                                     screen::Point { x: 3, y: 3 },
+                                     screen::Point { x: 2, y: 2 }],
+                    }],
+    };
+    let simplified = polygon.simplify_with(SimplifyAlgorithm::VisvalingamWhyatt, 1.0);
+    assert_eq!(simplified.rings.len(), 1);
+    assert_eq!(simplified.rings[0].points.len(), 5);
+    assert_eq!(simplified.rings[0].points[0], screen::Point { x: 0, y: 0 });
+    assert_eq!(simplified.rings[0].points[4], screen::Point { x: 0, y: 0 });
+
+    assert_eq!(SimplifyAlgorithm::from_name("vw"),
+               Some(SimplifyAlgorithm::VisvalingamWhyatt));
+    assert_eq!(SimplifyAlgorithm::from_name("x"), None);
+}
+
+#[test]
+fn test_snap() {
+    use mvt::geom_encoder::Snap;
+
+    assert_eq!(screen::Point { x: 5, y: -7 }.snap(4),
+               screen::Point { x: 4, y: -8 });
+    assert_eq!(screen::Point { x: 6, y: 4095 }.snap(4),
+               screen::Point { x: 8, y: 4096 });
+
+    // Repeated points are removed
+    let linestring = screen::LineString {
+        points: vec![screen::Point { x: 0, y: 0 },
+                     screen::Point { x: 1, y: 1 },
+                     screen::Point { x: 9, y: 3 },
+                     screen::Point { x: 21, y: 10 }],
+    };
+    assert_eq!(linestring.snap(4).points,
+               vec![screen::Point { x: 0, y: 0 },
+                    screen::Point { x: 8, y: 4 },
+                    screen::Point { x: 20, y: 12 }]);
+    assert_eq!(linestring.snap(1), linestring);
+
+    // Line collapsing to a point
+    let linestring = screen::LineString {
+        points: vec![screen::Point { x: 3, y: 3 }, screen::Point { x: 4, y: 5 }],
+    };
+    assert!(linestring.snap(4).points.is_empty());
+    let multilinestring = screen::MultiLineString { lines: vec![linestring] };
+    assert!(multilinestring.snap(4).lines.is_empty());
+
+    let polygon = screen::Polygon {
+        rings: vec![screen::LineString {
+                        points: vec![screen::Point { x: 0, y: 0 },
+                                     screen::Point { x: 17, y: 1 },
+                                     screen::Point { x: 15, y: 17 },
+                                     screen::Point { x: 1, y: 15 },
+                                     screen::Point { x: 0, y: 0 }],
+                    },
+                    // hole collapsing to a point
+                    screen::LineString {
+                        points: vec![screen::Point { x: 3, y: 3 },
+                                     screen::Point { x: 5, y: 3 },
+                                     screen::Point { x: 5, y: 5 },
+                                     screen::Point { x: 3, y: 3 }],
+                    }],
+    };
+    let snapped = polygon.snap(4);
+    assert_eq!(snapped.rings.len(), 1);
+    assert_eq!(snapped.rings[0].points,
+               vec![screen::Point { x: 0, y: 0 },
+                    screen::Point { x: 16, y: 0 },
+                    screen::Point { x: 16, y: 16 },
+                    screen::Point { x: 0, y: 16 },
+                    screen::Point { x: 0, y: 0 }]);
+
+    // Polygon collapsing to a point
+    assert!(polygon.snap(64).rings.is_empty());
+}
+
+#[test]
+fn test_decode_roundtrip() {
+    let p = |x, y| screen::Point { x: x, y: y };
+
+    let point = p(25, 17);
+    assert_eq!(point.encode().decode(), vec![vec![p(25, 17)]]);
+
+    let multipoint = screen::MultiPoint { points: vec![p(5, 7), p(3, 2)] };
+    assert_eq!(multipoint.encode().decode(),
+               vec![vec![p(5, 7)], vec![p(3, 2)]]);
+
+    let linestring = screen::LineString { points: vec![p(2, 2), p(2, 10), p(10, 10)] };
+    assert_eq!(linestring.encode().decode(), vec![linestring.points.clone()]);
+
+    let multilinestring = screen::MultiLineString {
+        lines: vec![linestring.clone(), screen::LineString { points: vec![p(1, 1), p(3, 5)] }],
+    };
+    assert_eq!(multilinestring.encode().decode(),
+               multilinestring
+                   .lines
+                   .iter()
+                   .map(|l| l.points.clone())
+                   .collect::<Vec<_>>());
+
+    let exterior = screen::LineString { points: vec![p(0, 0), p(10, 0), p(10, 10), p(0, 10), p(0, 0)] };
+    let hole = screen::LineString { points: vec![p(2, 2), p(2, 8), p(8, 8), p(8, 2), p(2, 2)] };
+    let polygon = screen::Polygon { rings: vec![exterior.clone(), hole.clone()] };
+    assert_eq!(polygon.encode().decode(),
+               vec![exterior.points.clone(), hole.points.clone()]);
+
+    let exterior2 = screen::LineString {
+        points: vec![p(20, 20), p(30, 20), p(30, 30), p(20, 30), p(20, 20)],
+    };
+    let multipolygon = screen::MultiPolygon {
+        polygons: vec![polygon.clone(), screen::Polygon { rings: vec![exterior2.clone()] }],
+    };
+    assert_eq!(multipolygon.encode().decode(),
+               vec![exterior.points, hole.points, exterior2.points]);
+}
+
+#[test]
+fn test_ring_winding() {
+    let p = |x, y| screen::Point { x: x, y: y };
+
+    // Exterior ring with negative area, hole with positive area
+    let exterior = vec![p(0, 0), p(0, 10), p(10, 10), p(10, 0), p(0, 0)];
+    let hole = vec![p(2, 2), p(8, 2), p(8, 8), p(2, 8), p(2, 2)];
+    let polygon = screen::Polygon {
+        rings: vec![screen::LineString { points: exterior.clone() },
+                    screen::LineString { points: hole.clone() },
+                    // Zero area ring
+                    screen::LineString { points: vec![p(3, 3), p(5, 5), p(3, 3)] }],
+    };
+    let rings = polygon.encode().decode();
+    assert_eq!(rings.len(), 2);
+    let exterior_area = screen::LineString { points: rings[0].clone() }.signed_area();
+    let hole_area = screen::LineString { points: rings[1].clone() }.signed_area();
+    assert_eq!(exterior_area, 100.0);
+    assert_eq!(hole_area, -36.0);
+    let mut reversed = exterior.clone();
+    reversed.reverse();
+    assert_eq!(rings[0], reversed);
+
+    // Correctly oriented polygon is unchanged
+    let polygon = screen::Polygon {
+        rings: vec![screen::LineString { points: rings[0].clone() },
+                    screen::LineString { points: rings[1].clone() }],
+    };
+    assert_eq!(polygon.encode().decode(), rings);
+
+    // Degenerated exterior ring
+    let polygon = screen::Polygon {
+        rings: vec![screen::LineString { points: vec![p(0, 0), p(10, 10), p(0, 0)] },
+                    screen::LineString { points: hole }],
+    };
+    assert!(polygon.encode().0.is_empty());
+}
@@ -7,7 +7,10 @@ pub mod tile;
 #[cfg_attr(rustfmt, rustfmt_skip)]
 pub mod vector_tile; // protoc --rust_out . vector_tile.proto
 pub mod geom_encoder;
+pub mod clip;
 #[cfg(test)]
 mod tile_test;
 #[cfg(test)]
 mod geom_encoder_test;
+#[cfg(test)]
+mod clip_test;
@@ -4,19 +4,22 @@
 //
 
 use core::layer::Layer;
-use core::feature::{Feature, FeatureAttrValType};
+use core::feature::{Feature, FeatureAttrValType, FieldType};
 use core::grid::Extent;
 use core::geom::GeometryType;
 use core::geom;
 use core::screen;
 use mvt::vector_tile;
-use mvt::geom_encoder::{EncodableGeom, CommandSequence};
+use mvt::geom_encoder::{EncodableGeom, CommandSequence, Simplify, SimplifyAlgorithm, Snap};
+use mvt::clip::{Clip, ClipRect};
 use protobuf::stream::CodedOutputStream;
 use protobuf::core::Message;
 use protobuf::error::ProtobufError;
 use protobuf::parse_from_reader;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::{BufReader, Read, Write};
+use std::io::{self, BufReader, Read, Write};
+use std::mem;
 use flate2::Compression;
 use flate2::write::GzEncoder;
 use flate2::read::GzDecoder;
@@ -27,6 +30,105 @@ pub struct Tile<'a> {
     tile_size: u32,
     extent: &'a Extent,
     reverse_y: bool,
+    /// Tile size in grid pixels, used for converting pixel buffers
+    grid_tile_size: u32,
+    /// Clipping rectangle of current layer
+    clip_rect: Option<ClipRect>,
+    /// Simplification tolerance of current layer
+    simplify_tolerance: Option<f64>,
+    /// Simplification algorithm of current layer
+    simplify_algorithm: SimplifyAlgorithm,
+    /// Coordinate precision of current layer
+    snap_step: Option<i32>,
+    /// Geometry encoding buffer reused across features
+    geom_buffer: CommandSequence,
+    /// Key and value table indices of current layer
+    tags: TagIndex,
+    /// Attribute type hints of current layer
+    field_types: BTreeMap<String, FieldType>,
+    /// JSON attributes of current layer encoded as separate tags, with nesting depth
+    flatten_json: Vec<String>,
+    json_flatten_depth: u8,
+}
+
+
+/// Hashable attribute value (floats compared by their bit pattern)
+#[derive(PartialEq, Eq, Hash)]
+enum TagValue {
+    String(String),
+    Float(u32),
+    Double(u64),
+    Int(i64),
+    UInt(u64),
+    SInt(i64),
+    Bool(bool),
+}
+
+impl TagValue {
+    fn from_attr(value: FeatureAttrValType) -> TagValue {
+        match value {
+            FeatureAttrValType::String(v) => TagValue::String(v),
+            FeatureAttrValType::Float(v) => TagValue::Float(v.to_bits()),
+            FeatureAttrValType::Double(v) => TagValue::Double(v.to_bits()),
+            FeatureAttrValType::Int(v) => TagValue::Int(v),
+            FeatureAttrValType::UInt(v) => TagValue::UInt(v),
+            FeatureAttrValType::SInt(v) => TagValue::SInt(v),
+            FeatureAttrValType::Bool(v) => TagValue::Bool(v),
+        }
+    }
+    fn mvt_value(&self) -> vector_tile::Tile_Value {
+        let mut mvt_value = vector_tile::Tile_Value::new();
+        match self {
+            &TagValue::String(ref v) => mvt_value.set_string_value(v.clone()),
+            &TagValue::Float(v) => mvt_value.set_float_value(f32::from_bits(v)),
+            &TagValue::Double(v) => mvt_value.set_double_value(f64::from_bits(v)),
+            &TagValue::Int(v) => mvt_value.set_int_value(v),
+            &TagValue::UInt(v) => mvt_value.set_uint_value(v),
+            &TagValue::SInt(v) => mvt_value.set_sint_value(v),
+            &TagValue::Bool(v) => mvt_value.set_bool_value(v),
+        }
+        mvt_value
+    }
+}
+
+/// Lookup tables for deduplicating layer keys and values
+#[derive(Default)]
+struct TagIndex {
+    keys: HashMap<String, u32>,
+    values: HashMap<TagValue, u32>,
+}
+
+impl TagIndex {
+    fn add_tag(&mut self,
+               mvt_layer: &mut vector_tile::Tile_Layer,
+               mvt_feature: &mut vector_tile::Tile_Feature,
+               key: String,
+               value: FeatureAttrValType) {
+        let keyentry = self.keys.get(&key).cloned();
+        let keyidx = match keyentry {
+            Some(idx) => idx,
+            None => {
+                let idx = mvt_layer.get_keys().len() as u32;
+                mvt_layer.mut_keys().push(key.clone());
+                self.keys.insert(key, idx);
+                idx
+            }
+        };
+        mvt_feature.mut_tags().push(keyidx);
+
+        let value = TagValue::from_attr(value);
+        let valentry = self.values.get(&value).cloned();
+        let validx = match valentry {
+            Some(idx) => idx,
+            None => {
+                let idx = mvt_layer.get_values().len() as u32;
+                mvt_layer.mut_values().push(value.mvt_value());
+                self.values.insert(value, idx);
+                idx
+            }
+        };
+        mvt_feature.mut_tags().push(validx);
+    }
 }
 
 
@@ -157,42 +259,131 @@ impl<'a> Tile<'a> {
             tile_size: tile_size,
             extent: extent,
             reverse_y: reverse_y,
+            grid_tile_size: 256,
+            clip_rect: None,
+            simplify_tolerance: None,
+            simplify_algorithm: SimplifyAlgorithm::DouglasPeucker,
+            snap_step: None,
+            geom_buffer: CommandSequence::new(),
+            tags: TagIndex::default(),
+            field_types: BTreeMap::new(),
+            flatten_json: Vec::new(),
+            json_flatten_depth: 1,
         }
     }
 
+    /// Set tile size of grid in pixels (default 256)
+    pub fn with_grid_tile_size(mut self, grid_tile_size: u32) -> Tile<'a> {
+        self.grid_tile_size = grid_tile_size;
+        self
+    }
+
     pub fn new_layer(&mut self, layer: &Layer) -> vector_tile::Tile_Layer {
         let mut mvt_layer = vector_tile::Tile_Layer::new();
         mvt_layer.set_version(2);
         mvt_layer.set_name(layer.name.clone());
         mvt_layer.set_extent(self.tile_size);
+        self.tags = TagIndex::default();
+        self.field_types = layer.field_types.clone();
+        self.flatten_json = layer.flatten_json.clone();
+        self.json_flatten_depth = layer.json_flatten_depth.unwrap_or(1);
+        // Geometries within the buffer of the feature query are kept
+        self.clip_rect = layer
+            .clip_rect_buffer(self.tile_size, self.grid_tile_size)
+            .map(|buffer| ClipRect::new(self.tile_size, buffer));
+        self.simplify_tolerance = if layer.simplify.unwrap_or(false) {
+            Some(layer.tolerance.unwrap_or(1.0))
+        } else {
+            None
+        };
+        self.simplify_algorithm = layer
+            .simplify_algorithm
+            .unwrap_or(SimplifyAlgorithm::DouglasPeucker);
+        self.snap_step = match layer.precision {
+            Some(step) if step > 1 => Some(step as i32),
+            _ => None,
+        };
         mvt_layer
     }
 
+    fn simplify_screen_geom<T: Simplify>(&self, geom: T) -> T {
+        match self.simplify_tolerance {
+            Some(tolerance) => geom.simplify_with(self.simplify_algorithm, tolerance),
+            None => geom,
+        }
+    }
+
+    fn snap_screen_geom<T: Snap>(&self, geom: T) -> T {
+        match self.snap_step {
+            Some(step) => geom.snap(step),
+            None => geom,
+        }
+    }
+
+    fn encode_screen_geom<T>(&self, geom: T, seq: &mut CommandSequence)
+        where T: EncodableGeom + Clip,
+              T::Output: EncodableGeom
+    {
+        match self.clip_rect {
+            Some(ref rect) => geom.clip(rect).encode_into(seq),
+            None => geom.encode_into(seq),
+        }
+    }
+
     pub fn encode_geom(&self, geom: geom::GeometryType) -> CommandSequence {
+        let mut seq = CommandSequence::new();
+        self.encode_geom_into(geom, &mut seq);
+        seq
+    }
+
+    /// Encode geometry into `seq`, replacing its content
+    pub fn encode_geom_into(&self, geom: geom::GeometryType, seq: &mut CommandSequence) {
         match geom {
             GeometryType::Point(ref g) => {
-                screen::Point::from_geom(&self.extent, self.reverse_y, self.tile_size, g).encode()
+                let screen_geom =
+                    screen::Point::from_geom(&self.extent, self.reverse_y, self.tile_size, g);
+                self.encode_screen_geom(self.snap_screen_geom(screen_geom), seq)
             }
             GeometryType::MultiPoint(ref g) => {
-                screen::MultiPoint::from_geom(&self.extent, self.reverse_y, self.tile_size, g)
-                    .encode()
+                let screen_geom =
+                    screen::MultiPoint::from_geom(&self.extent, self.reverse_y, self.tile_size, g);
+                self.encode_screen_geom(self.snap_screen_geom(screen_geom), seq)
             }
             GeometryType::LineString(ref g) => {
-                screen::LineString::from_geom(&self.extent, self.reverse_y, self.tile_size, g)
-                    .encode()
+                let screen_geom = screen::LineString::from_geom(&self.extent,
+                                                                self.reverse_y,
+                                                                self.tile_size,
+                                                                g);
+                let screen_geom = self.snap_screen_geom(screen_geom);
+                self.encode_screen_geom(self.simplify_screen_geom(screen_geom), seq)
             }
             GeometryType::MultiLineString(ref g) => {
-                screen::MultiLineString::from_geom(&self.extent, self.reverse_y, self.tile_size, g)
-                    .encode()
+                let screen_geom = screen::MultiLineString::from_geom(&self.extent,
+                                                                     self.reverse_y,
+                                                                     self.tile_size,
+                                                                     g);
+                let screen_geom = self.snap_screen_geom(screen_geom);
+                self.encode_screen_geom(self.simplify_screen_geom(screen_geom), seq)
             }
             GeometryType::Polygon(ref g) => {
-                screen::Polygon::from_geom(&self.extent, self.reverse_y, self.tile_size, g).encode()
+                let screen_geom = screen::Polygon::from_geom(&self.extent,
+                                                             self.reverse_y,
+                                                             self.tile_size,
+                                                             g);
+                let screen_geom = self.snap_screen_geom(screen_geom);
+                self.encode_screen_geom(self.simplify_screen_geom(screen_geom), seq)
             }
             GeometryType::MultiPolygon(ref g) => {
-                screen::MultiPolygon::from_geom(&self.extent, self.reverse_y, self.tile_size, g)
-                    .encode()
+                let screen_geom = screen::MultiPolygon::from_geom(&self.extent,
+                                                                  self.reverse_y,
+                                                                  self.tile_size,
+                                                                  g);
+                let screen_geom = self.snap_screen_geom(screen_geom);
+                self.encode_screen_geom(self.simplify_screen_geom(screen_geom), seq)
+            }
+            GeometryType::GeometryCollection(_) => {
+                panic!("GeometryCollection has to be flattened before encoding")
             }
-            GeometryType::GeometryCollection(_) => panic!("GeometryCollection not supported"),
         }
     }
 
@@ -226,47 +417,65 @@ impl<'a> Tile<'a> {
         mvt_feature.mut_tags().push(validx as u32);
     }
 
-    pub fn add_feature(&self, mut mvt_layer: &mut vector_tile::Tile_Layer, feature: &Feature) {
+    /// Encode feature into layer. Members of geometry collections are encoded as separate
+    /// features with their own geometry type, sharing the attributes of `feature`.
+    /// Only the first of them gets the feature id, since ids have to be unique within a layer.
+    pub fn add_feature(&mut self,
+                       mut mvt_layer: &mut vector_tile::Tile_Layer,
+                       feature: &Feature) {
         let mut mvt_feature = vector_tile::Tile_Feature::new();
         if let Some(fid) = feature.fid() {
             mvt_feature.set_id(fid);
         }
+        let mut attrs = Vec::new();
         for attr in feature.attributes() {
-            let mut mvt_value = vector_tile::Tile_Value::new();
-            match attr.value {
-                FeatureAttrValType::String(ref v) => {
-                    mvt_value.set_string_value(v.clone());
-                }
-                FeatureAttrValType::Double(v) => {
-                    mvt_value.set_double_value(v);
-                }
-                FeatureAttrValType::Float(v) => {
-                    mvt_value.set_float_value(v);
-                }
-                FeatureAttrValType::Int(v) => {
-                    mvt_value.set_int_value(v);
-                }
-                FeatureAttrValType::UInt(v) => {
-                    mvt_value.set_uint_value(v);
-                }
-                FeatureAttrValType::SInt(v) => {
-                    mvt_value.set_sint_value(v);
-                }
-                FeatureAttrValType::Bool(v) => {
-                    mvt_value.set_bool_value(v);
+            if self.flatten_json.contains(&attr.key) {
+                if let Some(members) = attr.flatten_json(self.json_flatten_depth) {
+                    attrs.extend(members);
+                    continue;
                 }
+                debug!("Field '{}': no JSON object", attr.key);
             }
-            Tile::add_feature_attribute(&mut mvt_layer,
-                                        &mut mvt_feature,
-                                        attr.key.clone(),
-                                        mvt_value);
+            attrs.push(attr);
+        }
+        for attr in attrs {
+            let value = match self.field_types.get(&attr.key) {
+                Some(field_type) => {
+                    attr.value
+                        .convert(*field_type)
+                        .unwrap_or_else(|| {
+                                            debug!("Field '{}': can't convert {:?} to {}",
+                                                   attr.key,
+                                                   attr.value,
+                                                   field_type.name());
+                                            attr.value.clone()
+                                        })
+                }
+                None => attr.value,
+            };
+            self.tags.add_tag(&mut mvt_layer, &mut mvt_feature, attr.key, value);
         }
         if let Ok(geom) = feature.geometry() {
-            if !geom.is_empty() {
-                mvt_feature.set_field_type(geom.mvt_field_type());
-                mvt_feature.set_geometry(self.encode_geom(geom).vec());
-                mvt_layer.mut_features().push(mvt_feature);
+            let mut seq = mem::replace(&mut self.geom_buffer, CommandSequence::new());
+            // Members of geometry collections are added as separate features
+            let mut first = true;
+            for geom in geom.flatten() {
+                if !geom.is_empty() {
+                    let mut mvt_feature = mvt_feature.clone();
+                    mvt_feature.set_field_type(geom.mvt_field_type());
+                    self.encode_geom_into(geom, &mut seq);
+                    // Skip features clipped away
+                    if !seq.0.is_empty() {
+                        if !first {
+                            mvt_feature.clear_id();
+                        }
+                        mvt_feature.set_geometry(seq.vec());
+                        mvt_layer.mut_features().push(mvt_feature);
+                        first = false;
+                    }
+                }
             }
+            self.geom_buffer = seq;
         }
     }
 
@@ -301,6 +510,29 @@ impl<'a> Tile<'a> {
         parse_from_reader::<vector_tile::Tile>(&mut reader)
     }
 
+    /// Tile without any features
+    pub fn is_empty(mvt_tile: &vector_tile::Tile) -> bool {
+        mvt_tile.get_layers().iter().all(|layer| layer.get_features().is_empty())
+    }
+
+    /// Check for gzip magic bytes
+    pub fn is_gzipped(data: &[u8]) -> bool {
+        data.len() >= 2 && data[0] == 0x1f && data[1] == 0x8b
+    }
+
+    pub fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut gz = GzEncoder::new(Vec::new(), Compression::Default);
+        let _ = gz.write_all(data);
+        gz.finish().unwrap_or(Vec::new())
+    }
+
+    pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut gz = GzDecoder::new(data)?;
+        let mut v = Vec::new();
+        gz.read_to_end(&mut v)?;
+        Ok(v)
+    }
+
     pub fn binary_tile(mvt_tile: &vector_tile::Tile) -> Vec<u8> {
         let mut v = Vec::new();
         Self::write_to(&mut v, mvt_tile);
@@ -51,6 +51,177 @@ fn test_point_to_screen_coords() {
 }
 
 
+#[test]
+fn test_tile_extent() {
+    let extent = Extent {
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 4096.0,
+        maxy: 4096.0,
+    };
+    let layer = Layer::new("points");
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: Vec::new(),
+        geometry: GeometryType::Point(geom::Point::new(1024.0, 3072.0, None)),
+    };
+    let mut decoded = Vec::new();
+    for tile_size in vec![4096, 256] {
+        let mut tile = Tile::new(&extent, tile_size, true);
+        let mut mvt_layer = tile.new_layer(&layer);
+        tile.add_feature(&mut mvt_layer, &feature);
+        assert_eq!(mvt_layer.get_extent(), tile_size);
+        let seq = ::mvt::geom_encoder::CommandSequence(mvt_layer.get_features()[0]
+                                                            .get_geometry()
+                                                            .to_vec());
+        decoded.push(seq.decode());
+    }
+    assert_eq!(decoded[0], vec![vec![screen::Point { x: 1024, y: 1024 }]]);
+    // Coordinates scale proportionally with tile extent
+    assert_eq!(decoded[1], vec![vec![screen::Point { x: 64, y: 64 }]]);
+}
+
+#[test]
+fn test_tile_precision() {
+    let extent = Extent {
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 4096.0,
+        maxy: 4096.0,
+    };
+    let mut layer = Layer::new("lines");
+    layer.precision = Some(4);
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: Vec::new(),
+        geometry: GeometryType::LineString(geom::LineString {
+                                               points: vec![geom::Point::new(9.0, 4089.0, None),
+                                                            geom::Point::new(7.0, 4087.0, None),
+                                                            geom::Point::new(1023.0, 3071.0, None)],
+                                               srid: None,
+                                           }),
+    };
+    let mut tile = Tile::new(&extent, 4096, true);
+    let mut mvt_layer = tile.new_layer(&layer);
+    tile.add_feature(&mut mvt_layer, &feature);
+    let seq = ::mvt::geom_encoder::CommandSequence(mvt_layer.get_features()[0]
+                                                        .get_geometry()
+                                                        .to_vec());
+    // Coordinates snapped to multiples of 4, without repeated points
+    assert_eq!(seq.decode(),
+               vec![vec![screen::Point { x: 8, y: 8 }, screen::Point { x: 1024, y: 1024 }]]);
+}
+
+#[test]
+fn test_tile_buffer() {
+    // Line east of tile A within a distance of 5 pixels, crossing into tile B
+    let line = geom::LineString {
+        points: vec![geom::Point::new(261.0, 128.0, None), geom::Point::new(300.0, 128.0, None)],
+        srid: None,
+    };
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: Vec::new(),
+        geometry: GeometryType::LineString(line),
+    };
+    let extent_a = Extent {
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 256.0,
+        maxy: 256.0,
+    };
+    let extent_b = Extent {
+        minx: 256.0,
+        miny: 0.0,
+        maxx: 512.0,
+        maxy: 256.0,
+    };
+    let mut layer = Layer::new("lines");
+    layer.buffer_size = Some(0);
+
+    let feature_count = |extent: &Extent, layer: &Layer| {
+        let mut tile = Tile::new(extent, 4096, true);
+        let mut mvt_layer = tile.new_layer(layer);
+        tile.add_feature(&mut mvt_layer, &feature);
+        mvt_layer.get_features().len()
+    };
+    // Clipped away without buffer
+    assert_eq!(feature_count(&extent_a, &layer), 0);
+    assert_eq!(feature_count(&extent_b, &layer), 1);
+
+    // Buffer of 10 pixels (= 160 tile coordinate units)
+    layer.buffer_size = Some(10);
+    assert_eq!(feature_count(&extent_a, &layer), 1);
+    assert_eq!(feature_count(&extent_b, &layer), 1);
+
+    let mut tile = Tile::new(&extent_a, 4096, true);
+    let mut mvt_layer = tile.new_layer(&layer);
+    tile.add_feature(&mut mvt_layer, &feature);
+    let seq = ::mvt::geom_encoder::CommandSequence(mvt_layer.get_features()[0]
+                                                        .get_geometry()
+                                                        .to_vec());
+    assert_eq!(seq.decode(),
+               vec![vec![screen::Point { x: 4176, y: 2048 }, screen::Point { x: 4256, y: 2048 }]]);
+
+    // No clipping
+    layer.buffer_size = Some(0);
+    layer.intersect = Some(false);
+    assert_eq!(feature_count(&extent_a, &layer), 1);
+}
+
+#[test]
+fn test_tile_intersect() {
+    use std::f64::consts::PI;
+
+    // Circle around the tile origin, covering the lower left quarter of the tile
+    let mut points: Vec<geom::Point> = (0..360)
+        .map(|i| {
+                 let angle = i as f64 * PI / 180.0;
+                 geom::Point::new(1000.0 * angle.cos(), 1000.0 * angle.sin(), None)
+             })
+        .collect();
+    let first = geom::Point::new(points[0].x, points[0].y, None);
+    points.push(first);
+    let polygon = geom::Polygon {
+        rings: vec![geom::LineString {
+                        points: points,
+                        srid: None,
+                    }],
+        srid: None,
+    };
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: Vec::new(),
+        geometry: GeometryType::Polygon(polygon),
+    };
+    let extent = Extent {
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 2000.0,
+        maxy: 2000.0,
+    };
+    let mut layer = Layer::new("polygons");
+    layer.intersect = Some(false);
+
+    let vertex_count = |layer: &Layer| {
+        let mut tile = Tile::new(&extent, 4096, true);
+        let mut mvt_layer = tile.new_layer(layer);
+        tile.add_feature(&mut mvt_layer, &feature);
+        let seq = ::mvt::geom_encoder::CommandSequence(mvt_layer.get_features()[0]
+                                                            .get_geometry()
+                                                            .to_vec());
+        seq.decode().iter().map(|ring| ring.len()).sum::<usize>()
+    };
+    let full_count = vertex_count(&layer);
+    assert!(full_count > 300);
+
+    layer.intersect = Some(true);
+    let clipped_count = vertex_count(&layer);
+    // Quarter circle arc plus tile corner
+    assert!(clipped_count < full_count / 3);
+    assert!(clipped_count > 50);
+}
+
 #[test]
 fn test_tile_values() {
     let mut value = vector_tile::Tile_Value::new();
@@ -233,6 +404,59 @@ const TILE_EXAMPLE: &'static str = r#"Tile {
     }
 }"#;
 
+#[test]
+fn test_gzip_roundtrip() {
+    use cache::{Cache, Filecache};
+    use std::env;
+    use std::fs;
+    use std::io::Read;
+
+    let mut f = File::open("src/test/tile.pbf").unwrap();
+    let mut data = Vec::new();
+    f.read_to_end(&mut data).unwrap();
+    assert!(!Tile::is_gzipped(&data));
+
+    let tilegz = Tile::gzip(&data);
+    assert!(Tile::is_gzipped(&tilegz));
+    assert!(tilegz.len() < data.len());
+
+    let mut dir = env::temp_dir();
+    dir.push("t_rex_test_gzip");
+    let basepath = format!("{}", &dir.display());
+    let _ = fs::remove_dir_all(&basepath);
+    let cache = Filecache {
+        basepath: basepath,
+        baseurl: None,
+        max_age: None,
+        delete_expired: false,
+        shard_levels: 0,
+        dedup: false,
+    };
+    let _ = cache.write("tileset/0/0/0.pbf", &tilegz);
+    let mut cached = Vec::new();
+    cache.read("tileset/0/0/0.pbf", |f| { let _ = f.read_to_end(&mut cached); });
+    assert!(Tile::is_gzipped(&cached));
+    assert_eq!(Tile::gunzip(&cached).unwrap(), data);
+
+    let tile = Tile::read_gz_from(&mut &cached[..]).unwrap();
+    assert_eq!(tile.get_layers()[0].get_name(), "roads");
+}
+
+#[test]
+fn test_empty_tile() {
+    let mut mvt_tile = vector_tile::Tile::new();
+    assert!(Tile::is_empty(&mvt_tile));
+
+    let mut mvt_layer = vector_tile::Tile_Layer::new();
+    mvt_layer.set_name(String::from("points"));
+    mvt_tile.mut_layers().push(mvt_layer);
+    assert!(Tile::is_empty(&mvt_tile));
+
+    let mut f = File::open("src/test/tile.pbf").unwrap();
+    let mvt_tile = Tile::read_from(&mut f).unwrap();
+    assert!(!Tile::is_empty(&mvt_tile));
+}
+
 #[test]
 fn test_build_mvt() {
     // https://github.com/mapbox/vector-tile-spec/tree/master/2.1#45-example
@@ -350,3 +574,309 @@ fn test_build_mvt_with_helpers() {
     path.push("out.pbf");
     tile.to_file(&format!("{}", &path.display()));
 }
+
+#[test]
+fn test_feature_attributes_roundtrip() {
+    let extent = Extent {
+        minx: 958826.08,
+        miny: 5987771.04,
+        maxx: 978393.96,
+        maxy: 6007338.92,
+    };
+    let mut tile = Tile::new(&extent, 4096, false);
+    let layer = Layer::new("points");
+    let mut mvt_layer = tile.new_layer(&layer);
+
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: vec![FeatureAttr {
+                             key: String::from("name"),
+                             value: FeatureAttrValType::String(String::from("Bern")),
+                         },
+                         FeatureAttr {
+                             key: String::from("pop"),
+                             value: FeatureAttrValType::Int(12345),
+                         },
+                         FeatureAttr {
+                             key: String::from("capital"),
+                             value: FeatureAttrValType::Bool(true),
+                         }],
+        geometry: GeometryType::Point(geom::Point::new(960000.0, 6002729.0, Some(3857))),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+    tile.add_layer(mvt_layer);
+
+    let data = Tile::binary_tile(&tile.mvt_tile);
+    let decoded = Tile::read_from(&mut &data[..]).unwrap();
+    let ref layer = decoded.get_layers()[0];
+    let ref feature = layer.get_features()[0];
+    let tags = feature.get_tags();
+    assert_eq!(tags.len(), 6);
+    assert_eq!(layer.get_keys()[tags[0] as usize], "name");
+    assert_eq!(layer.get_values()[tags[1] as usize].get_string_value(),
+               "Bern");
+    assert_eq!(layer.get_keys()[tags[2] as usize], "pop");
+    assert_eq!(layer.get_values()[tags[3] as usize].get_int_value(), 12345);
+    assert_eq!(layer.get_keys()[tags[4] as usize], "capital");
+    assert_eq!(layer.get_values()[tags[5] as usize].get_bool_value(), true);
+}
+
+#[test]
+fn test_field_type_hints() {
+    use core::feature::FieldType;
+
+    let extent = Extent {
+        minx: 958826.08,
+        miny: 5987771.04,
+        maxx: 978393.96,
+        maxy: 6007338.92,
+    };
+    let mut tile = Tile::new(&extent, 4096, false);
+    let mut layer = Layer::new("points");
+    layer.field_types.insert("pop".to_string(), FieldType::Int);
+    layer.field_types.insert("name".to_string(), FieldType::Int);
+    let mut mvt_layer = tile.new_layer(&layer);
+
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: vec![FeatureAttr {
+                             key: String::from("pop"),
+                             value: FeatureAttrValType::String(String::from("42")),
+                         },
+                         FeatureAttr {
+                             key: String::from("name"),
+                             value: FeatureAttrValType::String(String::from("Bern")),
+                         }],
+        geometry: GeometryType::Point(geom::Point::new(960000.0, 6002729.0, Some(3857))),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+    tile.add_layer(mvt_layer);
+
+    let ref layer = tile.mvt_tile.get_layers()[0];
+    let tags = layer.get_features()[0].get_tags();
+    let ref pop = layer.get_values()[tags[1] as usize];
+    assert!(pop.has_int_value());
+    assert_eq!(pop.get_int_value(), 42);
+    // Values not convertible are kept
+    assert_eq!(layer.get_values()[tags[3] as usize].get_string_value(),
+               "Bern");
+}
+
+#[test]
+fn test_flatten_json() {
+    let extent = Extent {
+        minx: 958826.08,
+        miny: 5987771.04,
+        maxx: 978393.96,
+        maxy: 6007338.92,
+    };
+    let mut tile = Tile::new(&extent, 4096, false);
+    let mut layer = Layer::new("points");
+    layer.flatten_json = vec!["tags".to_string(), "name".to_string()];
+    let mut mvt_layer = tile.new_layer(&layer);
+
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: vec![FeatureAttr {
+                             key: String::from("name"),
+                             value: FeatureAttrValType::String(String::from("Bern")),
+                         },
+                         FeatureAttr {
+                             key: String::from("tags"),
+                             value: FeatureAttrValType::String(String::from(r#"{"amenity": "cafe", "seats": 24}"#)),
+                         }],
+        geometry: GeometryType::Point(geom::Point::new(960000.0, 6002729.0, Some(3857))),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+
+    // One tag per JSON member, values without JSON object are kept
+    assert_eq!(mvt_layer.get_keys(),
+               &["name".to_string(), "tags_amenity".to_string(), "tags_seats".to_string()]);
+    let tags = mvt_layer.get_features()[0].get_tags();
+    assert_eq!(tags.len(), 6);
+    assert_eq!(mvt_layer.get_values()[tags[3] as usize].get_string_value(),
+               "cafe");
+    assert_eq!(mvt_layer.get_values()[tags[5] as usize].get_int_value(), 24);
+
+    // Nested objects up to json_flatten_depth
+    layer.json_flatten_depth = Some(2);
+    let mut mvt_layer = tile.new_layer(&layer);
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: vec![FeatureAttr {
+                             key: String::from("tags"),
+                             value: FeatureAttrValType::String(String::from(r#"{"address": {"city": "Bern", "geo": {"lat": 46.95}}, "open": null}"#)),
+                         }],
+        geometry: GeometryType::Point(geom::Point::new(960000.0, 6002729.0, Some(3857))),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+    assert_eq!(mvt_layer.get_keys(), &["tags_address.city".to_string()]);
+}
+
+#[test]
+fn test_feature_ids() {
+    let extent = Extent {
+        minx: 958826.08,
+        miny: 5987771.04,
+        maxx: 978393.96,
+        maxy: 6007338.92,
+    };
+    let mut tile = Tile::new(&extent, 4096, false);
+    let layer = Layer::new("points");
+    let mut mvt_layer = tile.new_layer(&layer);
+
+    let feature = FeatureStruct {
+        fid: Some(42),
+        attributes: vec![],
+        geometry: GeometryType::Point(geom::Point::new(960000.0, 6002729.0, Some(3857))),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: vec![],
+        geometry: GeometryType::Point(geom::Point::new(960000.0, 6002729.0, Some(3857))),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+
+    let features = mvt_layer.get_features();
+    assert!(features[0].has_id());
+    assert_eq!(features[0].get_id(), 42);
+    assert!(!features[1].has_id());
+}
+
+#[test]
+fn test_geometry_collection_features() {
+    use postgis::ewkb;
+
+    let extent = Extent {
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 256.0,
+        maxy: 256.0,
+    };
+    let line = geom::LineString {
+        points: vec![geom::Point::new(10.0, 10.0, None), geom::Point::new(100.0, 10.0, None)],
+        srid: None,
+    };
+    let collection = geom::GeometryCollection {
+        geometries: vec![ewkb::GeometryT::Point(geom::Point::new(10.0, 10.0, None)),
+                         ewkb::GeometryT::LineString(line.clone())],
+        srid: None,
+    };
+    let feature = FeatureStruct {
+        fid: Some(7),
+        attributes: vec![FeatureAttr {
+                             key: String::from("name"),
+                             value: FeatureAttrValType::String(String::from("Bern")),
+                         }],
+        geometry: GeometryType::GeometryCollection(collection),
+    };
+    let mut tile = Tile::new(&extent, 4096, false);
+    let layer = Layer::new("mixed");
+    let mut mvt_layer = tile.new_layer(&layer);
+    tile.add_feature(&mut mvt_layer, &feature);
+
+    // One feature for each member, with the geometry type of the member
+    let features = mvt_layer.get_features();
+    assert_eq!(features.len(), 2);
+    assert_eq!(features[0].get_field_type(), vector_tile::Tile_GeomType::POINT);
+    assert_eq!(features[0].get_geometry(), &[9, 320, 320]);
+    assert_eq!(features[1].get_field_type(),
+               vector_tile::Tile_GeomType::LINESTRING);
+    assert_eq!(features[1].get_geometry(),
+               &tile.encode_geom(GeometryType::LineString(line)).vec()[..]);
+    // Attributes of the source row are shared, the fid is kept unique
+    assert_eq!(features[0].get_tags(), features[1].get_tags());
+    assert_eq!(features[0].get_tags().len(), 2);
+    assert_eq!(features[0].get_id(), 7);
+    assert!(!features[1].has_id());
+}
+
+#[test]
+fn test_geometry_buffer_reuse() {
+    let extent = Extent {
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 256.0,
+        maxy: 256.0,
+    };
+    let line = geom::LineString {
+        points: vec![geom::Point::new(10.0, 10.0, None),
+                     geom::Point::new(100.0, 10.0, None),
+                     geom::Point::new(100.0, 100.0, None)],
+        srid: None,
+    };
+    let mut tile = Tile::new(&extent, 4096, false);
+    let layer = Layer::new("mixed");
+    let mut mvt_layer = tile.new_layer(&layer);
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: vec![],
+        geometry: GeometryType::LineString(line.clone()),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: vec![],
+        geometry: GeometryType::Point(geom::Point::new(10.0, 10.0, None)),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+
+    // Shorter geometry after longer one must not contain leftovers
+    let features = mvt_layer.get_features();
+    assert_eq!(features[0].get_geometry(),
+               &tile.encode_geom(GeometryType::LineString(line)).vec()[..]);
+    assert_eq!(features[1].get_geometry(), &[9, 320, 320]);
+}
+
+#[test]
+fn test_tag_deduplication() {
+    let extent = Extent {
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 256.0,
+        maxy: 256.0,
+    };
+    let mut tile = Tile::new(&extent, 4096, false);
+    let layer = Layer::new("points");
+    let mut mvt_layer = tile.new_layer(&layer);
+    for i in 0..1000 {
+        let feature = FeatureStruct {
+            fid: Some(i),
+            attributes: vec![FeatureAttr {
+                                 key: String::from("category"),
+                                 value: FeatureAttrValType::String(String::from("shop")),
+                             },
+                             FeatureAttr {
+                                 key: String::from("rank"),
+                                 value: FeatureAttrValType::Int((i % 2) as i64),
+                             }],
+            geometry: GeometryType::Point(geom::Point::new(10.0, 10.0, None)),
+        };
+        tile.add_feature(&mut mvt_layer, &feature);
+    }
+    assert_eq!(mvt_layer.get_features().len(), 1000);
+    assert_eq!(mvt_layer.get_keys(), &["category", "rank"]);
+    assert_eq!(mvt_layer.get_values().len(), 3);
+    assert_eq!(mvt_layer.get_values()[0].get_string_value(), "shop");
+    assert_eq!(mvt_layer.get_features()[0].get_tags(), &[0, 0, 1, 1]);
+    assert_eq!(mvt_layer.get_features()[1].get_tags(), &[0, 0, 1, 2]);
+
+    // Values of different type are not merged
+    let feature = FeatureStruct {
+        fid: None,
+        attributes: vec![FeatureAttr {
+                             key: String::from("rank"),
+                             value: FeatureAttrValType::Double(0.0),
+                         }],
+        geometry: GeometryType::Point(geom::Point::new(10.0, 10.0, None)),
+    };
+    tile.add_feature(&mut mvt_layer, &feature);
+    assert_eq!(mvt_layer.get_values().len(), 4);
+
+    // Tables are per layer
+    let mut mvt_layer = tile.new_layer(&Layer::new("other"));
+    tile.add_feature(&mut mvt_layer, &feature);
+    assert_eq!(mvt_layer.get_keys(), &["rank"]);
+    assert_eq!(mvt_layer.get_features()[0].get_tags(), &[0, 0]);
+}
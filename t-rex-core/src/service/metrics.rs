@@ -0,0 +1,149 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+
+/// Upper bounds of render duration histogram buckets in seconds
+const DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+                                     10.0];
+
+#[derive(Default)]
+struct TilesetCounters {
+    requests: u64,
+    cache_hits: u64,
+    cache_misses: u64,
+}
+
+struct Histogram {
+    /// Number of observations per bucket (not cumulative), last entry is +Inf
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+/// Tile service metrics exported in Prometheus text format
+pub struct Metrics {
+    tilesets: Mutex<BTreeMap<String, TilesetCounters>>,
+    render_duration: Mutex<Histogram>,
+}
+
+/// Duration in seconds
+pub fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1000000000.0
+}
+
+/// Escape label value for Prometheus text format
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Write counter with tileset label
+fn write_counter<F>(text: &mut String,
+                    name: &str,
+                    help: &str,
+                    tilesets: &BTreeMap<String, TilesetCounters>,
+                    value: F)
+    where F: Fn(&TilesetCounters) -> u64
+{
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} counter", name);
+    for (tileset, counters) in tilesets.iter() {
+        let _ = writeln!(text,
+                         "{}{{tileset=\"{}\"}} {}",
+                         name,
+                         label_value(tileset),
+                         value(counters));
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            tilesets: Mutex::new(BTreeMap::new()),
+            render_duration: Mutex::new(Histogram {
+                                            counts: vec![0; DURATION_BUCKETS.len() + 1],
+                                            sum: 0.0,
+                                        }),
+        }
+    }
+    /// Count tile request
+    pub fn tile_request(&self, tileset: &str, cache_hit: bool) {
+        let mut tilesets = self.tilesets.lock().unwrap();
+        let counters = tilesets
+            .entry(tileset.to_string())
+            .or_insert_with(TilesetCounters::default);
+        counters.requests += 1;
+        if cache_hit {
+            counters.cache_hits += 1;
+        } else {
+            counters.cache_misses += 1;
+        }
+    }
+    /// Record duration of rendering a tile
+    pub fn render_duration(&self, secs: f64) {
+        let mut histogram = self.render_duration.lock().unwrap();
+        let bucket = DURATION_BUCKETS
+            .iter()
+            .position(|le| secs <= *le)
+            .unwrap_or(DURATION_BUCKETS.len());
+        histogram.counts[bucket] += 1;
+        histogram.sum += secs;
+    }
+    /// Metrics in Prometheus text exposition format, including the number of
+    /// connections in use per datasource
+    pub fn prometheus_text(&self, pool_connections: &[(String, u32)]) -> String {
+        let mut text = String::new();
+        {
+            let tilesets = self.tilesets.lock().unwrap();
+            write_counter(&mut text,
+                          "trex_tile_requests_total",
+                          "Number of tile requests",
+                          &tilesets,
+                          |c| c.requests);
+            write_counter(&mut text,
+                          "trex_tile_cache_hits_total",
+                          "Number of tiles served from cache",
+                          &tilesets,
+                          |c| c.cache_hits);
+            write_counter(&mut text,
+                          "trex_tile_cache_misses_total",
+                          "Number of tiles not found in cache",
+                          &tilesets,
+                          |c| c.cache_misses);
+        }
+        {
+            let histogram = self.render_duration.lock().unwrap();
+            let name = "trex_tile_render_duration_seconds";
+            let _ = writeln!(text, "# HELP {} Duration of rendering tiles not found in cache", name);
+            let _ = writeln!(text, "# TYPE {} histogram", name);
+            let mut cumulative = 0;
+            for (i, le) in DURATION_BUCKETS.iter().enumerate() {
+                cumulative += histogram.counts[i];
+                let _ = writeln!(text, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+            }
+            cumulative += histogram.counts[DURATION_BUCKETS.len()];
+            let _ = writeln!(text, "{}_bucket{{le=\"+Inf\"}} {}", name, cumulative);
+            let _ = writeln!(text, "{}_sum {}", name, histogram.sum);
+            let _ = writeln!(text, "{}_count {}", name, cumulative);
+        }
+        let name = "trex_pool_connections_in_use";
+        let _ = writeln!(text, "# HELP {} Database connections in use", name);
+        let _ = writeln!(text, "# TYPE {} gauge", name);
+        for &(ref datasource, connections) in pool_connections {
+            let _ = writeln!(text,
+                             "{}{{datasource=\"{}\"}} {}",
+                             name,
+                             label_value(datasource),
+                             connections);
+        }
+        text
+    }
+}
@@ -0,0 +1,42 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+use service::metrics::{Metrics, duration_secs};
+use std::time::Duration;
+
+
+#[test]
+fn test_counters() {
+    let metrics = Metrics::new();
+    metrics.tile_request("osm", false);
+    metrics.tile_request("osm", true);
+    metrics.tile_request("osm", true);
+    metrics.tile_request("ne\"10m", false);
+
+    let text = metrics.prometheus_text(&[]);
+    assert!(text.contains("trex_tile_requests_total{tileset=\"osm\"} 3\n"));
+    assert!(text.contains("trex_tile_cache_hits_total{tileset=\"osm\"} 2\n"));
+    assert!(text.contains("trex_tile_cache_misses_total{tileset=\"osm\"} 1\n"));
+    assert!(text.contains("trex_tile_requests_total{tileset=\"ne\\\"10m\"} 1\n"));
+}
+
+#[test]
+fn test_histogram() {
+    let metrics = Metrics::new();
+    metrics.render_duration(0.003);
+    metrics.render_duration(0.2);
+    metrics.render_duration(20.0);
+
+    let text = metrics.prometheus_text(&[("default".to_string(), 3)]);
+    assert!(text.contains("trex_tile_render_duration_seconds_bucket{le=\"0.005\"} 1\n"));
+    assert!(text.contains("trex_tile_render_duration_seconds_bucket{le=\"0.1\"} 1\n"));
+    assert!(text.contains("trex_tile_render_duration_seconds_bucket{le=\"0.25\"} 2\n"));
+    assert!(text.contains("trex_tile_render_duration_seconds_bucket{le=\"10\"} 2\n"));
+    assert!(text.contains("trex_tile_render_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+    assert!(text.contains("trex_tile_render_duration_seconds_count 3\n"));
+    assert!(text.contains("trex_pool_connections_in_use{datasource=\"default\"} 3\n"));
+
+    assert_eq!(duration_secs(Duration::from_millis(1500)), 1.5);
+}
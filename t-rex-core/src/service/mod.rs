@@ -5,8 +5,11 @@
 
 pub mod mvt;
 pub mod glstyle_converter;
+pub mod metrics;
 
 #[cfg(test)]
 mod mvt_test;
 #[cfg(test)]
 mod glstyle_converter_test;
+#[cfg(test)]
+mod metrics_test;
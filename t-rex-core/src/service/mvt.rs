@@ -3,50 +3,198 @@
 // Licensed under the MIT License. See LICENSE file in the project root for full license information.
 //
 
-use datasource::{Datasource, DatasourceInput};
-use datasource::PostgisInput;
-use core::grid::{Grid, Extent, ExtentInt};
-use core::layer::Layer;
+use datasource::{Datasource, Datasources, DatasourceInput, PostgisInput};
+use core::grid::{Grid, Extent};
+use core::layer::{Layer, DEFAULT_MAX_FEATURES};
 use core::Config;
+use core::error::TrexError;
 use core::ApplicationCfg;
-use core::config::TilesetCfg;
+use core::config::{TilesetCfg, BufferCfg};
 use mvt::tile::Tile;
 use mvt::vector_tile;
 use cache::{Cache, Tilecache};
+use service::metrics::{Metrics, duration_secs};
 use serde_json;
 use pbr::ProgressBar;
-use std::io::Stdout;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Stdout};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use crossbeam;
+use log::LogLevel;
 
 
 /// Collection of layers in one MVT
 pub struct Tileset {
     pub name: String,
     pub extent: Option<Extent>,
+    pub minzoom: Option<u8>,
+    pub maxzoom: Option<u8>,
+    /// Serve requests above maxzoom with the content of maxzoom
+    pub overzoom: bool,
+    /// Retrieve layers from the same table with a single query
+    pub batch_layers: bool,
     pub layers: Vec<Layer>,
 }
 
 /// Mapbox Vector Tile Service
 pub struct MvtService {
-    pub input: PostgisInput,
+    pub datasources: Datasources,
     pub grid: Grid,
+    /// MVT coordinate extent
+    pub extent: u32,
     pub tilesets: Vec<Tileset>,
     pub cache: Tilecache,
+    /// Store gzip compressed tiles in cache
+    pub gzip: bool,
+    /// Include layers without features in tiles
+    pub keep_empty_layers: bool,
+    /// Default buffer of layers by geometry type
+    pub default_buffer: BufferCfg,
+    /// Request metrics
+    pub metrics: Metrics,
+    /// Detected data extents by tileset name and SRID
+    pub data_extents: Mutex<HashMap<(String, i32), Option<Extent>>>,
+}
+
+/// Summary of tile generation
+#[derive(Debug)]
+pub struct GenerateStats {
+    /// Number of tiles written to the cache
+    pub tiles: u64,
+    /// Total size of written tiles
+    pub bytes: u64,
+    pub elapsed: Duration,
 }
 
 type JsonResult = Result<serde_json::Value, serde_json::error::Error>;
 
 impl MvtService {
-    pub fn connect(&mut self) {
-        self.input = self.input.connected();
+    pub fn connect(&mut self) -> Result<(), String> {
+        self.datasources.connect()
     }
-    fn get_tileset(&self, name: &str) -> Option<&Tileset> {
+    pub fn get_tileset(&self, name: &str) -> Option<&Tileset> {
         self.tilesets.iter().find(|t| t.name == name)
     }
+    /// Check configuration and report all problems found.
+    /// Layers of connected datasources are checked against the database.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let maxlevel = self.grid.nlevels() - 1;
+        for (i, tileset) in self.tilesets.iter().enumerate() {
+            if self.tilesets[..i].iter().any(|t| t.name == tileset.name) {
+                errors.push(format!("Duplicate tileset '{}'", tileset.name));
+            }
+            if tileset.layers.is_empty() {
+                errors.push(format!("Tileset '{}' has no layers", tileset.name));
+            }
+            if tileset.minzoom() > tileset.maxzoom() {
+                errors.push(format!("Tileset '{}': minzoom {} is greater than maxzoom {}",
+                                    tileset.name,
+                                    tileset.minzoom(),
+                                    tileset.maxzoom()));
+            }
+            for (j, layer) in tileset.layers.iter().enumerate() {
+                let prefix = format!("Layer '{}' in tileset '{}'", layer.name, tileset.name);
+                if tileset.layers[..j].iter().any(|l| l.name == layer.name) {
+                    errors.push(format!("{}: duplicate layer name", prefix));
+                }
+                if let Some(ref name) = layer.datasource {
+                    if self.datasources.get(name).is_none() {
+                        errors.push(format!("{}: unknown datasource '{}'", prefix, name));
+                    }
+                }
+                // GeoJSON layers contain all features of the file
+                let geojson = match self.datasources.layer_datasource(layer) {
+                    Some(&Datasource::GeoJson(_)) => true,
+                    _ => false,
+                };
+                // Tile proxy layers default to the upstream layer with the same name
+                let tileproxy = match self.datasources.layer_datasource(layer) {
+                    Some(&Datasource::TileProxy(_)) => true,
+                    _ => false,
+                };
+                if geojson && layer.filter.is_some() {
+                    errors.push(format!("{}: 'filter' is not supported by GeoJSON datasources",
+                                        prefix));
+                }
+                if geojson && layer.order_by.is_some() {
+                    errors.push(format!("{}: 'order_by' is not supported by GeoJSON datasources",
+                                        prefix));
+                }
+                let postgis = match self.datasources.layer_datasource(layer) {
+                    Some(&Datasource::Postgis(_)) => true,
+                    _ => false,
+                };
+                if layer.cluster_threshold.is_some() && !postgis {
+                    errors.push(format!("{}: 'cluster_threshold' requires a PostGIS datasource",
+                                        prefix));
+                }
+                let has_query = layer.query.iter().any(|q| q.sql.is_some());
+                if layer.table_name.is_none() && !has_query && !geojson && !tileproxy {
+                    errors.push(format!("{}: either 'table_name' or 'query' is required",
+                                        prefix));
+                }
+                if layer.minzoom() > layer.maxzoom() {
+                    errors.push(format!("{}: minzoom {} is greater than maxzoom {}",
+                                        prefix,
+                                        layer.minzoom(),
+                                        layer.maxzoom()));
+                }
+                if let Some(ds) = self.datasources.layer_datasource(layer) {
+                    errors.extend(ds.check_layer(layer));
+                }
+                if layer.minzoom() > maxlevel {
+                    errors.push(format!("{}: minzoom {} exceeds grid maximum zoom level {}",
+                                        prefix,
+                                        layer.minzoom(),
+                                        maxlevel));
+                }
+                for (k, query) in layer.query.iter().enumerate() {
+                    // Nested ranges are allowed, partial overlaps are ambiguous
+                    let (min1, max1) = (query.minzoom(), query.maxzoom());
+                    for other in &layer.query[..k] {
+                        let (min2, max2) = (other.minzoom(), other.maxzoom());
+                        let overlap = min1 <= max2 && min2 <= max1;
+                        let nested = (min1 <= min2 && max1 >= max2) ||
+                                     (min2 <= min1 && max2 >= max1);
+                        if overlap && (!nested || (min1, max1) == (min2, max2)) {
+                            errors.push(format!("{}: query zoom ranges {}-{} and {}-{} overlap",
+                                                prefix,
+                                                min2,
+                                                max2,
+                                                min1,
+                                                max1));
+                        }
+                    }
+                    if query.sql.is_none() {
+                        errors.push(format!("{}: query without 'sql'", prefix));
+                    }
+                    if query.minzoom() > query.maxzoom() {
+                        errors.push(format!("{}: query minzoom {} is greater than maxzoom {}",
+                                            prefix,
+                                            query.minzoom(),
+                                            query.maxzoom()));
+                    }
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
     /// Get layers (as reference) of given tileset
     fn get_tileset_layers(&self, name: &str) -> Vec<&Layer> {
         match self.get_tileset(name) {
             Some(set) => set.layers.iter().map(|l| l).collect(),
-            None => Vec::new(),
+            None => {
+                warn!("Unknown tileset '{}'", name);
+                Vec::new()
+            }
         }
     }
     /// Service metadata for backend web application
@@ -104,19 +252,65 @@ impl MvtService {
         let mvt_info = MvtInfo { tilesets: tileset_infos };
         serde_json::to_value(mvt_info)
     }
+    /// Union of layer extents in SRS `srid`, detected on first use.
+    /// `None` if no layer extent could be detected, e.g. for empty tables.
+    fn data_extent(&self, tileset: &Tileset, srid: i32) -> Option<Extent> {
+        let key = (tileset.name.clone(), srid);
+        if let Some(extent) = self.data_extents.lock().unwrap().get(&key) {
+            return extent.clone();
+        }
+        let extent = tileset.layers
+            .iter()
+            .filter_map(|layer| {
+                            self.datasources
+                                .layer_datasource(layer)
+                                .and_then(|ds| ds.projected_layer_extent(layer, srid))
+                        })
+            .fold(None, |bounds: Option<Extent>, ext| match bounds {
+                Some(b) => {
+                    Some(Extent {
+                             minx: b.minx.min(ext.minx),
+                             miny: b.miny.min(ext.miny),
+                             maxx: b.maxx.max(ext.maxx),
+                             maxy: b.maxy.max(ext.maxy),
+                         })
+                }
+                None => Some(ext),
+            });
+        self.data_extents
+            .lock()
+            .unwrap()
+            .insert(key, extent.clone());
+        extent
+    }
+    /// Extent of tileset data in grid SRS
+    pub fn tileset_extent(&self, tileset: &str) -> Option<Extent> {
+        self.get_tileset(tileset)
+            .and_then(|ts| self.data_extent(ts, self.grid.srid()))
+    }
+    /// Tileset extent from configuration or union of layer extents (in WGS84)
+    fn get_tileset_bounds(&self, tileset: &Tileset) -> Extent {
+        if let Some(ref extent) = tileset.extent {
+            return extent.clone();
+        }
+        self.data_extent(tileset, 4326)
+            .unwrap_or(WORLD_EXTENT.clone())
+    }
     fn get_tilejson_metadata(&self, tileset: &str) -> JsonResult {
         let ts = self.get_tileset(tileset).unwrap();
-        let ext = ts.get_extent();
-        let center = ts.get_center();
+        let ext = self.get_tileset_bounds(ts);
+        let center = (ext.maxx - (ext.maxx - ext.minx) / 2.0,
+                      ext.maxy - (ext.maxy - ext.miny) / 2.0);
         let zoom = ts.get_start_zoom();
         Ok(json!({
+            "tilejson": "2.2.0",
             "id": tileset,
             "name": tileset,
             "description": tileset,
             "attribution": "",
             "format": "pbf",
             "version": "2.0.0",
-            "scheme": "xyz",
+            "scheme": if self.grid.tms { "tms" } else { "xyz" },
             "bounds": [ext.minx,
                        ext.miny,
                        ext.maxx,
@@ -147,7 +341,10 @@ impl MvtService {
                 "fields": {}
             });
                 //insert fields
-                let fields = self.input.detect_data_columns(&layer, query);
+                let fields = self.datasources
+                    .layer_datasource(&layer)
+                    .map(|ds| ds.detect_data_columns(&layer, query))
+                    .unwrap_or_default();
                 for (ref field, _) in fields {
                     meta_json["fields"]
                         .as_object_mut()
@@ -175,12 +372,27 @@ impl MvtService {
                 "fields": {}
             });
                 //insert fields
-                let fields = self.input.detect_data_columns(&layer, query);
-                for (ref field, _) in fields {
-                    layer_json["fields"]
-                        .as_object_mut()
-                        .unwrap()
-                        .insert(field.clone(), json!(""));
+                let fields = self.datasources
+                    .layer_datasource(&layer)
+                    .map(|ds| ds.detect_field_types(&layer, query))
+                    .unwrap_or_default();
+                {
+                    let layer_fields = layer_json["fields"].as_object_mut().unwrap();
+                    for (field, field_type) in fields {
+                        layer_fields.insert(field, json!(field_type));
+                    }
+                    // Configured fields not detected in datasource
+                    if let Some(ref configured) = layer.fields {
+                        for field in configured {
+                            if !layer_fields.contains_key(field) {
+                                layer_fields.insert(field.clone(), json!("String"));
+                            }
+                        }
+                    }
+                    // Type hints override detected types
+                    for (field, field_type) in &layer.field_types {
+                        layer_fields.insert(field.clone(), json!(field_type.tilejson_type()));
+                    }
                 }
                 layer_json
             })
@@ -199,6 +411,70 @@ impl MvtService {
         obj.insert("vector_layers".to_string(), vector_layers);
         Ok(json!(obj))
     }
+    /// Map viewer HTML page with all tilesets, centered on their bounds
+    pub fn viewer_html(&self, baseurl: &str) -> String {
+        let colors = ["#e41a1c", "#377eb8", "#4daf4a", "#984ea3", "#ff7f00", "#a65628"];
+        let mut sources = serde_json::Map::new();
+        let mut layers = Vec::new();
+        let mut bounds: Option<Extent> = None;
+        for (i, tileset) in self.tilesets.iter().enumerate() {
+            sources.insert(tileset.name.clone(),
+                           json!({
+                "type": "vector",
+                "url": format!("{}/{}.json", baseurl, tileset.name)
+            }));
+            let color = colors[i % colors.len()];
+            for layer in &tileset.layers {
+                let id = format!("{}.{}", tileset.name, layer.name);
+                layers.push(json!({
+                    "id": format!("{}.fill", id),
+                    "type": "fill",
+                    "source": tileset.name,
+                    "source-layer": layer.name,
+                    "filter": ["==", "$type", "Polygon"],
+                    "paint": {"fill-color": color, "fill-opacity": 0.3, "fill-outline-color": color}
+                }));
+                layers.push(json!({
+                    "id": format!("{}.line", id),
+                    "type": "line",
+                    "source": tileset.name,
+                    "source-layer": layer.name,
+                    "filter": ["==", "$type", "LineString"],
+                    "paint": {"line-color": color, "line-width": 1.5}
+                }));
+                layers.push(json!({
+                    "id": format!("{}.circle", id),
+                    "type": "circle",
+                    "source": tileset.name,
+                    "source-layer": layer.name,
+                    "filter": ["==", "$type", "Point"],
+                    "paint": {"circle-color": color, "circle-radius": 3}
+                }));
+            }
+            let ext = self.get_tileset_bounds(tileset);
+            bounds = Some(match bounds {
+                              Some(b) => {
+                                  Extent {
+                                      minx: b.minx.min(ext.minx),
+                                      miny: b.miny.min(ext.miny),
+                                      maxx: b.maxx.max(ext.maxx),
+                                      maxy: b.maxy.max(ext.maxy),
+                                  }
+                              }
+                              None => ext,
+                          });
+        }
+        let ext = bounds.unwrap_or(WORLD_EXTENT.clone());
+        let style = json!({
+            "version": 8,
+            "sources": sources,
+            "layers": layers
+        });
+        let bounds = json!([[ext.minx, ext.miny], [ext.maxx, ext.maxy]]);
+        VIEWER_TEMPLATE
+            .replace("{style}", &style.to_string())
+            .replace("{bounds}", &bounds.to_string())
+    }
     /// MapboxGL Style JSON (https://www.mapbox.com/mapbox-gl-style-spec/)
     pub fn get_stylejson(&self, baseurl: &str, tileset: &str) -> JsonResult {
         let mut stylejson = json!({
@@ -276,8 +552,19 @@ impl MvtService {
     /// MBTiles metadata.json
     pub fn get_mbtiles_metadata(&self, tileset: &str) -> JsonResult {
         let mut metadata = self.get_tilejson_metadata(tileset)?;
-        metadata["bounds"] = json!(metadata["bounds"].to_string());
-        metadata["center"] = json!(metadata["center"].to_string());
+        metadata.as_object_mut().unwrap().remove("tilejson");
+        // MBTiles files always use the TMS scheme
+        metadata.as_object_mut().unwrap().remove("scheme");
+        // Comma separated values without brackets
+        for key in &["bounds", "center"] {
+            let values: Vec<String> = metadata[*key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.to_string())
+                .collect();
+            metadata[*key] = json!(values.join(","));
+        }
         let layers = self.get_tilejson_layers(tileset)?;
         let vector_layers = self.get_tilejson_vector_layers(tileset)?;
         let metadata_vector_layers = json!({
@@ -291,69 +578,444 @@ impl MvtService {
     }
     /// Prepare datasource queries. Must be called before requesting tiles.
     pub fn prepare_feature_queries(&mut self) {
+        for tileset in &mut self.tilesets {
+            for layer in &mut tileset.layers {
+                if let Some(input) = self.datasources.layer_datasource_mut(layer) {
+                    input.detect_layer_geometry(layer);
+                    layer.set_default_buffer(&self.default_buffer);
+                    input.detect_fid_field(layer);
+                    input.prepare_queries(&layer,
+                                          self.grid.srid(),
+                                          self.extent,
+                                          self.grid.tile_size() as u32);
+                }
+            }
+        }
         for tileset in &self.tilesets {
-            for layer in &tileset.layers {
-                self.input.prepare_queries(&layer, self.grid.srid);
+            for batch in tileset.layer_batches(&self.datasources) {
+                if batch.len() > 1 {
+                    if let Some(input) = self.datasources.layer_datasource_mut(batch[0]) {
+                        input.prepare_batch_queries(&batch, self.grid.srid());
+                    }
+                }
+            }
+        }
+    }
+    /// Zoom level of the tileset content served for a request at `zoom`.
+    /// Returns `None` if `zoom` is outside the zoom range of the tileset,
+    /// or maxzoom for requests above maxzoom with `overzoom` enabled.
+    pub fn tileset_zoom(&self, tileset: &str, zoom: u8) -> Option<u8> {
+        let tileset = match self.get_tileset(tileset) {
+            Some(tileset) => tileset,
+            None => return Some(zoom),
+        };
+        match (tileset.minzoom, tileset.maxzoom) {
+            (Some(minzoom), _) if zoom < minzoom => None,
+            (_, Some(maxzoom)) if zoom > maxzoom => {
+                if tileset.overzoom { Some(maxzoom) } else { None }
             }
+            _ => Some(zoom),
         }
     }
-    /// Create vector tile from input at x, y, z in TMS adressing scheme
-    pub fn tile(&self, tileset: &str, xtile: u32, ytile: u32, zoom: u8) -> vector_tile::Tile {
+    /// Check zoom level and map scale range of layer
+    fn layer_visible(&self, layer: &Layer, zoom: u8) -> bool {
+        layer.is_visible(zoom) && layer.is_visible_at_scale(self.grid.scale_denominator(zoom))
+    }
+    /// Retrieve and encode features of a layer
+    fn layer_tile(&self,
+                  layer: &Layer,
+                  extent: &Extent,
+                  zoom: u8)
+                  -> Result<Vec<vector_tile::Tile_Layer>, TrexError> {
+        let input = match self.datasources.layer_datasource(layer) {
+            Some(input) => input,
+            None => return Ok(Vec::new()),
+        };
+        if input.native_mvt() {
+            // Layer encoded by PostGIS
+            if let Some(data) = input
+                   .retrieve_mvt_layer(&layer, extent, zoom, &self.grid)? {
+                return match Tile::read_from(&mut &data[..]) {
+                           Ok(mut mvt_tile) => Ok(mvt_tile.take_layers().into_vec()),
+                           Err(err) => {
+                               Err(TrexError::Encoding(format!("Layer '{}': {}",
+                                                                  layer.name,
+                                                                  err)))
+                           }
+                       };
+            }
+        }
+        // Each layer has its own encoder, since clipping and simplification are layer specific
+        let mut tile = Tile::new(extent, self.extent, true)
+            .with_grid_tile_size(self.grid.tile_size() as u32);
+        let mut mvt_layer = tile.new_layer(layer);
+        input.retrieve_features(&layer,
+                                extent,
+                                zoom,
+                                &self.grid,
+                                |feat| { tile.add_feature(&mut mvt_layer, feat); })?;
+        Ok(vec![mvt_layer])
+    }
+    /// Retrieve and encode features of layers reading the same table with one query
+    fn batch_tile(&self,
+                  layers: &[&Layer],
+                  extent: &Extent,
+                  zoom: u8)
+                  -> Result<Vec<vector_tile::Tile_Layer>, TrexError> {
+        if layers.len() == 1 {
+            return self.layer_tile(layers[0], extent, zoom);
+        }
+        let input = match self.datasources.layer_datasource(layers[0]) {
+            Some(input) => input,
+            None => return Ok(Vec::new()),
+        };
+        let mut tiles: Vec<Tile> = layers
+            .iter()
+            .map(|_| {
+                     Tile::new(extent, self.extent, true)
+                         .with_grid_tile_size(self.grid.tile_size() as u32)
+                 })
+            .collect();
+        let mut mvt_layers: Vec<vector_tile::Tile_Layer> = layers
+            .iter()
+            .zip(tiles.iter_mut())
+            .map(|(layer, tile)| tile.new_layer(layer))
+            .collect();
+        input
+            .retrieve_batch_features(layers, extent, zoom, &self.grid, |i, feat| {
+                tiles[i].add_feature(&mut mvt_layers[i], feat);
+            })?;
+        Ok(mvt_layers)
+    }
+    /// Create vector tile from input at x, y, z in TMS adressing scheme.
+    /// Layers are retrieved in parallel. Unknown tilesets return an empty tile.
+    pub fn tile(&self,
+                tileset: &str,
+                xtile: u32,
+                ytile: u32,
+                zoom: u8)
+                -> Result<vector_tile::Tile, TrexError> {
+        Self::empty_if_unknown(self.try_tile(tileset, xtile, ytile, zoom))
+    }
+    /// Create vector tile from input at x, y, z in TMS adressing scheme.
+    /// Layers are retrieved in parallel.
+    pub fn try_tile(&self,
+                    tileset: &str,
+                    xtile: u32,
+                    ytile: u32,
+                    zoom: u8)
+                    -> Result<vector_tile::Tile, TrexError> {
+        let (xtile, ytile) = self.normalize_tile(xtile, ytile, zoom)?;
+        self.build_tile(tileset, xtile, ytile, zoom, true)
+    }
+    /// Check tile coordinates against the tile matrix of the grid.
+    /// Columns beyond the antimeridian of global grids are wrapped around.
+    pub fn normalize_tile(&self,
+                          xtile: u32,
+                          ytile: u32,
+                          zoom: u8)
+                          -> Result<(u32, u32), TrexError> {
+        self.grid.normalize_tile(xtile, ytile, zoom)
+    }
+    /// Create vector tile retrieving one layer after the other
+    pub fn tile_sequential(&self,
+                           tileset: &str,
+                           xtile: u32,
+                           ytile: u32,
+                           zoom: u8)
+                           -> Result<vector_tile::Tile, TrexError> {
+        Self::empty_if_unknown(self.build_tile(tileset, xtile, ytile, zoom, false))
+    }
+    /// Result of `tile` returning an empty tile for unknown tilesets
+    fn empty_if_unknown(result: Result<vector_tile::Tile, TrexError>)
+                        -> Result<vector_tile::Tile, TrexError> {
+        match result {
+            Err(TrexError::UnknownTileset(name)) => {
+                warn!("Unknown tileset '{}'", name);
+                Ok(vector_tile::Tile::new())
+            }
+            result => result,
+        }
+    }
+    fn build_tile(&self,
+                  tileset: &str,
+                  xtile: u32,
+                  ytile: u32,
+                  zoom: u8,
+                  parallel: bool)
+                  -> Result<vector_tile::Tile, TrexError> {
         let extent = self.grid.tile_extent(xtile, ytile, zoom);
         debug!("MVT tile request {:?}", extent);
-        let mut tile = Tile::new(&extent, 4096, true);
+        let set = match self.get_tileset(tileset) {
+            Some(set) => set,
+            None => return Err(TrexError::UnknownTileset(tileset.to_string())),
+        };
+        // Overzoomed tiles are clipped from the layer content at maxzoom
+        let zoom = match self.tileset_zoom(tileset, zoom) {
+            Some(zoom) => zoom,
+            None => return Ok(vector_tile::Tile::new()),
+        };
+        let batches: Vec<Vec<&Layer>> = set.layer_batches(&self.datasources)
+            .into_iter()
+            .filter(|batch| self.layer_visible(batch[0], zoom))
+            .collect();
+        let mvt_layers: Vec<Result<Vec<vector_tile::Tile_Layer>, TrexError>> =
+            if parallel && batches.len() > 1 {
+                crossbeam::scope(|scope| {
+                    let extent = &extent;
+                    let workers: Vec<_> = batches
+                        .iter()
+                        .map(|batch| scope.spawn(move || self.batch_tile(batch, extent, zoom)))
+                        .collect();
+                    // Join in declaration order
+                    workers.into_iter().map(|worker| worker.join()).collect()
+                })
+            } else {
+                batches
+                    .iter()
+                    .map(|batch| self.batch_tile(batch, &extent, zoom))
+                    .collect()
+            };
+        let mut tile = Tile::new(&extent, self.extent, true);
+        for mvt_layer_result in mvt_layers {
+            for mvt_layer in mvt_layer_result? {
+                // Layers without features are dropped by default
+                if self.keep_empty_layers || !mvt_layer.get_features().is_empty() {
+                    tile.add_layer(mvt_layer);
+                }
+            }
+        }
+        Ok(tile.mvt_tile)
+    }
+    /// Features of tile at x, y, z as GeoJSON FeatureCollection for debugging.
+    /// Coordinates are in grid CRS and not quantized.
+    pub fn tile_geojson(&self,
+                        tileset: &str,
+                        xtile: u32,
+                        ytile: u32,
+                        zoom: u8)
+                        -> Result<String, TrexError> {
+        let (xtile, ytile) = self.normalize_tile(xtile, ytile, zoom)?;
+        let y = self.request_ytile(ytile, zoom);
+        let extent = self.grid.tile_extent(xtile, y, zoom);
+        let zoom = match self.tileset_zoom(tileset, zoom) {
+            Some(zoom) => zoom,
+            None => return Ok(json!({"type": "FeatureCollection", "features": []}).to_string()),
+        };
+        let mut features = Vec::new();
         for layer in self.get_tileset_layers(tileset) {
-            let mut mvt_layer = tile.new_layer(layer);
-            self.input
-                .retrieve_features(&layer,
-                                   &extent,
-                                   zoom,
-                                   &self.grid,
-                                   |feat| { tile.add_feature(&mut mvt_layer, feat); });
-            tile.add_layer(mvt_layer);
-        }
-        tile.mvt_tile
-    }
-    /// Fetch or create vector tile from input at x, y, z
+            if !self.layer_visible(layer, zoom) {
+                continue;
+            }
+            let input = match self.datasources.layer_datasource(layer) {
+                Some(input) => input,
+                None => continue,
+            };
+            input.retrieve_features(&layer, &extent, zoom, &self.grid, |feat| {
+                    let geometry = match feat.geometry() {
+                        Ok(geom) => geom.to_geojson(),
+                        Err(err) => {
+                            warn!("Layer '{}': {}", layer.name, err);
+                            return;
+                        }
+                    };
+                    let mut properties = serde_json::Map::new();
+                    for attr in feat.attributes() {
+                        properties.insert(attr.key, attr.value.to_json());
+                    }
+                    let mut feature = json!({
+                        "type": "Feature",
+                        "layer": layer.name,
+                        "geometry": geometry,
+                        "properties": properties
+                    });
+                    if let Some(fid) = feat.fid() {
+                        feature["id"] = json!(fid);
+                    }
+                    features.push(feature);
+                })?;
+        }
+        let collection = json!({
+            "type": "FeatureCollection",
+            "features": features
+        });
+        Ok(collection.to_string())
+    }
+    /// Tile row in grid scheme of a tile request
+    fn request_ytile(&self, ytile: u32, zoom: u8) -> u32 {
+        if self.grid.tms {
+            self.grid.ytile_from_tms(ytile, zoom)
+        } else {
+            self.grid.ytile_from_xyz(ytile, zoom)
+        }
+    }
+    /// Encoded tile for storing in cache. Empty tiles are stored as empty object.
+    fn tile_data(&self, mvt_tile: &vector_tile::Tile) -> Vec<u8> {
+        if Tile::is_empty(&mvt_tile) {
+            Vec::new()
+        } else if self.gzip {
+            let mut tilegz = Vec::new();
+            Tile::write_gz_to(&mut tilegz, &mvt_tile);
+            tilegz
+        } else {
+            Tile::binary_tile(&mvt_tile)
+        }
+    }
+    /// Cache path of requested tile. Cache paths are always in XYZ adressing scheme.
+    fn cache_path(&self, tileset: &str, xtile: u32, ytile: u32, zoom: u8) -> String {
+        let xyz_y = if self.grid.tms {
+            self.grid.flip_y(ytile, zoom)
+        } else {
+            ytile
+        };
+        format!("{}/{}/{}/{}.pbf", tileset, zoom, xtile, xyz_y)
+    }
+    /// Modification time of cached tile, if available
+    pub fn tile_modified(&self,
+                         tileset: &str,
+                         xtile: u32,
+                         ytile: u32,
+                         zoom: u8)
+                         -> Option<SystemTime> {
+        self.cache.modified(&self.cache_path(tileset, xtile, ytile, zoom))
+    }
+    /// Modification time and size of cached tile file
+    pub fn tile_file_stat(&self,
+                          tileset: &str,
+                          xtile: u32,
+                          ytile: u32,
+                          zoom: u8)
+                          -> Option<(SystemTime, u64)> {
+        let path = self.cache_path(tileset, xtile, ytile, zoom);
+        match (self.cache.modified(&path), self.cache.file_size(&path)) {
+            (Some(mtime), Some(size)) => Some((mtime, size)),
+            _ => None,
+        }
+    }
+    /// Fetch or create vector tile from input at x, y, z.
+    /// Returns gzip compressed tile if `gzip` is set and None for tiles without features.
+    /// Each request is logged with cache status, feature count, duration and size.
     pub fn tile_cached(&self,
                        tileset: &str,
                        xtile: u32,
                        ytile: u32,
                        zoom: u8,
-                       _gzip: bool)
-                       -> Vec<u8> {
-        // Reverse y for XYZ scheme (TODO: protocol instead of CRS dependent?)
-        let y = if self.grid.srid == 3857 {
-            self.grid.ytile_from_xyz(ytile, zoom)
+                       gzip: bool)
+                       -> Result<Option<Vec<u8>>, TrexError> {
+        let (xtile, ytile) = self.normalize_tile(xtile, ytile, zoom)?;
+        let start = if log_enabled!(LogLevel::Info) {
+            Some(Instant::now())
         } else {
-            ytile
+            None
         };
-        let path = format!("{}/{}/{}/{}.pbf", tileset, zoom, xtile, ytile);
+        if self.tileset_zoom(tileset, zoom).is_none() {
+            return Ok(None);
+        }
+        let y = self.request_ytile(ytile, zoom);
+        let path = self.cache_path(tileset, xtile, ytile, zoom);
 
-        let mut tile: Option<Vec<u8>> = None;
+        let mut tile: Option<Result<Vec<u8>, String>> = None;
         self.cache
             .read(&path, |mut f| {
                 let mut data = Vec::new();
-                let _ = f.read_to_end(&mut data);
-                tile = Some(data);
+                tile = Some(match f.read_to_end(&mut data) {
+                                Ok(_) => {
+                                    Self::check_tile_data(&data)
+                                        .map(|_| data)
+                                        .map_err(|err| err.to_string())
+                                }
+                                Err(err) => Err(err.to_string()),
+                            });
             });
-        if tile.is_some() {
-            //TODO: unzip if gzip == false
-            return tile.unwrap();
-        }
-
-        let mvt_tile = self.tile(tileset, xtile, y, zoom);
-
-        let mut tilegz = Vec::new();
-        Tile::write_gz_to(&mut tilegz, &mvt_tile);
-        let _ = self.cache.write(&path, &tilegz);
+        let tile = match tile {
+            Some(Ok(data)) => Some(data),
+            Some(Err(err)) => {
+                // Unreadable cache entries are replaced with a new tile
+                warn!("Invalid cache entry {}: {} - regenerating tile", path, err);
+                if let Err(err) = self.cache.remove(&path) {
+                    warn!("Error removing cache entry {}: {}", path, err);
+                }
+                None
+            }
+            None => None,
+        };
+        let cache_hit = tile.is_some();
+        self.metrics.tile_request(tileset, cache_hit);
+        let mut feature_count = None;
+        let data = match tile {
+            Some(data) => data,
+            None => {
+                let render_start = Instant::now();
+                let mvt_tile = self.tile(tileset, xtile, y, zoom)?;
+                self.metrics.render_duration(duration_secs(render_start.elapsed()));
+                if start.is_some() {
+                    feature_count = Some(mvt_tile
+                                             .get_layers()
+                                             .iter()
+                                             .map(|layer| layer.get_features().len())
+                                             .sum::<usize>());
+                }
+                let data = self.tile_data(&mvt_tile);
+                let _ = self.cache.write(&path, &data);
+                data
+            }
+        };
 
-        //TODO: return unzipped if gzip == false
-        tilegz
+        // Cached tiles may be stored with or without compression
+        let tile = match (gzip, Tile::is_gzipped(&data)) {
+            _ if data.is_empty() => None,
+            (true, false) => Some(Tile::gzip(&data)),
+            (false, true) => {
+                match Tile::gunzip(&data) {
+                    Ok(tile) => Some(tile),
+                    Err(e) => {
+                        error!("Error decompressing tile {}: {}", path, e);
+                        None
+                    }
+                }
+            }
+            _ => Some(data),
+        };
+        if let Some(start) = start {
+            info!("tile tileset={} z={} x={} y={} cache_hit={} features={} time_ms={:.1} size={}",
+                  tileset,
+                  zoom,
+                  xtile,
+                  ytile,
+                  cache_hit,
+                  feature_count.map(|cnt| cnt.to_string()).unwrap_or("-".to_string()),
+                  duration_secs(start.elapsed()) * 1000.0,
+                  tile.as_ref().map(|data| data.len()).unwrap_or(0));
+        }
+        Ok(tile)
+    }
+    /// Check that cached tile data is a valid (compressed) tile or empty
+    pub fn check_tile_data(data: &[u8]) -> Result<(), TrexError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let decompressed;
+        let tile_data = if Tile::is_gzipped(data) {
+            decompressed = Tile::gunzip(data)
+                .map_err(|err| TrexError::Encoding(err.to_string()))?;
+            &decompressed[..]
+        } else {
+            data
+        };
+        Tile::read_from(&mut &tile_data[..])
+            .map(|_| ())
+            .map_err(|err| TrexError::Encoding(err.to_string()))
     }
-    fn progress_bar(&self, msg: &str, limits: &ExtentInt) -> ProgressBar<Stdout> {
-        let tiles = (limits.maxx as u64 - limits.minx as u64) *
-                    (limits.maxy as u64 - limits.miny as u64);
+    /// Request metrics and datasource connections in use in Prometheus text format
+    pub fn metrics_text(&self) -> String {
+        let pool_connections: Vec<(String, u32)> = self.datasources
+            .datasources
+            .iter()
+            .filter_map(|(name, ds)| ds.connections_in_use().map(|cnt| (name.clone(), cnt)))
+            .collect();
+        self.metrics.prometheus_text(&pool_connections)
+    }
+    fn progress_bar(&self, msg: &str, tiles: u64) -> ProgressBar<Stdout> {
         let mut pb = ProgressBar::new(tiles);
         pb.message(msg);
         //pb.set_max_refresh_rate(Some(Duration::from_millis(200)));
@@ -366,9 +1028,10 @@ impl MvtService {
     pub fn extent_from_wgs84(&self, extent: &Extent) -> Extent {
         // TODO: use proj4 (directly)
         // and maybe fast track for Web Mercator (see fn xy in grid_test)
-        self.input
-            .extent_from_wgs84(extent, self.grid.srid)
-            .expect(&format!("Error transforming {:?} to SRID {}", extent, self.grid.srid))
+        self.datasources
+            .default_datasource()
+            .and_then(|ds| ds.extent_from_wgs84(extent, self.grid.srid()))
+            .expect(&format!("Error transforming {:?} to SRID {}", extent, self.grid.srid()))
     }
     /// Populate tile cache
     pub fn generate(&self,
@@ -378,14 +1041,32 @@ impl MvtService {
                     extent: Option<Extent>,
                     nodes: Option<u8>,
                     nodeno: Option<u8>,
+                    threads: Option<u8>,
                     progress: bool,
-                    overwrite: bool) {
-        self.init_cache();
-        let minzoom = minzoom.unwrap_or(0);
-        let maxzoom = maxzoom.unwrap_or(self.grid.maxzoom());
+                    overwrite: bool,
+                    dry_run: bool)
+                    -> GenerateStats {
+        let start = Instant::now();
+        let written = AtomicUsize::new(0);
+        let bytes = AtomicUsize::new(0);
+        if !dry_run {
+            self.init_cache();
+        }
         let nodes = nodes.unwrap_or(1) as u64;
         let nodeno = nodeno.unwrap_or(0) as u64;
+        // Use one worker per pooled DB connection by default
+        let threads = threads
+            .map(|t| t as u64)
+            .unwrap_or(self.datasources.pool_size() as u64)
+            .max(1);
+        // Number of tiles n < tileno with n % nodes == nodeno
+        let node_count = |tileno: u64| if tileno > nodeno {
+            (tileno - nodeno - 1) / nodes + 1
+        } else {
+            0
+        };
         let mut tileno: u64 = 0;
+        let mut total: u64 = 0;
         for tileset in &self.tilesets {
             if tileset_name.is_some() && tileset_name.unwrap() != &tileset.name {
                 continue;
@@ -402,50 +1083,129 @@ impl MvtService {
             };
             debug!("tile limits: {:?}", ext_proj);
 
-            let tolerance = 0;
-            let limits = self.grid.tile_limits(ext_proj, tolerance);
+            let tileset_maxzoom = cmp::min(tileset.maxzoom(), self.grid.maxzoom());
+            if maxzoom.unwrap_or(0) > tileset_maxzoom {
+                warn!("Zoom levels above maximal zoom level of tileset '{}' ({}) are skipped",
+                      tileset.name,
+                      tileset_maxzoom);
+            }
+            let minzoom = cmp::max(minzoom.unwrap_or(0), tileset.minzoom());
+            let maxzoom = cmp::min(maxzoom.unwrap_or(tileset_maxzoom), tileset_maxzoom);
+
             for zoom in minzoom..maxzoom + 1 {
-                if zoom > self.grid.maxzoom() {
-                    warn!("Zoom level exceeds maximal zoom level of grid ({}) - skipping", self.grid.maxzoom());
+                // Tile range in XYZ adressing scheme
+                let (minx, miny, maxx, maxy) = self.grid.extent_to_tile_range(&ext_proj, zoom);
+                debug!("level {}: {:?}", zoom, (minx, miny, maxx, maxy));
+                let rows = (maxy - miny + 1) as u64;
+                let level_tiles = (maxx - minx + 1) as u64 * rows;
+                // Tile numbers of this level assigned to this node
+                let level_start = tileno;
+                tileno += level_tiles;
+                let node_tiles = node_count(tileno) - node_count(level_start);
+                // Index of the first tile of this level assigned to this node
+                let node_start = (nodeno + nodes - level_start % nodes) % nodes;
+                total += node_tiles;
+                if dry_run {
+                    if progress {
+                        println!("Level {}: {} tiles", zoom, node_tiles);
+                    }
                     continue;
                 }
-                let ref limit = limits[zoom as usize];
-                debug!("level {}: {:?}", zoom, limit);
-                let mut pb = self.progress_bar(&format!("Level {}: ", zoom), &limit);
+
+                let pb = Mutex::new(self.progress_bar(&format!("Level {}: ", zoom), node_tiles));
                 if progress {
-                    pb.tick();
+                    pb.lock().unwrap().tick();
                 }
-                for xtile in limit.minx..limit.maxx + 1 {
-                    for ytile in limit.miny..limit.maxy + 1 {
-                        let skip = tileno % nodes != nodeno;
-                        tileno += 1;
-                        if skip {
-                            continue;
+                // Workers take the next tile of this node from a shared counter
+                let next_tile = AtomicUsize::new(0);
+                crossbeam::scope(|scope| for _ in 0..threads {
+                    let (pb, next_tile) = (&pb, &next_tile);
+                    let (written, bytes) = (&written, &bytes);
+                    scope.spawn(move || loop {
+                        let k = next_tile.fetch_add(1, Ordering::Relaxed) as u64;
+                        if k >= node_tiles {
+                            break;
                         }
+                        let i = node_start + k * nodes;
+                        let xtile = minx + (i / rows) as u32;
+                        let ytile = miny + (i % rows) as u32;
 
                         // store in xyz schema. TODO: make configurable
-                        let y = self.grid.ytile_from_xyz(ytile, zoom);
-                        let path = format!("{}/{}/{}/{}.pbf", &tileset.name, zoom, xtile, y);
+                        let path = format!("{}/{}/{}/{}.pbf", &tileset.name, zoom, xtile, ytile);
 
                         if overwrite || !self.cache.exists(&path) {
                             // Entry doesn't exist, or we're ignoring it, so generate it
-                            let mvt_tile =
-                                self.tile(&tileset.name, xtile as u32, ytile as u32, zoom);
-                            let mut tilegz = Vec::new();
-                            Tile::write_gz_to(&mut tilegz, &mvt_tile);
-                            let _ = self.cache.write(&path, &tilegz);
+                            let y = self.grid.ytile_from_xyz(ytile, zoom);
+                            // Workers already use all pooled connections
+                            match self.tile_sequential(&tileset.name, xtile, y, zoom) {
+                                Ok(mvt_tile) => {
+                                    let data = self.tile_data(&mvt_tile);
+                                    match self.cache.write(&path, &data) {
+                                        Ok(_) => {
+                                            written.fetch_add(1, Ordering::Relaxed);
+                                            bytes.fetch_add(data.len(), Ordering::Relaxed);
+                                        }
+                                        Err(err) => error!("Tile {}: {}", path, err),
+                                    }
+                                }
+                                Err(err) => error!("Tile {}: {}", path, err),
+                            }
                         }
 
                         if progress {
-                            pb.inc();
+                            pb.lock().unwrap().inc();
                         }
-                    }
-                }
+                    });
+                });
             }
         }
         if progress {
             println!("");
         }
+        let stats = GenerateStats {
+            tiles: written.load(Ordering::Relaxed) as u64,
+            bytes: bytes.load(Ordering::Relaxed) as u64,
+            elapsed: start.elapsed(),
+        };
+        if dry_run {
+            println!("Total: {} tiles", total);
+        } else if progress {
+            println!("{} tiles written ({} bytes) in {:.1}s",
+                     stats.tiles,
+                     stats.bytes,
+                     duration_secs(stats.elapsed));
+        }
+        stats
+    }
+    /// Remove tiles from cache
+    pub fn purge(&self,
+                 tileset_name: Option<&str>,
+                 minzoom: Option<u8>,
+                 maxzoom: Option<u8>,
+                 extent: Option<Extent>)
+                 -> Result<(), io::Error> {
+        // Convert extent to grid SRS
+        let ext_proj = match extent {
+            Some(ref ext_wgs84) if *ext_wgs84 != WORLD_EXTENT => self.extent_from_wgs84(&ext_wgs84),
+            _ => self.grid.extent.clone(),
+        };
+        for tileset in &self.tilesets {
+            if tileset_name.is_some() && tileset_name.unwrap() != &tileset.name {
+                continue;
+            }
+            // Overzoomed tiles are cached up to the grid maxzoom
+            let tileset_maxzoom = if tileset.overzoom {
+                self.grid.maxzoom()
+            } else {
+                cmp::min(tileset.maxzoom(), self.grid.maxzoom())
+            };
+            let minzoom = cmp::max(minzoom.unwrap_or(0), tileset.minzoom());
+            let maxzoom = cmp::min(maxzoom.unwrap_or(tileset_maxzoom), tileset_maxzoom);
+            info!("Removing tiles of tileset '{}'", tileset.name);
+            self.cache
+                .purge(&tileset.name, &self.grid, &ext_proj, minzoom, maxzoom)?;
+        }
+        Ok(())
     }
     pub fn init_cache(&self) {
         info!("{}", &self.cache.info());
@@ -482,10 +1242,10 @@ static WORLD_EXTENT: Extent = Extent {
 
 impl Tileset {
     pub fn minzoom(&self) -> u8 {
-        0 // TODO: from layers or config?
+        self.minzoom.unwrap_or(0)
     }
     pub fn maxzoom(&self) -> u8 {
-        22 // TODO: from layers or config?
+        self.maxzoom.unwrap_or(22)
     }
     pub fn get_extent(&self) -> &Extent {
         self.extent.as_ref().unwrap_or(&WORLD_EXTENT)
@@ -497,10 +1257,35 @@ impl Tileset {
     pub fn get_start_zoom(&self) -> u8 {
         2 // TODO: from config
     }
-    pub fn gen_runtime_config_from_input(&self, input: &PostgisInput) -> String {
+    /// Layers grouped by batch key, in order of their first layer.
+    /// Without `batch_layers`, each layer is retrieved on its own.
+    pub fn layer_batches(&self, datasources: &Datasources) -> Vec<Vec<&Layer>> {
+        let mut batches: Vec<(Option<String>, Vec<&Layer>)> = Vec::new();
+        for layer in &self.layers {
+            let batch_queries = datasources
+                .layer_datasource(layer)
+                .map_or(false, |input| input.batch_queries());
+            let key = if self.batch_layers && batch_queries {
+                layer.batch_key()
+            } else {
+                None
+            };
+            if key.is_some() {
+                if let Some(batch) = batches.iter_mut().find(|batch| batch.0 == key) {
+                    batch.1.push(layer);
+                    continue;
+                }
+            }
+            batches.push((key, vec![layer]));
+        }
+        batches.into_iter().map(|(_, layers)| layers).collect()
+    }
+    pub fn gen_runtime_config_from_input(&self, datasources: &Datasources) -> String {
         let mut config = String::new();
         for layer in &self.layers {
-            config.push_str(&layer.gen_runtime_config_from_input(input));
+            if let Some(input) = datasources.layer_datasource(layer) {
+                config.push_str(&layer.gen_runtime_config_from_input(input));
+            }
         }
         config
     }
@@ -511,11 +1296,25 @@ impl<'a> Config<'a, Tileset, TilesetCfg> for Tileset {
         let layers = tileset_cfg
             .layers
             .iter()
-            .map(|layer| Layer::from_config(layer).unwrap())
-            .collect();
+            .map(|layer| Layer::from_config(layer))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Layer names identify MVT layers and may differ from table names,
+        // but have to be unique within a tileset
+        let mut names = HashSet::new();
+        for layer in &layers {
+            if !names.insert(&layer.name) {
+                return Err(format!("Tileset '{}': duplicate layer name '{}'",
+                                   tileset_cfg.name,
+                                   layer.name));
+            }
+        }
         Ok(Tileset {
                name: tileset_cfg.name.clone(),
                extent: tileset_cfg.extent.clone(),
+               minzoom: tileset_cfg.minzoom,
+               maxzoom: tileset_cfg.maxzoom,
+               overzoom: tileset_cfg.overzoom.unwrap_or(false),
+               batch_layers: tileset_cfg.batch_layers.unwrap_or(false),
                layers: layers,
            })
     }
@@ -535,25 +1334,39 @@ impl<'a> Config<'a, Tileset, TilesetCfg> for Tileset {
 
 impl<'a> Config<'a, MvtService, ApplicationCfg> for MvtService {
     fn from_config(config: &ApplicationCfg) -> Result<Self, String> {
-        let pg = PostgisInput::from_config(&config.datasource)?;
+        let datasources = Datasources::from_config(&config.datasources)?;
         let grid = Grid::from_config(&config.grid)?;
-        let tilesets = config
+        let max_features = config.service.mvt.max_features.unwrap_or(DEFAULT_MAX_FEATURES);
+        let default_buffer = config.service.mvt.buffer.clone().unwrap_or_default();
+        let mut tilesets = config
             .tilesets
             .iter()
-            .map(|ts_cfg| Tileset::from_config(ts_cfg).unwrap())
-            .collect(); //FIXME: avoid unwrap
+            .map(|ts_cfg| Tileset::from_config(ts_cfg))
+            .collect::<Result<Vec<_>, _>>()?;
+        for tileset in &mut tilesets {
+            for layer in &mut tileset.layers {
+                layer.max_features = Some(max_features);
+                layer.set_default_buffer(&default_buffer);
+            }
+        }
         let cache = Tilecache::from_config(&config)?;
         Ok(MvtService {
-               input: pg,
+               datasources: datasources,
                grid: grid,
+               extent: config.service.mvt.extent.unwrap_or(4096),
                tilesets: tilesets,
                cache: cache,
+               gzip: config.cache.as_ref().and_then(|c| c.gzip).unwrap_or(true),
+               keep_empty_layers: config.service.mvt.keep_empty_layers.unwrap_or(false),
+               default_buffer: default_buffer,
+               metrics: Metrics::new(),
+               data_extents: Mutex::new(HashMap::new()),
            })
     }
     fn gen_config() -> String {
         let mut config = String::new();
         config.push_str(TOML_SERVICES);
-        config.push_str(&Datasource::gen_config());
+        config.push_str(&Datasources::gen_config());
         config.push_str(&Grid::gen_config());
         config.push_str(&Tileset::gen_config());
         config.push_str(&Tilecache::gen_config());
@@ -562,19 +1375,80 @@ impl<'a> Config<'a, MvtService, ApplicationCfg> for MvtService {
     fn gen_runtime_config(&self) -> String {
         let mut config = String::new();
         config.push_str(TOML_SERVICES);
-        config.push_str(&self.input.gen_runtime_config());
+        config.push_str(&self.datasources.gen_runtime_config());
         config.push_str(&self.grid.gen_runtime_config());
         for tileset in &self.tilesets {
-            config.push_str(&tileset.gen_runtime_config_from_input(&self.input));
+            config.push_str(&tileset.gen_runtime_config_from_input(&self.datasources));
         }
         config.push_str(&self.cache.gen_runtime_config());
         config
     }
 }
 
+/// Generate configuration with a layer for each spatial table in the database,
+/// including detected geometry type, SRID and fid_field
+pub fn gen_config_from_db(datasource_url: &str) -> String {
+    let pg = PostgisInput::new(datasource_url).connected();
+    let mut layers = pg.detect_layers(true);
+    layers.reverse();
+    for layer in &mut layers {
+        pg.detect_fid_field(layer);
+    }
+    let mut datasources = Datasources::new();
+    datasources.add("default", Datasource::Postgis(pg));
+
+    let mut config = String::new();
+    config.push_str(TOML_SERVICES);
+    config.push_str(&datasources.gen_runtime_config());
+    config.push_str(&Grid::gen_config());
+    if let Some(input) = datasources.default_datasource() {
+        for layer in &layers {
+            config.push_str(&layer.gen_runtime_config_from_input(input));
+        }
+    }
+    config.push_str(&Tilecache::gen_config());
+    config
+}
+
+
+const VIEWER_TEMPLATE: &'static str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>t-rex viewer</title>
+  <meta name="viewport" content="initial-scale=1,maximum-scale=1,user-scalable=no">
+  <script src="https://api.tiles.mapbox.com/mapbox-gl-js/v0.44.1/mapbox-gl.js"></script>
+  <link href="https://api.tiles.mapbox.com/mapbox-gl-js/v0.44.1/mapbox-gl.css" rel="stylesheet">
+  <style>
+    body { margin: 0; padding: 0; }
+    #map { position: absolute; top: 0; bottom: 0; width: 100%; }
+  </style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+var map = new mapboxgl.Map({
+  container: 'map',
+  style: {style},
+  bounds: {bounds},
+  hash: true
+});
+map.addControl(new mapboxgl.NavigationControl());
+</script>
+</body>
+</html>
+"#;
 
 const TOML_SERVICES: &'static str = r#"# t-rex configuration
 
 [service.mvt]
 viewer = true
+# MVT coordinate extent (default 4096)
+#extent = 4096
+# Include layers without features in tiles (default false)
+#keep_empty_layers = false
+# Upper feature count limit of all layer queries (default 100000)
+#max_features = 100000
+# Default buffer_size of layers by geometry type
+#buffer = { line = 64, polygon = 64, point = 0 }
 "#;
@@ -14,10 +14,13 @@ extern crate rustc_serialize;
 extern crate hyper;
 extern crate clap;
 extern crate open;
+extern crate ctrlc;
+extern crate num_cpus;
 
 extern crate t_rex_core;
 
 pub mod server;
+pub mod shutdown;
 
 use t_rex_core::core;
 use t_rex_core::datasource;
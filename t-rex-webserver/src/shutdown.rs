@@ -0,0 +1,121 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+//! Graceful shutdown waiting for requests in progress
+
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+
+struct State {
+    shutting_down: bool,
+    /// Number of requests in progress
+    in_flight: usize,
+}
+
+/// Shutdown state shared by request handlers and the signal handler
+pub struct Shutdown {
+    state: Mutex<State>,
+    drained: Condvar,
+}
+
+/// Request in progress, finished when dropped
+pub struct RequestGuard<'a> {
+    shutdown: &'a Shutdown,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown {
+            state: Mutex::new(State {
+                                  shutting_down: false,
+                                  in_flight: 0,
+                              }),
+            drained: Condvar::new(),
+        }
+    }
+    /// Register request in progress. Returns `None` if the server is shutting down.
+    pub fn start_request(&self) -> Option<RequestGuard> {
+        let mut state = self.state.lock().unwrap();
+        if state.shutting_down {
+            return None;
+        }
+        state.in_flight += 1;
+        Some(RequestGuard { shutdown: self })
+    }
+    pub fn is_shutting_down(&self) -> bool {
+        self.state.lock().unwrap().shutting_down
+    }
+    /// Reject new requests and wait until all requests in progress are finished.
+    /// Returns `false` if requests are still running after `timeout`.
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        let start = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.shutting_down = true;
+        while state.in_flight > 0 {
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return false;
+            }
+            state = self.drained.wait_timeout(state, timeout - elapsed).unwrap().0;
+        }
+        true
+    }
+}
+
+impl<'a> Drop for RequestGuard<'a> {
+    fn drop(&mut self) {
+        let mut state = self.shutdown.state.lock().unwrap();
+        state.in_flight -= 1;
+        if state.in_flight == 0 {
+            self.shutdown.drained.notify_all();
+        }
+    }
+}
+
+
+#[test]
+fn test_shutdown_without_requests() {
+    let shutdown = Shutdown::new();
+    assert!(!shutdown.is_shutting_down());
+    assert!(shutdown.shutdown(Duration::from_millis(10)));
+    assert!(shutdown.is_shutting_down());
+    assert!(shutdown.start_request().is_none());
+}
+
+#[test]
+fn test_request_completes_before_shutdown() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    let shutdown = Arc::new(Shutdown::new());
+    let finished = Arc::new(AtomicBool::new(false));
+
+    // Request issued just before shutdown
+    let (request_shutdown, request_finished) = (shutdown.clone(), finished.clone());
+    let (started_tx, started_rx) = ::std::sync::mpsc::channel();
+    let request = thread::spawn(move || {
+                                    let _request = request_shutdown.start_request().unwrap();
+                                    started_tx.send(()).unwrap();
+                                    thread::sleep(Duration::from_millis(200));
+                                    request_finished.store(true, Ordering::SeqCst);
+                                });
+    started_rx.recv().unwrap();
+
+    assert!(shutdown.shutdown(Duration::from_secs(10)));
+    // Shutdown waited for the request
+    assert!(finished.load(Ordering::SeqCst));
+    assert!(shutdown.start_request().is_none());
+    request.join().unwrap();
+}
+
+#[test]
+fn test_shutdown_timeout() {
+    let shutdown = Shutdown::new();
+    let _request = shutdown.start_request().unwrap();
+    assert!(!shutdown.shutdown(Duration::from_millis(50)));
+    assert!(shutdown.is_shutting_down());
+}
@@ -0,0 +1,243 @@
+//
+// Copyright (c) Pirmin Kalberer. All rights reserved.
+// Licensed under the MIT License. See LICENSE file in the project root for full license information.
+//
+
+//! Integration tests launching the `t_rex serve` binary
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// Server process, killed when dropped
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn t_rex_bin() -> PathBuf {
+    // Test executable is in target/<profile>/deps
+    let mut path = env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.join(format!("t_rex{}", env::consts::EXE_SUFFIX))
+}
+
+fn write_config(name: &str, toml: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("t_rex_test_{}.toml", name));
+    let mut file = File::create(&path).unwrap();
+    file.write_all(toml.as_bytes()).unwrap();
+    path
+}
+
+fn geojson_config() -> String {
+    let geojson = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("t-rex-core/src/test/places.geojson");
+    format!(r#"
+[service.mvt]
+viewer = false
+
+[datasource]
+type = "geojson"
+path = "{}"
+
+[grid]
+predefined = "web_mercator"
+
+[[tileset]]
+name = "places"
+
+[[tileset.layer]]
+name = "places"
+geometry_type = "POINT"
+
+[webserver]
+bind = "127.0.0.1"
+port = 6767
+"#,
+            geojson.to_str().unwrap().replace('\\', "/"))
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn http_get(addr: &str, path: &str) -> Option<(String, Vec<u8>)> {
+    http_request(addr, path, "")
+}
+
+/// HTTP GET request with additional header lines.
+/// Returns the status line with headers and the (possibly binary) body.
+fn http_request(addr: &str, path: &str, headers: &str) -> Option<(String, Vec<u8>)> {
+    let mut stream = match TcpStream::connect(addr) {
+        Ok(stream) => stream,
+        Err(_) => return None,
+    };
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n{}Connection: close\r\n\r\n",
+                          path,
+                          addr,
+                          headers);
+    if stream.write_all(request.as_bytes()).is_err() {
+        return None;
+    }
+    let mut response = Vec::new();
+    if stream.read_to_end(&mut response).is_err() {
+        return None;
+    }
+    let split = match response.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => pos,
+        None => return None,
+    };
+    let body = response.split_off(split + 4);
+    Some((String::from_utf8_lossy(&response[..split]).to_string(), body))
+}
+
+/// Start `t_rex serve` with two worker threads on a free port and wait until it is listening.
+/// Returns the server process and its address.
+fn start_server(name: &str, toml: &str) -> (Server, String) {
+    let config = write_config(name, toml);
+    let addr = format!("127.0.0.1:{}", free_port());
+    let server = Server(Command::new(t_rex_bin())
+                            .args(&["serve", "--config", config.to_str().unwrap()])
+                            .args(&["--bind", &addr, "--threads", "2"])
+                            .args(&["--openbrowser", "false"])
+                            .stdout(Stdio::null())
+                            .spawn()
+                            .expect("Failed to start t_rex"));
+    let start = Instant::now();
+    while http_get(&addr, "/health").is_none() {
+        assert!(start.elapsed() < Duration::from_secs(30),
+                "Server not listening on {}",
+                addr);
+        thread::sleep(Duration::from_millis(100));
+    }
+    (server, addr)
+}
+
+#[test]
+fn test_serve_health() {
+    let (_server, addr) = start_server("serve", &geojson_config());
+    let (head, body) = http_get(&addr, "/health").expect("No response");
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(body.ends_with(b"OK"), "{}", String::from_utf8_lossy(&body));
+}
+
+#[test]
+fn test_serve_invalid_config() {
+    let toml = geojson_config().replace("predefined = \"web_mercator\"",
+                                        "predefined = \"unknown\"");
+    let config = write_config("invalid", &toml);
+    let output = Command::new(t_rex_bin())
+        .args(&["serve", "--config", config.to_str().unwrap()])
+        .args(&["--bind", &format!("127.0.0.1:{}", free_port())])
+        .output()
+        .expect("Failed to start t_rex");
+    assert!(!output.status.success());
+    assert!(!output.stdout.is_empty());
+}
+
+#[test]
+fn test_serve_invalid_bind() {
+    let config = write_config("bind", &geojson_config());
+    let output = Command::new(t_rex_bin())
+        .args(&["serve", "--config", config.to_str().unwrap()])
+        .args(&["--bind", "127.0.0.1:notaport"])
+        .output()
+        .expect("Failed to start t_rex");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout)
+                .contains("Invalid port in bind address '127.0.0.1:notaport'"));
+}
+
+/// Open a keep-alive connection and wait for the response of a first request.
+/// The worker thread serving it is then blocked until the connection is closed.
+fn keep_alive_connection(addr: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let request = format!("GET /health HTTP/1.1\r\nHost: {}\r\n\r\n", addr);
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = Vec::new();
+    let mut buf = [0; 1024];
+    while !response.ends_with(b"OK") {
+        let n = stream.read(&mut buf).expect("No keep-alive response");
+        assert!(n > 0, "Connection closed by server");
+        response.extend_from_slice(&buf[..n]);
+    }
+    stream
+}
+
+#[test]
+fn test_serve_threads() {
+    let (_server, addr) = start_server("threads", &geojson_config());
+
+    // Both worker threads are occupied by a keep-alive connection
+    let busy: Vec<TcpStream> = (0..2).map(|_| keep_alive_connection(&addr)).collect();
+    let (tx, rx) = mpsc::channel();
+    let probe_addr = addr.clone();
+    thread::spawn(move || { let _ = tx.send(http_get(&probe_addr, "/health")); });
+    // No worker available for the probe request
+    assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+    drop(busy);
+    let (head, _) = rx.recv_timeout(Duration::from_secs(10))
+        .unwrap()
+        .expect("No response");
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+}
+
+#[test]
+fn test_serve_if_modified_since() {
+    let toml = geojson_config() + "last_modified = \"Thu, 01 Jan 2015 00:00:00 GMT\"\n";
+    let (_server, addr) = start_server("modified", &toml);
+
+    let (head, body) = http_get(&addr, "/places/0/0/0.pbf").expect("No response");
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(head.contains("Last-Modified: Thu, 01 Jan 2015 00:00:00 GMT"), "{}", head);
+    assert!(!body.is_empty());
+
+    let (head, _) = http_request(&addr,
+                                 "/places/0/0/0.pbf",
+                                 "If-Modified-Since: Mon, 01 Jan 2018 00:00:00 GMT\r\n")
+        .expect("No response");
+    assert!(head.starts_with("HTTP/1.1 304"), "{}", head);
+
+    let (head, _) = http_request(&addr,
+                                 "/places/0/0/0.pbf",
+                                 "If-Modified-Since: Mon, 01 Jan 2001 00:00:00 GMT\r\n")
+        .expect("No response");
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+}
+
+#[test]
+fn test_serve_tile_download() {
+    let (_server, addr) = start_server("download", &geojson_config());
+
+    // Inline by default
+    let (head, body) = http_get(&addr, "/places/0/0/0.pbf").expect("No response");
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(!head.contains("Content-Disposition"), "{}", head);
+    assert!(!body.is_empty());
+
+    let (head, _) = http_get(&addr, "/places/0/0/0.pbf?download=1").expect("No response");
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(head.contains("Content-Disposition: attachment; filename=\"0_0_0.pbf\""),
+            "{}",
+            head);
+}